@@ -1,46 +1,281 @@
 //! Build-time helpers for configuring `prost_build`.
 //!
-//! These helpers attach `proto_name` and `json_name` attributes so the derive
-//! macros can serialize both forms correctly.
+//! These helpers attach the canonical derives to generated types, and
+//! `proto_name` and `json_name` attributes so the derive macros can serialize
+//! both forms correctly.
 //!
 //! # Example
 //! ```rust,ignore
 //! use std::path::PathBuf;
 //!
 //! let mut config = prost_build::Config::new();
-//! config.type_attribute(
-//!     "demo.Example",
-//!     "#[derive(prost_canonical_serde::CanonicalSerialize, prost_canonical_serde::CanonicalDeserialize)]",
-//! );
 //! let includes = [PathBuf::from("proto")];
 //! let fds = config.load_fds(&[PathBuf::from("proto/example.proto")], &includes)?;
+//! add_canonical_derives(&mut config, &fds);
 //! add_json_name_attributes(&mut config, &fds);
 //! config.compile_fds(fds)?;
 //! ```
-use prost_types::{DescriptorProto, FileDescriptorSet};
+use std::collections::BTreeSet;
+
+use prost_types::{DescriptorProto, EnumDescriptorProto, FileDescriptorSet};
+
+/// The derive attribute attached by [`add_canonical_derives`].
+const CANONICAL_DERIVES: &str = "#[derive(::prost_canonical_serde::CanonicalSerialize, ::prost_canonical_serde::CanonicalDeserialize)]";
+
+/// Derives `CanonicalSerialize` and `CanonicalDeserialize` for every message,
+/// oneof, and enum in `fds`.
+///
+/// Each type, nested ones included, is registered on its own by its
+/// fully-qualified name without the leading dot. prost matches such a path
+/// against the end of a type's name, so unlike a `"."` type attribute it does
+/// not spill onto the types nested under it. It does reach any type whose
+/// name ends with it, though, such as `other.demo.Status` for `demo.Status`,
+/// whether or not that type is in `fds`; such a type is registered only once.
+pub fn add_canonical_derives(config: &mut prost_build::Config, fds: &FileDescriptorSet) {
+    add_canonical_derives_except(config, fds, &[]);
+}
+
+/// Like [`add_canonical_derives`], but skips the types named in `excluded`,
+/// so they can be given hand-written impls instead.
+///
+/// Names are fully qualified, such as `"pkg.Outer.Inner"` for a nested
+/// message or `"pkg.Outer.choice"` for a oneof, with or without a leading
+/// dot. Excluding a message leaves the types nested in it derived.
+///
+/// A type whose name is a suffix of an excluded one, such as `demo.Status`
+/// when `other.demo.Status` is excluded, is registered by its dotted path
+/// instead, which prost matches exactly.
+///
+/// # Panics
+/// Panics if such a type has types nested in it, since its dotted path would
+/// reach those as well.
+pub fn add_canonical_derives_except(
+    config: &mut prost_build::Config,
+    fds: &FileDescriptorSet,
+    excluded: &[&str],
+) {
+    for path in canonical_derive_paths(fds, excluded) {
+        config.type_attribute(path, CANONICAL_DERIVES);
+    }
+}
+
+/// Collects the paths that register every generated type in `fds` that is
+/// not excluded, and nothing else, exactly once.
+fn canonical_derive_paths(fds: &FileDescriptorSet, excluded: &[&str]) -> Vec<String> {
+    let mut types = Vec::new();
+    for file in &fds.file {
+        let package = file.package.as_deref().unwrap_or("");
+        add_type_paths(&mut types, package, &file.message_type, &file.enum_type);
+    }
+    let excluded: BTreeSet<&str> = excluded
+        .iter()
+        .map(|name| name.strip_prefix('.').unwrap_or(name))
+        .collect();
+    let (excluded, derived): (Vec<&str>, Vec<&str>) = types
+        .iter()
+        .map(String::as_str)
+        .partition(|name| excluded.contains(name));
+
+    // Types registered by their dotted path, which matches only the type
+    // itself and the types nested in it.
+    let mut rooted = BTreeSet::new();
+    loop {
+        // A suffix path also matches every type whose name ends with it, so
+        // a type already covered by a shorter one must not be registered
+        // twice.
+        let paths: Vec<&str> = derived
+            .iter()
+            .copied()
+            .filter(|name| {
+                !derived.iter().any(|other| {
+                    other != name && !rooted.contains(other) && suffix_matches(other, name)
+                })
+            })
+            .collect();
+        let conflicts: Vec<&str> = paths
+            .iter()
+            .copied()
+            .filter(|path| {
+                !rooted.contains(path) && excluded.iter().any(|name| suffix_matches(path, name))
+            })
+            .collect();
+        if conflicts.is_empty() {
+            return paths
+                .into_iter()
+                .map(|path| {
+                    if rooted.contains(path) {
+                        format!(".{path}")
+                    } else {
+                        path.to_string()
+                    }
+                })
+                .collect();
+        }
+        for path in conflicts {
+            let nested = format!("{path}.");
+            assert!(
+                !types.iter().any(|name| name.starts_with(&nested)),
+                "`{path}` also matches an excluded type, and cannot be registered on its own \
+                 because types are nested in it; exclude it as well"
+            );
+            rooted.insert(path);
+        }
+    }
+}
+
+/// Whether prost applies the undotted `path` to the type `name`.
+fn suffix_matches(path: &str, name: &str) -> bool {
+    name.strip_suffix(path)
+        .is_some_and(|rest| rest.is_empty() || rest.ends_with('.'))
+}
+
+/// The full name of `name` declared in the package or message `scope`.
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{scope}.{name}")
+    }
+}
+
+/// Whether `message` is a synthetic map entry, which prost turns into a map
+/// rather than a struct.
+fn is_map_entry(message: &DescriptorProto) -> bool {
+    message
+        .options
+        .as_ref()
+        .is_some_and(prost_types::MessageOptions::map_entry)
+}
+
+fn add_type_paths(
+    paths: &mut Vec<String>,
+    scope: &str,
+    messages: &[DescriptorProto],
+    enums: &[EnumDescriptorProto],
+) {
+    for message in messages {
+        let Some(name) = message.name.as_deref() else {
+            continue;
+        };
+        if is_map_entry(message) {
+            continue;
+        }
+        let path = qualify(scope, name);
+        paths.push(path.clone());
+        // Synthetic oneofs of proto3 `optional` fields become plain `Option`s.
+        for (index, oneof) in message.oneof_decl.iter().enumerate() {
+            let Some(oneof_name) = oneof.name.as_deref() else {
+                continue;
+            };
+            let is_real = message.field.iter().any(|field| {
+                field.oneof_index.and_then(|i| usize::try_from(i).ok()) == Some(index)
+                    && !field.proto3_optional()
+            });
+            if is_real {
+                paths.push(format!("{path}.{oneof_name}"));
+            }
+        }
+        add_type_paths(paths, &path, &message.nested_type, &message.enum_type);
+    }
+    for enumeration in enums {
+        if let Some(name) = enumeration.name.as_deref() {
+            paths.push(qualify(scope, name));
+        }
+    }
+}
 
 /// Adds `prost_canonical_serde` field attributes with proto/json names.
+///
+/// Enums declared with `allow_alias` also get an `alias` attribute for each
+/// duplicate value name, since prost leaves those out of the generated enum.
 pub fn add_json_name_attributes(config: &mut prost_build::Config, fds: &FileDescriptorSet) {
+    for (path, attr) in json_name_attributes(fds) {
+        config.field_attribute(path, attr);
+    }
+    for (path, attr) in enum_alias_attributes(fds) {
+        config.type_attribute(path, attr);
+    }
+}
+
+/// Collects the `(field path, attribute)` pairs for every generated field.
+fn json_name_attributes(fds: &FileDescriptorSet) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
     for file in &fds.file {
         let package = file.package.as_deref().unwrap_or("");
         for message in &file.message_type {
             if let Some(name) = message.name.as_deref() {
-                let fq_name = if package.is_empty() {
-                    name.to_string()
-                } else {
-                    format!("{package}.{name}")
-                };
-                add_message_field_attributes(config, &fq_name, message);
+                add_message_field_attributes(&mut attributes, &qualify(package, name), message);
+            }
+        }
+    }
+    attributes
+}
+
+/// Collects the `(enum path, attribute)` pairs for every aliased enum value.
+fn enum_alias_attributes(fds: &FileDescriptorSet) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    for file in &fds.file {
+        let package = file.package.as_deref().unwrap_or("");
+        add_enum_alias_attributes(
+            &mut attributes,
+            package,
+            &file.enum_type,
+            &file.message_type,
+        );
+    }
+    attributes
+}
+
+fn add_enum_alias_attributes(
+    attributes: &mut Vec<(String, String)>,
+    scope: &str,
+    enums: &[EnumDescriptorProto],
+    messages: &[DescriptorProto],
+) {
+    for enumeration in enums {
+        let Some(name) = enumeration.name.as_deref() else {
+            continue;
+        };
+        // Like prost, treat the first value with a given number as primary.
+        let mut numbers = BTreeSet::new();
+        for value in &enumeration.value {
+            let Some(value_name) = value.name.as_deref() else {
+                continue;
+            };
+            if numbers.insert(value.number()) {
+                continue;
             }
+            let number = value.number();
+            attributes.push((
+                qualify(scope, name),
+                format!(
+                    "#[prost_canonical_serde(alias(name = {value_name:?}, number = {number}))]"
+                ),
+            ));
+        }
+    }
+
+    for message in messages {
+        if let Some(name) = message.name.as_deref() {
+            add_enum_alias_attributes(
+                attributes,
+                &qualify(scope, name),
+                &message.enum_type,
+                &message.nested_type,
+            );
         }
     }
 }
 
 fn add_message_field_attributes(
-    config: &mut prost_build::Config,
+    attributes: &mut Vec<(String, String)>,
     fq_message_name: &str,
     message: &DescriptorProto,
 ) {
+    if is_map_entry(message) {
+        return;
+    }
+
     for field in &message.field {
         let Some(proto_name) = field.name.as_deref() else {
             continue;
@@ -51,7 +286,7 @@ fn add_message_field_attributes(
         let attr =
             format!("#[prost_canonical_serde(proto_name = {proto_lit}, json_name = {json_lit})]");
         let field_path = format!("{fq_message_name}.{proto_name}");
-        config.field_attribute(field_path, attr.clone());
+        attributes.push((field_path, attr.clone()));
 
         if let Some(oneof_index) = field.oneof_index {
             let Ok(oneof_index) = usize::try_from(oneof_index) else {
@@ -65,14 +300,231 @@ fn add_message_field_attributes(
             };
             let oneof_fq = format!("{fq_message_name}.{oneof_name}");
             let oneof_field_path = format!("{oneof_fq}.{proto_name}");
-            config.field_attribute(oneof_field_path, attr);
+            attributes.push((oneof_field_path, attr));
         }
     }
 
     for nested in &message.nested_type {
         if let Some(name) = nested.name.as_deref() {
-            let nested_fq = format!("{fq_message_name}.{name}");
-            add_message_field_attributes(config, &nested_fq, nested);
+            add_message_field_attributes(attributes, &qualify(fq_message_name, name), nested);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_derive_paths, enum_alias_attributes, json_name_attributes};
+    use prost_types::field_descriptor_proto::{Label, Type};
+    use prost_types::{
+        DescriptorProto, EnumDescriptorProto, EnumOptions, EnumValueDescriptorProto,
+        FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet, MessageOptions,
+        OneofDescriptorProto,
+    };
+
+    fn field(name: &str, json_name: &str, number: i32, ty: Type) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            json_name: Some(json_name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(ty as i32),
+            ..FieldDescriptorProto::default()
         }
     }
+
+    #[test]
+    fn map_entry_types_get_no_field_attributes() {
+        let mut labels = field("labels", "labels", 1, Type::Message);
+        labels.label = Some(Label::Repeated as i32);
+        labels.type_name = Some(".demo.Foo.LabelsEntry".to_string());
+        let entry = DescriptorProto {
+            name: Some("LabelsEntry".to_string()),
+            field: vec![
+                field("key", "key", 1, Type::String),
+                field("value", "value", 2, Type::String),
+            ],
+            options: Some(MessageOptions {
+                map_entry: Some(true),
+                ..MessageOptions::default()
+            }),
+            ..DescriptorProto::default()
+        };
+        let fds = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("demo.proto".to_string()),
+                package: Some("demo".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Foo".to_string()),
+                    field: vec![
+                        labels,
+                        field("display_name", "displayName", 2, Type::String),
+                    ],
+                    nested_type: vec![entry],
+                    ..DescriptorProto::default()
+                }],
+                ..FileDescriptorProto::default()
+            }],
+        };
+
+        let paths: Vec<_> = json_name_attributes(&fds)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, ["demo.Foo.labels", "demo.Foo.display_name"]);
+    }
+
+    #[test]
+    fn every_type_is_registered_for_the_derives() {
+        let enumeration = |name: &str| EnumDescriptorProto {
+            name: Some(name.to_string()),
+            ..EnumDescriptorProto::default()
+        };
+        let oneof = |name: &str| OneofDescriptorProto {
+            name: Some(name.to_string()),
+            ..OneofDescriptorProto::default()
+        };
+        let mut name = field("name", "name", 1, Type::String);
+        name.oneof_index = Some(0);
+        let mut count = field("count", "count", 2, Type::Int32);
+        count.oneof_index = Some(1);
+        count.proto3_optional = Some(true);
+        let fds = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("demo.proto".to_string()),
+                package: Some("demo".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Outer".to_string()),
+                    field: vec![name, count],
+                    oneof_decl: vec![oneof("choice"), oneof("_count")],
+                    nested_type: vec![
+                        DescriptorProto {
+                            name: Some("Inner".to_string()),
+                            ..DescriptorProto::default()
+                        },
+                        DescriptorProto {
+                            name: Some("LabelsEntry".to_string()),
+                            options: Some(MessageOptions {
+                                map_entry: Some(true),
+                                ..MessageOptions::default()
+                            }),
+                            ..DescriptorProto::default()
+                        },
+                    ],
+                    enum_type: vec![enumeration("Kind")],
+                    ..DescriptorProto::default()
+                }],
+                enum_type: vec![enumeration("Status")],
+                ..FileDescriptorProto::default()
+            }],
+        };
+
+        assert_eq!(
+            canonical_derive_paths(&fds, &[]),
+            [
+                "demo.Outer",
+                "demo.Outer.choice",
+                "demo.Outer.Inner",
+                "demo.Outer.Kind",
+                "demo.Status",
+            ]
+        );
+        assert_eq!(
+            canonical_derive_paths(&fds, &[".demo.Outer.Inner", "demo.Outer.choice"]),
+            ["demo.Outer", "demo.Outer.Kind", "demo.Status"]
+        );
+
+        // `demo.Status` already matches `.other.demo.Status` by suffix.
+        let file = |package: &str| FileDescriptorProto {
+            package: Some(package.to_string()),
+            enum_type: vec![enumeration("Status")],
+            ..FileDescriptorProto::default()
+        };
+        let fds = FileDescriptorSet {
+            file: vec![file("demo"), file("other.demo"), file("third.demo")],
+        };
+        assert_eq!(canonical_derive_paths(&fds, &[]), ["demo.Status"]);
+        // Excluding the shorter name leaves the others to their own paths.
+        assert_eq!(
+            canonical_derive_paths(&fds, &["demo.Status"]),
+            ["other.demo.Status", "third.demo.Status"]
+        );
+        // Excluding a longer one registers the shorter name exactly, so it
+        // no longer reaches the excluded type.
+        assert_eq!(
+            canonical_derive_paths(&fds, &["other.demo.Status"]),
+            [".demo.Status", "third.demo.Status"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "`demo.Outer` also matches an excluded type")]
+    fn excluding_a_type_matched_by_a_message_with_nested_types_panics() {
+        let file = |package: &str| FileDescriptorProto {
+            package: Some(package.to_string()),
+            message_type: vec![DescriptorProto {
+                name: Some("Outer".to_string()),
+                nested_type: vec![DescriptorProto {
+                    name: Some("Inner".to_string()),
+                    ..DescriptorProto::default()
+                }],
+                ..DescriptorProto::default()
+            }],
+            ..FileDescriptorProto::default()
+        };
+        let fds = FileDescriptorSet {
+            file: vec![file("demo"), file("other.demo")],
+        };
+        canonical_derive_paths(&fds, &["other.demo.Outer"]);
+    }
+
+    #[test]
+    fn aliased_enum_values_get_alias_attributes() {
+        let value = |name: &str, number| EnumValueDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            ..EnumValueDescriptorProto::default()
+        };
+        let fds = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("demo.proto".to_string()),
+                package: Some("demo".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Job".to_string()),
+                    enum_type: vec![EnumDescriptorProto {
+                        name: Some("State".to_string()),
+                        value: vec![
+                            value("STATE_UNSPECIFIED", 0),
+                            value("STATE_RUNNING", 1),
+                            value("STATE_STARTED", 1),
+                            value("STATE_FAILED", -1),
+                            value("STATE_ERROR", -1),
+                        ],
+                        options: Some(EnumOptions {
+                            allow_alias: Some(true),
+                            ..EnumOptions::default()
+                        }),
+                        ..EnumDescriptorProto::default()
+                    }],
+                    ..DescriptorProto::default()
+                }],
+                ..FileDescriptorProto::default()
+            }],
+        };
+
+        assert_eq!(
+            enum_alias_attributes(&fds),
+            [
+                (
+                    "demo.Job.State".to_string(),
+                    r#"#[prost_canonical_serde(alias(name = "STATE_STARTED", number = 1))]"#
+                        .to_string(),
+                ),
+                (
+                    "demo.Job.State".to_string(),
+                    r#"#[prost_canonical_serde(alias(name = "STATE_ERROR", number = -1))]"#
+                        .to_string(),
+                ),
+            ]
+        );
+    }
 }
@@ -1,3 +1,4 @@
+use std::env;
 use std::path::PathBuf;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -25,6 +26,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         "#[derive(::prost_canonical_serde::CanonicalSerialize, ::prost_canonical_serde::CanonicalDeserialize)]",
     );
     config.btree_map(["."]);
+    // The descriptor set drives text format support in the harness.
+    config.file_descriptor_set_path(PathBuf::from(env::var("OUT_DIR")?).join("test_messages.bin"));
 
     let fds = config.load_fds(&[proto2, proto3], &[proto_root])?;
     prost_canonical_serde_build::add_json_name_attributes(&mut config, &fds);
@@ -4,11 +4,17 @@
 //! JSON and binary conformance cases against this crate's implementation.
 extern crate alloc;
 
+use std::env;
+use std::fs;
 use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::LazyLock;
 
 use prost::Message;
 use prost_canonical_serde::{Canonical, CanonicalDeserialize, CanonicalSerialize, CanonicalValue};
 
+mod text_format;
+
 pub mod conformance {
     #![expect(
         clippy::doc_markdown,
@@ -41,9 +47,56 @@ pub mod conformance_proto3 {
 
 use conformance::conformance_request::Payload;
 use conformance::conformance_response::Result as ResponseResult;
-use conformance::{ConformanceRequest, ConformanceResponse, FailureSet, TestCategory, WireFormat};
+use conformance::{
+    ConformanceRequest, ConformanceResponse, FailureSet, TestCategory, TestStatus, WireFormat,
+};
 use conformance_proto2::TestAllTypesProto2;
 use conformance_proto3::TestAllTypesProto3;
+use text_format::Descriptors;
+
+static DESCRIPTORS: LazyLock<Descriptors> = LazyLock::new(|| {
+    Descriptors::decode(include_bytes!(concat!(
+        env!("OUT_DIR"),
+        "/test_messages.bin"
+    )))
+    .expect("decode test message descriptors")
+});
+
+/// Environment variable that overrides the expected-failure list path.
+const FAILURE_LIST_ENV: &str = "PROST_CANONICAL_SERDE_FAILURE_LIST";
+
+const DEFAULT_FAILURE_LIST: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/conformance/failure_list_rust_cc.txt"
+);
+
+/// Loads the expected failures reported to the runner.
+///
+/// Each non-empty line names a test, optionally followed by `#` and the
+/// expected failure message.
+fn load_failure_set() -> io::Result<FailureSet> {
+    let path =
+        env::var_os(FAILURE_LIST_ENV).map_or_else(|| DEFAULT_FAILURE_LIST.into(), PathBuf::from);
+    let contents = fs::read_to_string(&path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("failed to read failure list {}: {err}", path.display()),
+        )
+    })?;
+    let test = contents
+        .lines()
+        .filter_map(|line| {
+            let (name, message) = line.split_once('#').unwrap_or((line, ""));
+            let name = name.trim();
+            (!name.is_empty()).then(|| TestStatus {
+                name: name.to_string(),
+                failure_message: message.trim().to_string(),
+                ..TestStatus::default()
+            })
+        })
+        .collect();
+    Ok(FailureSet { test })
+}
 
 fn read_frame() -> io::Result<Option<Vec<u8>>> {
     let mut len_bytes = [0u8; 4];
@@ -117,13 +170,18 @@ fn json_response(payload: String) -> ConformanceResponse {
     }
 }
 
-fn failure_list_response() -> ConformanceResponse {
-    let failures = FailureSet::default();
-    let payload = failures.encode_to_vec();
-    protobuf_response(payload)
+fn text_response(payload: String) -> ConformanceResponse {
+    ConformanceResponse {
+        result: Some(ResponseResult::TextPayload(payload)),
+    }
+}
+
+fn failure_list_response(failures: &FailureSet) -> ConformanceResponse {
+    protobuf_response(failures.encode_to_vec())
 }
 
 fn handle_message<T>(
+    message_type: &str,
     input_payload: &Payload,
     output_format: WireFormat,
     _test_category: TestCategory,
@@ -140,8 +198,14 @@ where
             Ok(value) => value,
             Err(err) => return parse_error(&err),
         },
-        Payload::TextPayload(_) => {
-            return skipped("text format input not supported");
+        Payload::TextPayload(text) => {
+            match DESCRIPTORS
+                .parse(message_type, text)
+                .and_then(|bytes| decode_proto::<T>(&bytes))
+            {
+                Ok(value) => value,
+                Err(err) => return parse_error(&err),
+            }
         }
         Payload::JspbPayload(_) => {
             return skipped("jspb input not supported");
@@ -154,15 +218,18 @@ where
             Ok(json) => json_response(json),
             Err(err) => serialize_error(&err),
         },
-        WireFormat::TextFormat => skipped("text format output not supported"),
+        WireFormat::TextFormat => match DESCRIPTORS.print(message_type, &encode_proto(&message)) {
+            Ok(text) => text_response(text),
+            Err(err) => serialize_error(&err),
+        },
         WireFormat::Jspb => skipped("jspb output not supported"),
         WireFormat::Unspecified => skipped("unspecified output format"),
     }
 }
 
-fn handle_request(request: ConformanceRequest) -> ConformanceResponse {
+fn handle_request(request: ConformanceRequest, failures: &FailureSet) -> ConformanceResponse {
     if request.message_type == "conformance.FailureSet" {
-        return failure_list_response();
+        return failure_list_response(failures);
     }
 
     let output_format =
@@ -174,18 +241,26 @@ fn handle_request(request: ConformanceRequest) -> ConformanceResponse {
         return skipped("no payload provided");
     };
 
-    match request.message_type.as_str() {
-        "protobuf_test_messages.proto2.TestAllTypesProto2" => {
-            handle_message::<TestAllTypesProto2>(&payload, output_format, test_category)
-        }
-        "protobuf_test_messages.proto3.TestAllTypesProto3" => {
-            handle_message::<TestAllTypesProto3>(&payload, output_format, test_category)
-        }
+    let message_type = request.message_type.as_str();
+    match message_type {
+        "protobuf_test_messages.proto2.TestAllTypesProto2" => handle_message::<TestAllTypesProto2>(
+            message_type,
+            &payload,
+            output_format,
+            test_category,
+        ),
+        "protobuf_test_messages.proto3.TestAllTypesProto3" => handle_message::<TestAllTypesProto3>(
+            message_type,
+            &payload,
+            output_format,
+            test_category,
+        ),
         _ => skipped("unsupported message type"),
     }
 }
 
 fn main() -> io::Result<()> {
+    let failures = load_failure_set()?;
     while let Some(frame) = read_frame()? {
         let request = match ConformanceRequest::decode(&frame[..]) {
             Ok(request) => request,
@@ -195,7 +270,7 @@ fn main() -> io::Result<()> {
                 continue;
             }
         };
-        let response = handle_request(request);
+        let response = handle_request(request, &failures);
         write_frame(&response)?;
     }
     Ok(())
@@ -0,0 +1,948 @@
+//! Protobuf text format for the conformance harness.
+//!
+//! prost has no reflection, so messages are printed by walking their binary
+//! encoding alongside the descriptor set captured at build time, and text is
+//! parsed by encoding it straight back to the binary form. Extensions, `Any`
+//! expansion, and unknown fields are not supported.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use prost::Message;
+use prost_types::field_descriptor_proto::Type;
+use prost_types::{
+    DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto,
+    FileDescriptorSet,
+};
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LEN: u64 = 2;
+const WIRE_START_GROUP: u64 = 3;
+const WIRE_END_GROUP: u64 = 4;
+const WIRE_FIXED32: u64 = 5;
+
+/// Message and enum descriptors indexed by fully-qualified name.
+pub struct Descriptors {
+    messages: HashMap<String, DescriptorProto>,
+    enums: HashMap<String, EnumDescriptorProto>,
+}
+
+impl Descriptors {
+    /// Decodes an encoded `FileDescriptorSet`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let set = FileDescriptorSet::decode(bytes).map_err(|err| err.to_string())?;
+        let mut descriptors = Self {
+            messages: HashMap::new(),
+            enums: HashMap::new(),
+        };
+        for file in set.file {
+            let prefix = match file.package() {
+                "" => String::new(),
+                package => format!(".{package}"),
+            };
+            for message in file.message_type {
+                descriptors.add_message(&prefix, message);
+            }
+            for enum_type in file.enum_type {
+                descriptors.add_enum(&prefix, enum_type);
+            }
+        }
+        Ok(descriptors)
+    }
+
+    fn add_message(&mut self, prefix: &str, mut message: DescriptorProto) {
+        let name = format!("{prefix}.{}", message.name());
+        for nested in core::mem::take(&mut message.nested_type) {
+            self.add_message(&name, nested);
+        }
+        for enum_type in core::mem::take(&mut message.enum_type) {
+            self.add_enum(&name, enum_type);
+        }
+        self.messages.insert(name, message);
+    }
+
+    fn add_enum(&mut self, prefix: &str, enum_type: EnumDescriptorProto) {
+        self.enums
+            .insert(format!("{prefix}.{}", enum_type.name()), enum_type);
+    }
+
+    fn message(&self, name: &str) -> Result<&DescriptorProto, String> {
+        self.messages
+            .get(name)
+            .ok_or_else(|| format!("unknown message type {name}"))
+    }
+
+    fn enum_type(&self, name: &str) -> Result<&EnumDescriptorProto, String> {
+        self.enums
+            .get(name)
+            .ok_or_else(|| format!("unknown enum type {name}"))
+    }
+
+    /// Prints the binary encoding of `message_type` as text format.
+    pub fn print(&self, message_type: &str, bytes: &[u8]) -> Result<String, String> {
+        let descriptor = self.message(&format!(".{message_type}"))?;
+        let mut out = String::new();
+        let mut input = bytes;
+        self.print_fields(descriptor, &mut input, None, &mut out)?;
+        Ok(out)
+    }
+
+    /// Parses text format for `message_type` into its binary encoding.
+    pub fn parse(&self, message_type: &str, text: &str) -> Result<Vec<u8>, String> {
+        let descriptor = self.message(&format!(".{message_type}"))?;
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let mut out = Vec::new();
+        self.parse_fields(descriptor, &mut parser, None, &mut out)?;
+        Ok(out)
+    }
+
+    fn print_fields(
+        &self,
+        descriptor: &DescriptorProto,
+        input: &mut &[u8],
+        group: Option<u64>,
+        out: &mut String,
+    ) -> Result<(), String> {
+        while !input.is_empty() {
+            let key = read_varint(input)?;
+            let (number, wire_type) = (key >> 3, key & 7);
+            if wire_type == WIRE_END_GROUP {
+                return if group == Some(number) {
+                    Ok(())
+                } else {
+                    Err("unexpected end group".to_string())
+                };
+            }
+            let field = descriptor
+                .field
+                .iter()
+                .find(|field| u64::try_from(field.number()).ok() == Some(number));
+            let Some(field) = field else {
+                skip_value(input, number, wire_type)?;
+                continue;
+            };
+            self.print_field(field, wire_type, input, out)?;
+        }
+        match group {
+            Some(_) => Err("unterminated group".to_string()),
+            None => Ok(()),
+        }
+    }
+
+    fn print_field(
+        &self,
+        field: &FieldDescriptorProto,
+        wire_type: u64,
+        input: &mut &[u8],
+        out: &mut String,
+    ) -> Result<(), String> {
+        match (field.r#type(), wire_type) {
+            (Type::Group, WIRE_START_GROUP) => {
+                let nested = self.message(field.type_name())?;
+                out.push_str(nested.name());
+                out.push_str(" { ");
+                let number = u64::try_from(field.number()).map_err(|err| err.to_string())?;
+                self.print_fields(nested, input, Some(number), out)?;
+                out.push_str("} ");
+            }
+            (Type::Message, WIRE_LEN) => {
+                let nested = self.message(field.type_name())?;
+                let mut bytes = read_len(input)?;
+                out.push_str(field.name());
+                out.push_str(" { ");
+                self.print_fields(nested, &mut bytes, None, out)?;
+                out.push_str("} ");
+            }
+            (Type::String | Type::Bytes, WIRE_LEN) => {
+                let bytes = read_len(input)?;
+                out.push_str(field.name());
+                out.push_str(": ");
+                escape_bytes(bytes, out);
+                out.push(' ');
+            }
+            (_, WIRE_LEN) => {
+                // Packed repeated scalars.
+                let mut bytes = read_len(input)?;
+                let element_wire_type = scalar_wire_type(field.r#type())?;
+                while !bytes.is_empty() {
+                    self.print_scalar(field, element_wire_type, &mut bytes, out)?;
+                }
+            }
+            _ => self.print_scalar(field, wire_type, input, out)?,
+        }
+        Ok(())
+    }
+
+    #[expect(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        reason = "Wire values are reinterpreted exactly as protobuf decoders do."
+    )]
+    fn print_scalar(
+        &self,
+        field: &FieldDescriptorProto,
+        wire_type: u64,
+        input: &mut &[u8],
+        out: &mut String,
+    ) -> Result<(), String> {
+        if wire_type != scalar_wire_type(field.r#type())? {
+            return Err(format!("wire type mismatch for field {}", field.name()));
+        }
+        let value = match field.r#type() {
+            Type::Int32 => (read_varint(input)? as i32).to_string(),
+            Type::Int64 => (read_varint(input)? as i64).to_string(),
+            Type::Uint32 => (read_varint(input)? as u32).to_string(),
+            Type::Uint64 => read_varint(input)?.to_string(),
+            Type::Sint32 => zigzag_decode(u64::from(read_varint(input)? as u32)).to_string(),
+            Type::Sint64 => zigzag_decode(read_varint(input)?).to_string(),
+            Type::Bool => (read_varint(input)? != 0).to_string(),
+            Type::Enum => {
+                let number = read_varint(input)? as i32;
+                self.enum_type(field.type_name())?
+                    .value
+                    .iter()
+                    .find(|value| value.number() == number)
+                    .map_or_else(|| number.to_string(), |value| value.name().to_string())
+            }
+            Type::Fixed32 => read_fixed32(input)?.to_string(),
+            Type::Sfixed32 => (read_fixed32(input)? as i32).to_string(),
+            Type::Float => format_float(f64::from(f32::from_bits(read_fixed32(input)?))),
+            Type::Fixed64 => read_fixed64(input)?.to_string(),
+            Type::Sfixed64 => (read_fixed64(input)? as i64).to_string(),
+            Type::Double => format_float(f64::from_bits(read_fixed64(input)?)),
+            Type::String | Type::Bytes | Type::Message | Type::Group => {
+                return Err(format!("field {} is not a scalar", field.name()));
+            }
+        };
+        out.push_str(field.name());
+        out.push_str(": ");
+        out.push_str(&value);
+        out.push(' ');
+        Ok(())
+    }
+
+    fn parse_fields(
+        &self,
+        descriptor: &DescriptorProto,
+        parser: &mut Parser,
+        end: Option<char>,
+        out: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        loop {
+            match (parser.peek(), end) {
+                (None, None) => return Ok(()),
+                (None, Some(end)) => return Err(format!("expected `{end}`")),
+                (Some(Token::Punct(punct)), Some(end)) if *punct == end => {
+                    parser.position += 1;
+                    return Ok(());
+                }
+                _ => {}
+            }
+
+            let name = match parser.next() {
+                Some(Token::Ident(name)) => name,
+                Some(Token::Punct('[')) => {
+                    return Err("extensions and Any expansion are not supported".to_string());
+                }
+                _ => return Err("expected field name".to_string()),
+            };
+            let field = descriptor
+                .field
+                .iter()
+                .find(|field| {
+                    field.name() == name
+                        || (field.r#type() == Type::Group
+                            && field.type_name().rsplit('.').next() == Some(name.as_str()))
+                })
+                .ok_or_else(|| format!("unknown field {name}"))?;
+
+            if matches!(field.r#type(), Type::Message | Type::Group) {
+                parser.eat(':');
+                if parser.eat('[') {
+                    if !parser.eat(']') {
+                        loop {
+                            self.parse_nested(field, parser, out)?;
+                            if parser.eat(']') {
+                                break;
+                            }
+                            parser.expect(',')?;
+                        }
+                    }
+                } else {
+                    self.parse_nested(field, parser, out)?;
+                }
+            } else {
+                parser.expect(':')?;
+                if parser.eat('[') {
+                    if !parser.eat(']') {
+                        loop {
+                            self.parse_scalar(field, parser, out)?;
+                            if parser.eat(']') {
+                                break;
+                            }
+                            parser.expect(',')?;
+                        }
+                    }
+                } else {
+                    self.parse_scalar(field, parser, out)?;
+                }
+            }
+
+            if !parser.eat(',') {
+                parser.eat(';');
+            }
+        }
+    }
+
+    fn parse_nested(
+        &self,
+        field: &FieldDescriptorProto,
+        parser: &mut Parser,
+        out: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        let nested = self.message(field.type_name())?;
+        let end = if parser.eat('{') {
+            '}'
+        } else if parser.eat('<') {
+            '>'
+        } else {
+            return Err(format!("expected message value for field {}", field.name()));
+        };
+        let number = u64::try_from(field.number()).map_err(|err| err.to_string())?;
+        if field.r#type() == Type::Group {
+            write_varint(number << 3 | WIRE_START_GROUP, out);
+            self.parse_fields(nested, parser, Some(end), out)?;
+            write_varint(number << 3 | WIRE_END_GROUP, out);
+        } else {
+            let mut bytes = Vec::new();
+            self.parse_fields(nested, parser, Some(end), &mut bytes)?;
+            write_varint(number << 3 | WIRE_LEN, out);
+            write_len(&bytes, out);
+        }
+        Ok(())
+    }
+
+    #[expect(
+        clippy::cast_sign_loss,
+        clippy::cast_possible_truncation,
+        reason = "Values are range-checked before being written in their wire form."
+    )]
+    fn parse_scalar(
+        &self,
+        field: &FieldDescriptorProto,
+        parser: &mut Parser,
+        out: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        let number = u64::try_from(field.number()).map_err(|err| err.to_string())?;
+        let field_type = field.r#type();
+        let wire_type = if matches!(field_type, Type::String | Type::Bytes) {
+            WIRE_LEN
+        } else {
+            scalar_wire_type(field_type)?
+        };
+        write_varint(number << 3 | wire_type, out);
+        match field_type {
+            Type::Int32 => write_varint(i64::from(parser.signed::<i32>()?) as u64, out),
+            Type::Int64 => write_varint(parser.signed::<i64>()? as u64, out),
+            Type::Uint32 => write_varint(u64::from(parser.unsigned::<u32>()?), out),
+            Type::Uint64 => write_varint(parser.unsigned::<u64>()?, out),
+            Type::Sint32 => write_varint(zigzag_encode(i64::from(parser.signed::<i32>()?)), out),
+            Type::Sint64 => write_varint(zigzag_encode(parser.signed::<i64>()?), out),
+            Type::Bool => write_varint(u64::from(parser.bool()?), out),
+            Type::Enum => {
+                let value = if let Some(Token::Ident(name)) = parser.peek() {
+                    let name = name.clone();
+                    parser.position += 1;
+                    self.enum_type(field.type_name())?
+                        .value
+                        .iter()
+                        .find(|value| value.name() == name)
+                        .map(EnumValueDescriptorProto::number)
+                        .ok_or_else(|| format!("unknown enum value {name}"))?
+                } else {
+                    parser.signed::<i32>()?
+                };
+                write_varint(i64::from(value) as u64, out);
+            }
+            Type::Fixed32 => out.extend_from_slice(&parser.unsigned::<u32>()?.to_le_bytes()),
+            Type::Sfixed32 => out.extend_from_slice(&parser.signed::<i32>()?.to_le_bytes()),
+            Type::Float => out.extend_from_slice(&(parser.float()? as f32).to_le_bytes()),
+            Type::Fixed64 => out.extend_from_slice(&parser.unsigned::<u64>()?.to_le_bytes()),
+            Type::Sfixed64 => out.extend_from_slice(&parser.signed::<i64>()?.to_le_bytes()),
+            Type::Double => out.extend_from_slice(&parser.float()?.to_le_bytes()),
+            Type::String => {
+                let bytes = parser.string()?;
+                if core::str::from_utf8(&bytes).is_err() {
+                    return Err(format!("invalid UTF-8 in field {}", field.name()));
+                }
+                write_len(&bytes, out);
+            }
+            Type::Bytes => write_len(&parser.string()?, out),
+            Type::Message | Type::Group => unreachable!("handled by parse_nested"),
+        }
+        Ok(())
+    }
+}
+
+fn scalar_wire_type(field_type: Type) -> Result<u64, String> {
+    match field_type {
+        Type::Int32
+        | Type::Int64
+        | Type::Uint32
+        | Type::Uint64
+        | Type::Sint32
+        | Type::Sint64
+        | Type::Bool
+        | Type::Enum => Ok(WIRE_VARINT),
+        Type::Fixed32 | Type::Sfixed32 | Type::Float => Ok(WIRE_FIXED32),
+        Type::Fixed64 | Type::Sfixed64 | Type::Double => Ok(WIRE_FIXED64),
+        Type::String | Type::Bytes | Type::Message | Type::Group => {
+            Err("not a packable scalar type".to_string())
+        }
+    }
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0_u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input
+            .split_first()
+            .ok_or_else(|| "truncated varint".to_string())?;
+        *input = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("varint too long".to_string())
+}
+
+fn read_bytes<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+    if input.len() < len {
+        return Err("truncated field".to_string());
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+fn read_len<'a>(input: &mut &'a [u8]) -> Result<&'a [u8], String> {
+    let len = usize::try_from(read_varint(input)?).map_err(|err| err.to_string())?;
+    read_bytes(input, len)
+}
+
+fn read_fixed32(input: &mut &[u8]) -> Result<u32, String> {
+    let bytes = read_bytes(input, 4)?;
+    Ok(u32::from_le_bytes(bytes.try_into().expect("four bytes")))
+}
+
+fn read_fixed64(input: &mut &[u8]) -> Result<u64, String> {
+    let bytes = read_bytes(input, 8)?;
+    Ok(u64::from_le_bytes(bytes.try_into().expect("eight bytes")))
+}
+
+fn skip_value(input: &mut &[u8], number: u64, wire_type: u64) -> Result<(), String> {
+    match wire_type {
+        WIRE_VARINT => read_varint(input).map(drop),
+        WIRE_FIXED64 => read_bytes(input, 8).map(drop),
+        WIRE_LEN => read_len(input).map(drop),
+        WIRE_FIXED32 => read_bytes(input, 4).map(drop),
+        WIRE_START_GROUP => loop {
+            let key = read_varint(input)?;
+            if key & 7 == WIRE_END_GROUP {
+                if key >> 3 == number {
+                    return Ok(());
+                }
+                return Err("unexpected end group".to_string());
+            }
+            skip_value(input, key >> 3, key & 7)?;
+        },
+        _ => Err(format!("invalid wire type {wire_type}")),
+    }
+}
+
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(u8::try_from(value & 0x7f).expect("seven bits") | 0x80);
+        value >>= 7;
+    }
+    out.push(u8::try_from(value).expect("seven bits"));
+}
+
+fn write_len(bytes: &[u8], out: &mut Vec<u8>) {
+    write_varint(bytes.len() as u64, out);
+    out.extend_from_slice(bytes);
+}
+
+#[expect(
+    clippy::cast_possible_wrap,
+    reason = "Zigzag decoding reinterprets the shifted bits."
+)]
+fn zigzag_decode(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+#[expect(
+    clippy::cast_sign_loss,
+    reason = "Zigzag encoding reinterprets the shifted bits."
+)]
+fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        format!("{value:?}")
+    }
+}
+
+fn escape_bytes(bytes: &[u8], out: &mut String) {
+    out.push('"');
+    for &byte in bytes {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\'' => out.push_str("\\'"),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x20..=0x7e => out.push(char::from(byte)),
+            _ => {
+                let _ = write!(out, "\\{byte:03o}");
+            }
+        }
+    }
+    out.push('"');
+}
+
+enum Token {
+    Ident(String),
+    Number(String),
+    String(Vec<u8>),
+    Punct(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some((index, c)) =
+                chars.next_if(|&(_, c)| c.is_ascii_alphanumeric() || c == '_')
+            {
+                end = index + c.len_utf8();
+            }
+            tokens.push(Token::Ident(text[start..end].to_string()));
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            let mut previous = c;
+            while let Some((index, c)) = chars.next_if(|&(_, c)| {
+                c.is_ascii_alphanumeric()
+                    || c == '.'
+                    || (matches!(c, '+' | '-') && matches!(previous, 'e' | 'E'))
+            }) {
+                end = index + c.len_utf8();
+                previous = c;
+            }
+            tokens.push(Token::Number(text[start..end].to_string()));
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            tokens.push(Token::String(unescape(&mut chars, c)?));
+        } else {
+            chars.next();
+            tokens.push(Token::Punct(c));
+        }
+    }
+    Ok(tokens)
+}
+
+fn unescape(
+    chars: &mut core::iter::Peekable<std::str::CharIndices<'_>>,
+    quote: char,
+) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    loop {
+        let (_, c) = chars
+            .next()
+            .ok_or_else(|| "unterminated string".to_string())?;
+        if c == quote {
+            return Ok(out);
+        }
+        if c == '\n' {
+            return Err("newline in string".to_string());
+        }
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let (_, escape) = chars
+            .next()
+            .ok_or_else(|| "unterminated string".to_string())?;
+        match escape {
+            'n' => out.push(b'\n'),
+            'r' => out.push(b'\r'),
+            't' => out.push(b'\t'),
+            'a' => out.push(0x07),
+            'b' => out.push(0x08),
+            'f' => out.push(0x0c),
+            'v' => out.push(0x0b),
+            '\\' | '\'' | '"' | '?' => out.push(u8::try_from(escape).expect("ASCII escape")),
+            '0'..='7' => {
+                let mut value = escape.to_digit(8).expect("octal digit");
+                for _ in 0..2 {
+                    match chars.next_if(|&(_, c)| c.is_digit(8)) {
+                        Some((_, c)) => value = value * 8 + c.to_digit(8).expect("octal digit"),
+                        None => break,
+                    }
+                }
+                out.push(u8::try_from(value).map_err(|_| "octal escape out of range".to_string())?);
+            }
+            'x' => {
+                let mut value = 0;
+                let mut digits = 0;
+                while digits < 2 {
+                    match chars.next_if(|&(_, c)| c.is_ascii_hexdigit()) {
+                        Some((_, c)) => value = value * 16 + c.to_digit(16).expect("hex digit"),
+                        None => break,
+                    }
+                    digits += 1;
+                }
+                if digits == 0 {
+                    return Err("invalid hex escape".to_string());
+                }
+                out.push(u8::try_from(value).expect("two hex digits"));
+            }
+            'u' | 'U' => {
+                let len = if escape == 'u' { 4 } else { 8 };
+                let mut value = 0;
+                for _ in 0..len {
+                    let (_, c) = chars
+                        .next_if(|&(_, c)| c.is_ascii_hexdigit())
+                        .ok_or_else(|| "invalid unicode escape".to_string())?;
+                    value = value * 16 + c.to_digit(16).expect("hex digit");
+                }
+                let c =
+                    char::from_u32(value).ok_or_else(|| "invalid unicode escape".to_string())?;
+                let mut buf = [0; 4];
+                out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            _ => return Err(format!("invalid escape `\\{escape}`")),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get_mut(self.position)?;
+        self.position += 1;
+        Some(core::mem::replace(token, Token::Punct(' ')))
+    }
+
+    fn eat(&mut self, punct: char) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(c)) if *c == punct) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: char) -> Result<(), String> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(format!("expected `{punct}`"))
+        }
+    }
+
+    /// Returns the sign and magnitude of an integer literal.
+    fn integer(&mut self) -> Result<(bool, u64), String> {
+        let negative = self.eat('-');
+        let Some(Token::Number(text)) = self.next() else {
+            return Err("expected integer".to_string());
+        };
+        let magnitude =
+            if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                u64::from_str_radix(hex, 16)
+            } else if text.len() > 1 && text.starts_with('0') {
+                u64::from_str_radix(&text[1..], 8)
+            } else {
+                text.parse()
+            };
+        let magnitude = magnitude.map_err(|_| format!("invalid integer `{text}`"))?;
+        Ok((negative, magnitude))
+    }
+
+    fn signed<T: TryFrom<i128>>(&mut self) -> Result<T, String> {
+        let (negative, magnitude) = self.integer()?;
+        let value = if negative {
+            -i128::from(magnitude)
+        } else {
+            i128::from(magnitude)
+        };
+        T::try_from(value).map_err(|_| "integer out of range".to_string())
+    }
+
+    fn unsigned<T: TryFrom<u64>>(&mut self) -> Result<T, String> {
+        match self.integer()? {
+            (false, magnitude) => {
+                T::try_from(magnitude).map_err(|_| "integer out of range".to_string())
+            }
+            (true, _) => Err("expected unsigned integer".to_string()),
+        }
+    }
+
+    fn float(&mut self) -> Result<f64, String> {
+        let negative = self.eat('-');
+        let value = match self.next() {
+            Some(Token::Ident(name)) => match name.to_ascii_lowercase().as_str() {
+                "inf" | "infinity" => f64::INFINITY,
+                "nan" => f64::NAN,
+                _ => return Err(format!("invalid float `{name}`")),
+            },
+            Some(Token::Number(text)) => {
+                let digits = text
+                    .strip_suffix(['f', 'F'])
+                    .filter(|_| !text.starts_with("0x"))
+                    .unwrap_or(&text);
+                digits
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid float `{text}`"))?
+            }
+            _ => return Err("expected float".to_string()),
+        };
+        Ok(if negative { -value } else { value })
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        match self.next() {
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" | "True" | "t" => Ok(true),
+                "false" | "False" | "f" => Ok(false),
+                _ => Err(format!("invalid bool `{name}`")),
+            },
+            Some(Token::Number(text)) => match text.as_str() {
+                "1" => Ok(true),
+                "0" => Ok(false),
+                _ => Err(format!("invalid bool `{text}`")),
+            },
+            _ => Err("expected bool".to_string()),
+        }
+    }
+
+    /// Reads one or more adjacent string literals, which concatenate.
+    fn string(&mut self) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        let mut any = false;
+        while let Some(Token::String(_)) = self.peek() {
+            if let Some(Token::String(bytes)) = self.next() {
+                out.extend_from_slice(&bytes);
+            }
+            any = true;
+        }
+        if any {
+            Ok(out)
+        } else {
+            Err("expected string".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost_types::FileDescriptorProto;
+    use prost_types::field_descriptor_proto::Label;
+
+    use super::*;
+
+    fn field(name: &str, number: i32, field_type: Type, type_name: &str) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional.into()),
+            r#type: Some(field_type.into()),
+            type_name: (!type_name.is_empty()).then(|| type_name.to_string()),
+            ..FieldDescriptorProto::default()
+        }
+    }
+
+    fn repeated(mut field: FieldDescriptorProto) -> FieldDescriptorProto {
+        field.set_label(Label::Repeated);
+        field
+    }
+
+    fn message(name: &str, field: Vec<FieldDescriptorProto>) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            field,
+            ..DescriptorProto::default()
+        }
+    }
+
+    /// `test.Outer` with a nested `test.Inner`, a `test.Color` enum, and a
+    /// `google.protobuf.Any` field.
+    fn descriptors() -> Descriptors {
+        let inner = message(
+            "Inner",
+            vec![
+                field("id", 1, Type::Int32, ""),
+                field("note", 2, Type::String, ""),
+            ],
+        );
+        let outer = message(
+            "Outer",
+            vec![
+                field("inner", 1, Type::Message, ".test.Inner"),
+                repeated(field("values", 2, Type::Sint32, "")),
+                repeated(field("items", 3, Type::Message, ".test.Inner")),
+                field("data", 4, Type::Bytes, ""),
+                field("text", 5, Type::String, ""),
+                field("color", 6, Type::Enum, ".test.Color"),
+                field("any", 7, Type::Message, ".google.protobuf.Any"),
+                field("ratio", 8, Type::Double, ""),
+            ],
+        );
+        let color = EnumDescriptorProto {
+            name: Some("Color".to_string()),
+            value: ["COLOR_UNSPECIFIED", "COLOR_RED"]
+                .into_iter()
+                .zip(0..)
+                .map(|(name, number)| EnumValueDescriptorProto {
+                    name: Some(name.to_string()),
+                    number: Some(number),
+                    ..EnumValueDescriptorProto::default()
+                })
+                .collect(),
+            ..EnumDescriptorProto::default()
+        };
+        let any = message(
+            "Any",
+            vec![
+                field("type_url", 1, Type::String, ""),
+                field("value", 2, Type::Bytes, ""),
+            ],
+        );
+        let set = FileDescriptorSet {
+            file: vec![
+                FileDescriptorProto {
+                    package: Some("test".to_string()),
+                    message_type: vec![inner, outer],
+                    enum_type: vec![color],
+                    ..FileDescriptorProto::default()
+                },
+                FileDescriptorProto {
+                    package: Some("google.protobuf".to_string()),
+                    message_type: vec![any],
+                    ..FileDescriptorProto::default()
+                },
+            ],
+        };
+        Descriptors::decode(&set.encode_to_vec()).expect("descriptor set")
+    }
+
+    fn roundtrip(text: &str) -> String {
+        let descriptors = descriptors();
+        let bytes = descriptors.parse("test.Outer", text).expect(text);
+        descriptors.print("test.Outer", &bytes).expect("print")
+    }
+
+    fn parse_error(text: &str) -> String {
+        descriptors().parse("test.Outer", text).expect_err(text)
+    }
+
+    #[test]
+    fn escapes_are_decoded_and_printed_back() {
+        assert_eq!(
+            roundtrip(r#"data: "q\"\'\\\n\r\t\a\x41\101\0\377""#),
+            r#"data: "q\"\'\\\n\r\t\007AA\000\377" "#
+        );
+        assert_eq!(roundtrip(r#"text: "café""#), "text: \"caf\\303\\251\" ");
+        assert_eq!(roundtrip(r#"text: "a" 'b' "c""#), r#"text: "abc" "#);
+    }
+
+    #[test]
+    fn nested_messages_accept_both_delimiters() {
+        assert_eq!(
+            roundtrip(r#"inner { id: 1 note: "x" } color: COLOR_RED"#),
+            r#"inner { id: 1 note: "x" } color: COLOR_RED "#
+        );
+        assert_eq!(roundtrip("inner: < id: -2 >"), "inner { id: -2 } ");
+    }
+
+    #[test]
+    fn repeated_fields_accept_lists_and_repeats() {
+        assert_eq!(
+            roundtrip("values: [1, -2] values: 3 items [{ id: 1 }, < id: 2 >] items {}"),
+            "values: 1 values: -2 values: 3 items { id: 1 } items { id: 2 } items { } "
+        );
+        assert_eq!(roundtrip("values: [] items: []"), "");
+
+        // Packed encoding prints one entry per element.
+        let descriptors = descriptors();
+        let packed = [0x12, 0x03, 0x02, 0x03, 0x04];
+        assert_eq!(
+            descriptors.print("test.Outer", &packed).expect("print"),
+            "values: 1 values: -2 values: 2 "
+        );
+    }
+
+    #[test]
+    fn any_is_a_plain_message_without_expansion() {
+        assert_eq!(
+            roundtrip(r#"any { type_url: "type.googleapis.com/test.Inner" value: "\010\001" }"#),
+            r#"any { type_url: "type.googleapis.com/test.Inner" value: "\010\001" } "#
+        );
+        assert_eq!(
+            parse_error("any { [type.googleapis.com/test.Inner] { id: 1 } }"),
+            "extensions and Any expansion are not supported"
+        );
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        for (text, err) in [
+            ("missing: 1", "unknown field missing"),
+            ("inner { id: 1", "expected `}`"),
+            ("inner { id: 1 >", "expected field name"),
+            ("ratio 1.5", "expected `:`"),
+            ("inner: 1", "expected message value for field inner"),
+            (r#"text: "open"#, "unterminated string"),
+            (r#"text: "\q""#, "invalid escape `\\q`"),
+            (r#"text: "\xff""#, "invalid UTF-8 in field text"),
+            ("values: 4294967296", "integer out of range"),
+            ("color: COLOR_BLUE", "unknown enum value COLOR_BLUE"),
+            ("values: [1 2]", "expected `,`"),
+        ] {
+            assert_eq!(parse_error(text), err, "{text}");
+        }
+        assert_eq!(
+            descriptors()
+                .print("test.Outer", &[0x10])
+                .expect_err("truncated"),
+            "truncated varint"
+        );
+    }
+}
@@ -17,8 +17,86 @@
 //!
 //! let json = serde_json::to_string(&Example { value: 1 }).unwrap();
 //! ```
+//!
+//! # Attributes
+//! Each key below applies only to the item its section names; a key on any
+//! other item, or a key not listed here, is a compile error.
+//!
+//! # Field attributes
+//! - `proto_name = "..."` / `json_name = "..."`: the names accepted on input;
+//!   `json_name` is also used on output. Two fields accepting the same name
+//!   are rejected at compile time. These two also apply to oneof members.
+//! - `timestamp_epoch`: encodes an `Option<Timestamp>` field as a JSON number
+//!   of seconds since the Unix epoch instead of an RFC 3339 string. Input may
+//!   use either form. This is not canonical and is meant for migrating legacy
+//!   encodings.
+//! - `accept_byte_array`: lets a `bytes` field also be read from a JSON array
+//!   of byte values such as `[0, 1, 255]`. Output stays base64.
+//! - `oneof_type = "path::To::Enum"`: the oneof enum type used by the
+//!   generated code for a `#[prost(oneof = "...")]` field. Defaults to the
+//!   type inside the field's `Option`; set it when that path does not resolve
+//!   where the message is defined.
+//! - `default = "expr"`: the value a field takes when it is absent from the
+//!   input (or `null`), for proto2 fields declared with `[default = ...]`.
+//!   On output the field is omitted when it equals the default and written
+//!   otherwise, including when it holds the protobuf zero value, so every
+//!   value reads back unchanged. The expression is evaluated once per
+//!   deserialized message and once per serialized one.
+//! - `with = "module"`: encodes the field with `module::serialize` and
+//!   `module::deserialize`, like serde's `with`, instead of the canonical
+//!   form. The field is still omitted when it holds its default value, and
+//!   an absent or `null` key still leaves it at its default.
+//! - `flatten`: inlines the fields of a message (or `Option` message) field
+//!   into the parent object instead of nesting them under the field's name.
+//!   On input, every key the parent does not recognize is buffered and
+//!   passed to each flattened field, so a flattened message cannot have a
+//!   catch-all for unknown keys of its own. An `Option` field is set only
+//!   when one of the message's keys is present. Requires the `std` feature.
+//! - `omit_empty_message`: leaves an `Option` message field out of the
+//!   output when it is `Some` but would serialize as `{}`. This is not
+//!   canonical. Checking costs one extra pass over the message's field
+//!   presence checks each time the field is set, without serializing values.
+//! - `skip`: leaves the field out of canonical JSON entirely. It is never
+//!   emitted and is initialized with `Default::default()` on deserialize.
+//!   `PhantomData` fields are always treated this way.
+//!
+//! # Newtypes and unit structs
+//! A tuple struct with exactly one field, such as `struct Wrapper(Inner);`,
+//! serializes and deserializes exactly like the wrapped type.
+//! A unit struct is a message without fields and is written as `{}`.
+//!
+//! # Oneofs
+//! A oneof field inside a message is optional: `{}` or an explicit `null`
+//! leaves it unset. A oneof enum deserialized on its own has no unset state,
+//! so input without exactly one recognized, non-null member is an error.
+//! An explicit `null` for a member, as in `{"name": null}`, is read like an
+//! absent key; the `reject_null_oneof_members` option makes it an error.
+//!
+//! # Message attributes
+//! - `message_name = "..."`: the type name accepted as a wrapper key when the
+//!   `unwrap_message_name` option is set. Defaults to the struct name.
+//! - `try_from_value`: also implements `TryFrom<serde_json::Value>` through
+//!   `from_canonical_value`, with `serde_json::Error` as the error type.
+//!   Requires the `std` feature.
+//! - `canonical_fields`: also implements `CanonicalFields`, listing each
+//!   field's proto name, JSON name, and kind, and adds an inherent
+//!   `canonical_field_names()` returning the `(proto_name, json_name)` pairs.
+//!
+//! # Enum attributes
+//! - `allow_quoted_enum_numbers`: also accepts quoted numbers such as `"2"`
+//!   for this enum on input, written like integer map keys: no `+`, leading
+//!   zeros, or whitespace. This is not canonical, so it is off by default.
+//! - `enum_numbers`: writes this enum as its number instead of its name,
+//!   both on its own and wherever a message field uses it. Input still
+//!   accepts either form.
+//! - `alias(name = "...", number = N)`: also accepts `name` on input for the
+//!   value numbered `N`. prost drops `allow_alias` duplicates from the
+//!   generated enum, so output always uses the primary name. Repeat it once
+//!   per alias; `prost-canonical-serde-build` emits these automatically.
+use std::collections::BTreeMap;
+
 use proc_macro::TokenStream;
-use quote::quote;
+use quote::{quote, quote_spanned};
 use syn::{
     parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Fields, Ident, LitStr, Path,
     Type, TypePath,
@@ -71,27 +149,156 @@ fn expand_serialize_struct(
     data: &syn::DataStruct,
 ) -> syn::Result<proc_macro2::TokenStream> {
     let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let attrs = parse_canonical_attrs(&input.attrs, AttrTarget::Message)?;
+    if let Some(inner) = newtype_field(&data.fields)? {
+        if attrs.canonical_fields {
+            return Err(syn::Error::new(
+                inner.span(),
+                "`canonical_fields` is not supported on newtype structs",
+            ));
+        }
+        return Ok(quote! {
+            impl #impl_generics ::prost_canonical_serde::CanonicalSerialize for #name #ty_generics #where_clause {
+                fn serialize_canonical<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: ::serde::Serializer,
+                {
+                    <#inner as ::prost_canonical_serde::CanonicalSerialize>::serialize_canonical(
+                        &self.0,
+                        serializer,
+                    )
+                }
+            }
+
+            impl #impl_generics ::serde::Serialize for #name #ty_generics #where_clause {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: ::serde::Serializer,
+                {
+                    <Self as ::prost_canonical_serde::CanonicalSerialize>::serialize_canonical(
+                        self,
+                        serializer,
+                    )
+                }
+            }
+        });
+    }
     let fields = extract_fields(&data.fields)?;
     let mut field_serializers = Vec::new();
-
-    for field in &fields {
-        field_serializers.push(serialize_field(field));
+    let mut feature_guards = Vec::new();
+
+    let mut merge_children = Vec::new();
+    let mut info_counts = Vec::new();
+    let mut info_copies = Vec::new();
+
+    for field in fields.iter().filter(|field| !field.skip) {
+        field_serializers.push(serialize_field(field, input));
+        merge_children.push(field_merge_children(field));
+        let (count, copy) = field_info(field);
+        info_counts.push(count);
+        info_copies.push(copy);
+        if field.with.is_none() {
+            feature_guards.extend(bytes_guard(&field.kind, &field.ty));
+            feature_guards.extend(any_guard(&field.ty));
+        }
     }
 
+    // The table is built in inner consts, which cannot name `Self` or the
+    // struct's generics, so it is spelled out wherever it is needed.
+    let fields_len = quote! { 0 #(+ #info_counts)* };
+    let fields_table = quote! {
+        {
+            let mut fields = [::prost_canonical_serde::CanonicalFieldInfo {
+                proto_name: "",
+                json_name: "",
+                kind: ::prost_canonical_serde::CanonicalFieldKind::Other,
+                oneof: ::core::option::Option::None,
+            }; LEN];
+            let mut index = 0;
+            #({ #info_copies })*
+            assert!(index == LEN);
+            fields
+        }
+    };
+    let canonical_fields = attrs.canonical_fields.then(|| {
+        quote! {
+            impl #impl_generics ::prost_canonical_serde::CanonicalFields for #name #ty_generics #where_clause {
+                const FIELDS: &'static [::prost_canonical_serde::CanonicalFieldInfo] =
+                    <Self as ::prost_canonical_serde::ProstMessage>::FIELDS;
+            }
+
+            impl #impl_generics #name #ty_generics #where_clause {
+                /// Returns `(proto_name, json_name)` for every field, in
+                /// declaration order, with oneof members in place of their oneof
+                /// and flattened fields in place of their message.
+                pub fn canonical_field_names() -> &'static [(&'static str, &'static str)] {
+                    const LEN: usize = #fields_len;
+                    const NAMES: [(&str, &str); LEN] = {
+                        let fields: [::prost_canonical_serde::CanonicalFieldInfo; LEN] = #fields_table;
+                        let mut names = [("", ""); LEN];
+                        let mut index = 0;
+                        while index < LEN {
+                            names[index] = (fields[index].proto_name, fields[index].json_name);
+                            index += 1;
+                        }
+                        names
+                    };
+                    &NAMES
+                }
+            }
+        }
+    });
+
     Ok(quote! {
-        impl ::prost_canonical_serde::CanonicalSerialize for #name {
+        #(#feature_guards)*
+        #canonical_fields
+
+        impl #impl_generics ::prost_canonical_serde::ProstMessage for #name #ty_generics #where_clause {
+            const FIELDS: &'static [::prost_canonical_serde::CanonicalFieldInfo] = {
+                const LEN: usize = #fields_len;
+                const FIELDS: [::prost_canonical_serde::CanonicalFieldInfo; LEN] = #fields_table;
+                &FIELDS
+            };
+
+            fn serialize_fields<S>(&self, map: &mut S) -> Result<(), S::Error>
+            where
+                S: ::serde::ser::SerializeMap,
+            {
+                #(#field_serializers)*
+                Ok(())
+            }
+
+            fn merge_children() -> ::prost_canonical_serde::__alloc::vec::Vec<::prost_canonical_serde::MergeChildren> {
+                let mut children: ::prost_canonical_serde::__alloc::vec::Vec<
+                    ::prost_canonical_serde::MergeChildren,
+                > = ::prost_canonical_serde::__alloc::vec::Vec::new();
+                #(#merge_children)*
+                children
+            }
+        }
+
+        impl #impl_generics ::prost_canonical_serde::CanonicalSerialize for #name #ty_generics #where_clause {
             fn serialize_canonical<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
             where
                 S: ::serde::Serializer,
             {
                 use ::serde::ser::SerializeMap;
                 let mut map = serializer.serialize_map(None)?;
-                #(#field_serializers)*
+                <Self as ::prost_canonical_serde::ProstMessage>::serialize_fields(self, &mut map)?;
                 map.end()
             }
+
+            fn __merge_fields() -> ::core::option::Option<
+                ::prost_canonical_serde::__alloc::vec::Vec<::prost_canonical_serde::MergeField>,
+            > {
+                ::core::option::Option::Some(
+                    <Self as ::prost_canonical_serde::ProstMessage>::merge_fields(),
+                )
+            }
         }
 
-        impl ::serde::Serialize for #name {
+        impl #impl_generics ::serde::Serialize for #name #ty_generics #where_clause {
             fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
             where
                 S: ::serde::Serializer,
@@ -105,93 +312,275 @@ fn expand_serialize_struct(
     })
 }
 
+/// Builds the `TryFrom<serde_json::Value>` impl requested by the
+/// `try_from_value` attribute.
+fn try_from_value_impl(input: &DeriveInput) -> proc_macro2::TokenStream {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        ::prost_canonical_serde::__require_std!("try_from_value");
+
+        impl #impl_generics ::core::convert::TryFrom<::prost_canonical_serde::__serde_json::Value>
+            for #name #ty_generics #where_clause
+        {
+            type Error = ::prost_canonical_serde::__serde_json::Error;
+
+            fn try_from(
+                value: ::prost_canonical_serde::__serde_json::Value,
+            ) -> Result<Self, Self::Error> {
+                ::prost_canonical_serde::from_canonical_value(value)
+            }
+        }
+    }
+}
+
+/// Builds the deserialize impls of a single-field tuple struct, which reads
+/// and merges as its field.
+fn newtype_deserialize_impl(input: &DeriveInput, inner: &Type) -> proc_macro2::TokenStream {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut de_generics = input.generics.clone();
+    de_generics.params.insert(0, syn::parse_quote!('de));
+    let (de_impl_generics, _, _) = de_generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::prost_canonical_serde::CanonicalDeserialize for #name #ty_generics #where_clause {
+            fn deserialize_canonical<'de, D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+            {
+                <#inner as ::prost_canonical_serde::CanonicalDeserialize>::deserialize_canonical(
+                    deserializer,
+                )
+                .map(#name)
+            }
+
+            fn __merge_canonical<'de, D>(&mut self, deserializer: D) -> Result<(), D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+            {
+                <#inner as ::prost_canonical_serde::CanonicalDeserialize>::__merge_canonical(
+                    &mut self.0,
+                    deserializer,
+                )
+            }
+        }
+
+        impl #de_impl_generics ::serde::Deserialize<'de> for #name #ty_generics #where_clause {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+            {
+                <Self as ::prost_canonical_serde::CanonicalDeserialize>::deserialize_canonical(
+                    deserializer,
+                )
+            }
+        }
+    }
+}
+
 fn expand_deserialize_struct(
     input: &DeriveInput,
     data: &syn::DataStruct,
 ) -> syn::Result<proc_macro2::TokenStream> {
     let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut de_generics = input.generics.clone();
+    de_generics.params.insert(0, syn::parse_quote!('de));
+    let (de_impl_generics, _, _) = de_generics.split_for_impl();
+    let attrs = parse_canonical_attrs(&input.attrs, AttrTarget::Message)?;
+    let try_from_value = attrs.try_from_value.then(|| try_from_value_impl(input));
+    if let Some(inner) = newtype_field(&data.fields)? {
+        let newtype_impl = newtype_deserialize_impl(input, inner);
+        return Ok(quote! {
+            #try_from_value
+            #newtype_impl
+        });
+    }
+    let message_name = attrs.message_name.unwrap_or_else(|| name.to_string());
     let fields = extract_fields(&data.fields)?;
     let mut field_inits = Vec::new();
     let mut field_names = Vec::new();
     let mut match_arms = Vec::new();
     let mut oneof_checks = Vec::new();
+    let mut key_names = Vec::new();
+    let mut oneof_key_names = Vec::new();
+    let mut claimed_keys = BTreeMap::new();
+    let mut feature_guards = Vec::new();
+    let mut flatten_fields = Vec::new();
+    let mut oneof_fields = Vec::new();
 
     for field in &fields {
         let ident = field.ident.clone();
         field_names.push(ident.clone());
         field_inits.push(init_field(field));
 
-        if field.is_oneof {
+        if field.skip {
+            continue;
+        }
+        if field.with.is_none() {
+            feature_guards.extend(bytes_guard(&field.kind, &field.ty));
+            feature_guards.extend(any_guard(&field.ty));
+        }
+
+        if let Some(message_ty) = &field.flatten {
+            flatten_fields.push((field, message_ty));
+        } else if field.is_oneof {
             let oneof_type = field
                 .oneof_type
                 .as_ref()
                 .ok_or_else(|| syn::Error::new(ident.span(), "oneof field must be Option"))?;
-            oneof_checks.push(quote! {
-                match <#oneof_type as ::prost_canonical_serde::ProstOneof>::try_deserialize(
-                    key,
-                    &mut map,
-                )? {
-                    ::prost_canonical_serde::OneofMatch::Matched(Some(value)) => {
-                        if #ident.is_some() {
-                            return Err(::serde::de::Error::custom("multiple oneof fields set"));
-                        }
-                        #ident = Some(value);
-                        continue;
-                    }
-                    ::prost_canonical_serde::OneofMatch::Matched(None) => {
-                        continue;
-                    }
-                    ::prost_canonical_serde::OneofMatch::NoMatch => {}
-                }
+            oneof_fields.push((ident.clone(), oneof_type));
+            oneof_key_names.push(quote! {
+                <#oneof_type as ::prost_canonical_serde::ProstOneof>::FIELD_NAMES
             });
+            oneof_checks.push(oneof_check(&ident, oneof_type));
         } else {
-            match_arms.push(deserialize_match_arm(field)?);
+            for key in [&field.json_name, &field.proto_name] {
+                let Some(other) = claimed_keys.insert(key.clone(), &field.ident) else {
+                    continue;
+                };
+                if other != &field.ident {
+                    let mut err = syn::Error::new(
+                        ident.span(),
+                        format!("JSON key `{key}` is also accepted by field `{other}`"),
+                    );
+                    err.combine(syn::Error::new(
+                        other.span(),
+                        format!("JSON key `{key}` is also accepted by field `{ident}`"),
+                    ));
+                    return Err(err);
+                }
+            }
+            key_names.push(LitStr::new(&field.json_name, ident.span()));
+            if field.proto_name != field.json_name {
+                key_names.push(LitStr::new(&field.proto_name, ident.span()));
+            }
+            match_arms.push(deserialize_match_arm(field, input)?);
         }
     }
 
+    let oneof_key_checks = oneof_key_checks(&oneof_fields, &claimed_keys);
+    let (flatten_guard, flatten_init, unknown_key) = flatten_buffer(!flatten_fields.is_empty());
+    let flatten_fields = flatten_field_exprs(&flatten_fields);
+    let null_message_methods = null_message_methods();
+    // Generated locals use mixed-site hygiene so fields with the same name
+    // neither shadow them nor are shadowed by them.
+    let first_key = Ident::new("first_key", proc_macro2::Span::mixed_site());
+    let merge_target = merge_target_ident();
+    let mut visitor_generics = input.generics.clone();
+    visitor_generics
+        .params
+        .insert(0, syn::parse_quote!('__target));
+    let (visitor_impl_generics, visitor_ty_generics, _) = visitor_generics.split_for_impl();
+    let mut visitor_de_generics = visitor_generics.clone();
+    visitor_de_generics.params.insert(0, syn::parse_quote!('de));
+    let (visitor_de_impl_generics, _, _) = visitor_de_generics.split_for_impl();
+
     Ok(quote! {
-        impl ::prost_canonical_serde::CanonicalDeserialize for #name {
-            fn deserialize_canonical<'de, D>(deserializer: D) -> Result<Self, D::Error>
-            where
-                D: ::serde::Deserializer<'de>,
-            {
-                struct Visitor;
+        #(#feature_guards)*
+        #(#oneof_key_checks)*
+        #flatten_guard
+        #try_from_value
+
+        const _: () = {
+            // Reads a message, or with a target merges the input onto it.
+            struct Visitor #visitor_impl_generics (
+                ::prost_canonical_serde::MessageOptions,
+                ::core::option::Option<&'__target mut #name #ty_generics>,
+            ) #where_clause;
+
+            impl #visitor_impl_generics Visitor #visitor_ty_generics #where_clause {
+                fn read<'de, D>(self, deserializer: D) -> Result<#name #ty_generics, D::Error>
+                where
+                    D: ::serde::Deserializer<'de>,
+                {
+                    if self.0.null_message_as_default {
+                        deserializer.deserialize_option(self)
+                    } else {
+                        deserializer.deserialize_map(self)
+                    }
+                }
+            }
 
-                impl<'de> ::serde::de::Visitor<'de> for Visitor {
-                    type Value = #name;
+            impl #visitor_de_impl_generics ::serde::de::Visitor<'de> for Visitor #visitor_ty_generics #where_clause {
+                type Value = #name #ty_generics;
 
-                    fn expecting(&self, formatter: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
-                        formatter.write_str("map")
-                    }
+                fn expecting(&self, formatter: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    formatter.write_str("map")
+                }
 
-                    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
-                    where
-                        A: ::serde::de::MapAccess<'de>,
-                    {
-                        #(#field_inits)*
-
-                        while let Some(key) = map.next_key::<::alloc::borrow::Cow<'de, str>>()? {
-                            let key = key.as_ref();
-                            #(#oneof_checks)*
-                            match key {
-                                #(#match_arms)*
-                                _ => {
-                                    let _ = map.next_value::<::serde::de::IgnoredAny>()?;
-                                }
+                fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+                where
+                    A: ::serde::de::MapAccess<'de>,
+                {
+                    let options = self.0;
+                    let mut #merge_target = self.1;
+                    #(#field_inits)*
+                    #flatten_init
+
+                    let mut #first_key = true;
+                    while let Some(key) = map.next_key::<::prost_canonical_serde::__alloc::borrow::Cow<'de, str>>()? {
+                        if ::core::mem::take(&mut #first_key)
+                            && options.unwrap_message_name
+                            && key.as_ref() == #message_name
+                        {
+                            let mut message = #name {
+                                #(#field_names),*
+                            };
+                            ::prost_canonical_serde::unwrap_message_name(
+                                #message_name,
+                                &mut message,
+                                #merge_target.is_some(),
+                                &mut map,
+                            )?;
+                            return Ok(message);
+                        }
+                        let key = ::prost_canonical_serde::resolve_field_key(
+                            key.as_ref(),
+                            &[&[#(#key_names),*], #(#oneof_key_names),*],
+                            options.case_insensitive_keys,
+                        );
+                        #(#oneof_checks)*
+                        match key {
+                            #(#match_arms)*
+                            _ => {
+                                #unknown_key
                             }
                         }
-
-                        Ok(#name {
-                            #(#field_names),*
-                        })
                     }
+                    #(#flatten_fields)*
+
+                    Ok(#name {
+                        #(#field_names),*
+                    })
                 }
 
-                deserializer.deserialize_map(Visitor)
+                #null_message_methods
             }
-        }
 
-        impl<'de> ::serde::Deserialize<'de> for #name {
+            impl #impl_generics ::prost_canonical_serde::CanonicalDeserialize for #name #ty_generics #where_clause {
+                fn deserialize_canonical<'de, D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: ::serde::Deserializer<'de>,
+                {
+                    // Read once per message, so keys are matched without going
+                    // back to the thread-local options.
+                    let options = ::prost_canonical_serde::message_options();
+                    Visitor(options, ::core::option::Option::None).read(deserializer)
+                }
+
+                fn __merge_canonical<'de, D>(&mut self, deserializer: D) -> Result<(), D::Error>
+                where
+                    D: ::serde::Deserializer<'de>,
+                {
+                    let options = ::prost_canonical_serde::message_options();
+                    *self = Visitor(options, ::core::option::Option::Some(self)).read(deserializer)?;
+                    Ok(())
+                }
+            }
+        };
+
+        impl #de_impl_generics ::serde::Deserialize<'de> for #name #ty_generics #where_clause {
             fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
             where
                 D: ::serde::Deserializer<'de>,
@@ -239,18 +628,44 @@ fn expand_serialize_enum(
         });
     }
 
+    let attrs = parse_canonical_attrs(&input.attrs, AttrTarget::Enum)?;
+    for variant in &data.variants {
+        parse_canonical_attrs(&variant.attrs, AttrTarget::EnumValue)?;
+    }
+    let allow_quoted_numbers = attrs
+        .allow_quoted_enum_numbers
+        .then(|| quote! { const ALLOW_QUOTED_NUMBERS: bool = true; });
+    let serialize_as_number = attrs
+        .enum_numbers
+        .then(|| quote! { const SERIALIZE_AS_NUMBER: bool = true; });
+    let from_str_name = if attrs.aliases.is_empty() {
+        quote! { #name::from_str_name(value) }
+    } else {
+        let alias_names = attrs.aliases.iter().map(|(alias, _)| alias);
+        let alias_numbers = attrs.aliases.iter().map(|(_, number)| number);
+        quote! {
+            match value {
+                #(#alias_names => Self::try_from(#alias_numbers).ok(),)*
+                _ => #name::from_str_name(value),
+            }
+        }
+    };
+
     Ok(quote! {
         impl ::prost_canonical_serde::ProstEnum for #name {
+            #allow_quoted_numbers
+            #serialize_as_number
+
             fn from_i32(value: i32) -> ::core::option::Option<Self> {
                 Self::try_from(value).ok()
             }
 
             fn from_str_name(value: &str) -> ::core::option::Option<Self> {
-                #name::from_str_name(value)
+                #from_str_name
             }
 
             fn as_str_name(&self) -> &'static str {
-                self.as_str_name()
+                #name::as_str_name(self)
             }
 
             fn as_i32(&self) -> i32 {
@@ -263,7 +678,10 @@ fn expand_serialize_enum(
             where
                 S: ::serde::Serializer,
             {
-                serializer.serialize_str(self.as_str_name())
+                ::serde::Serialize::serialize(
+                    &::prost_canonical_serde::CanonicalEnum::<Self>::new(*self as i32),
+                    serializer,
+                )
             }
         }
 
@@ -304,7 +722,7 @@ fn expand_deserialize_enum(input: &DeriveInput, data: &syn::DataEnum) -> proc_ma
                             A: ::serde::de::MapAccess<'de>,
                         {
                             let mut found = None;
-                            while let Some(key) = map.next_key::<::alloc::borrow::Cow<'de, str>>()? {
+                            while let Some(key) = map.next_key::<::prost_canonical_serde::__alloc::borrow::Cow<'de, str>>()? {
                                 let key = key.as_ref();
                                 match <#name as ::prost_canonical_serde::ProstOneof>::try_deserialize(
                                     key,
@@ -384,29 +802,35 @@ fn expand_oneof_impl(
     let name = &input.ident;
     let mut serialize_arms = Vec::new();
     let mut deserialize_arms = Vec::new();
+    let mut field_names = Vec::new();
+    let mut merge_children = Vec::new();
+    let mut merge_arms = Vec::new();
+    let mut field_infos = Vec::new();
+    let mut feature_guards = Vec::new();
+    parse_canonical_attrs(&input.attrs, AttrTarget::Oneof)?;
 
     for variant in &data.variants {
         let ident = &variant.ident;
-        let (proto_name_attr, json_name_attr) = parse_canonical_attrs(&variant.attrs)?;
+        let attrs = parse_canonical_attrs(&variant.attrs, AttrTarget::OneofMember)?;
         let (value_ty, kind, enum_path) = parse_variant(variant)?;
+        feature_guards.extend(bytes_guard(&kind, &value_ty));
+        feature_guards.extend(any_guard(&value_ty));
         let fallback = lower_camel(&ident.to_string());
-        let proto_name = proto_name_attr.unwrap_or_else(|| fallback.clone());
-        let json_name = json_name_attr.unwrap_or_else(|| fallback.clone());
+        let proto_name = attrs.proto_name.unwrap_or_else(|| fallback.clone());
+        let json_name = attrs.json_name.unwrap_or_else(|| fallback.clone());
         let json_name_literal = LitStr::new(&json_name, ident.span());
         let proto_name_literal = LitStr::new(&proto_name, ident.span());
         let value_ident = Ident::new("value", ident.span());
 
         let serialize_expr = serialize_value_expr(&kind, &value_ident, enum_path.as_ref());
-        let deserialize_expr = if let Kind::Enum(path) = &kind {
+        let wrapper_ty = if let Kind::Enum(path) = &kind {
             let path = enum_path.as_ref().unwrap_or(path);
-            quote! {
-                map.next_value::<::prost_canonical_serde::CanonicalEnumOption<#path>>()?.0
-            }
+            quote! { ::prost_canonical_serde::CanonicalEnumOption<#path> }
         } else {
-            quote! {
-                map.next_value::<::prost_canonical_serde::CanonicalOption<#value_ty>>()?.0
-            }
+            quote! { ::prost_canonical_serde::CanonicalOption<#value_ty> }
         };
+        let next_value = next_value_expr(&wrapper_ty, &json_name_literal);
+        let deserialize_expr = quote! { #next_value.0 };
 
         serialize_arms.push(quote! {
             Self::#ident(#value_ident) => {
@@ -415,22 +839,84 @@ fn expand_oneof_impl(
             }
         });
 
+        field_names.push(json_name_literal.clone());
+        let field_kind = field_kind(&kind);
+        field_infos.push(quote! {
+            ::prost_canonical_serde::CanonicalFieldInfo {
+                proto_name: #proto_name_literal,
+                json_name: #json_name_literal,
+                kind: #field_kind,
+                oneof: ::core::option::Option::None,
+            }
+        });
+        merge_children.push(message_children(&kind, &value_ty, None));
         let match_pat = if json_name == proto_name {
             quote! { #json_name_literal }
         } else {
+            field_names.push(proto_name_literal.clone());
             quote! { #json_name_literal | #proto_name_literal }
         };
 
+        if matches!(kind, Kind::Message) {
+            merge_arms.push(quote! {
+                (#match_pat, ::core::option::Option::Some(Self::#ident(value))) => {
+                    if !map.next_value_seed(::prost_canonical_serde::MergeSeed::field(
+                        value,
+                        #json_name_literal,
+                    ))? {
+                        *target = ::core::option::Option::None;
+                    }
+                    Ok(true)
+                }
+            });
+        }
         deserialize_arms.push(quote! {
             #match_pat => {
                 let value = #deserialize_expr;
+                if value.is_none() && ::prost_canonical_serde::reject_null_oneof_members() {
+                    return Err(::prost_canonical_serde::field_error(
+                        #json_name_literal,
+                        &<A::Error as ::serde::de::Error>::custom("oneof member must not be null"),
+                    ));
+                }
                 Ok(::prost_canonical_serde::OneofMatch::Matched(value.map(Self::#ident)))
             }
         });
     }
 
+    // Members other than messages keep the trait's default, which replaces
+    // the oneof.
+    let try_merge = (!merge_arms.is_empty()).then(|| {
+        quote! {
+            fn try_merge<'de, A>(
+                key: &str,
+                map: &mut A,
+                target: &mut ::core::option::Option<Self>,
+            ) -> Result<bool, A::Error>
+            where
+                A: ::serde::de::MapAccess<'de>,
+            {
+                match (key, target.as_mut()) {
+                    #(#merge_arms)*
+                    _ => match <Self as ::prost_canonical_serde::ProstOneof>::try_deserialize(key, map)? {
+                        ::prost_canonical_serde::OneofMatch::Matched(value) => {
+                            *target = value;
+                            Ok(true)
+                        }
+                        ::prost_canonical_serde::OneofMatch::NoMatch => Ok(false),
+                    },
+                }
+            }
+        }
+    });
+
     Ok(quote! {
+        #(#feature_guards)*
+
         impl ::prost_canonical_serde::ProstOneof for #name {
+            const FIELD_NAMES: &'static [&'static str] = &[#(#field_names),*];
+            const FIELDS: &'static [::prost_canonical_serde::CanonicalFieldInfo] = &[#(#field_infos),*];
+
             fn serialize_field<S>(&self, map: &mut S) -> Result<(), S::Error>
             where
                 S: ::serde::ser::SerializeMap,
@@ -450,125 +936,546 @@ fn expand_oneof_impl(
                     _ => Ok(::prost_canonical_serde::OneofMatch::NoMatch),
                 }
             }
+
+            #try_merge
+
+            fn merge_children() -> ::prost_canonical_serde::__alloc::vec::Vec<::prost_canonical_serde::MergeChildren> {
+                ::prost_canonical_serde::__alloc::vec![#((#merge_children) as ::prost_canonical_serde::MergeChildren),*]
+            }
         }
     })
 }
 
-fn serialize_field(field: &FieldInfo) -> proc_macro2::TokenStream {
+/// Returns the number of `CanonicalFieldInfo` entries `field` contributes and
+/// the const statements copying them into `fields`.
+fn field_info(field: &FieldInfo) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let json_name = LitStr::new(&field.json_name, field.ident.span());
+    if let Some(oneof_type) = &field.oneof_type {
+        let members = quote! { <#oneof_type as ::prost_canonical_serde::ProstOneof>::FIELDS };
+        return (
+            quote! { #members.len() },
+            quote! {
+                let members = #members;
+                let mut member = 0;
+                while member < members.len() {
+                    let mut field = members[member];
+                    field.oneof = ::core::option::Option::Some(#json_name);
+                    fields[index] = field;
+                    index += 1;
+                    member += 1;
+                }
+            },
+        );
+    }
+    if let Some(message_ty) = &field.flatten {
+        let inner = quote! { <#message_ty as ::prost_canonical_serde::ProstMessage>::FIELDS };
+        return (
+            quote! { #inner.len() },
+            quote! {
+                let inner = #inner;
+                let mut field = 0;
+                while field < inner.len() {
+                    fields[index] = inner[field];
+                    index += 1;
+                    field += 1;
+                }
+            },
+        );
+    }
+    let proto_name = LitStr::new(&field.proto_name, field.ident.span());
+    let kind = if field.with.is_some() {
+        quote! { ::prost_canonical_serde::CanonicalFieldKind::Other }
+    } else {
+        field_kind(&field.kind)
+    };
+    (
+        quote! { 1 },
+        quote! {
+            fields[index] = ::prost_canonical_serde::CanonicalFieldInfo {
+                proto_name: #proto_name,
+                json_name: #json_name,
+                kind: #kind,
+                oneof: ::core::option::Option::None,
+            };
+            index += 1;
+        },
+    )
+}
+
+/// Returns the `CanonicalFieldKind` expression describing `kind`.
+fn field_kind(kind: &Kind) -> proc_macro2::TokenStream {
+    let variant = match kind {
+        Kind::Scalar(ScalarKind::Bool) => quote! { Bool },
+        Kind::Scalar(ScalarKind::I32) => quote! { Int32 },
+        Kind::Scalar(ScalarKind::U32) => quote! { Uint32 },
+        Kind::Scalar(ScalarKind::I64) => quote! { Int64 },
+        Kind::Scalar(ScalarKind::U64) => quote! { Uint64 },
+        Kind::Scalar(ScalarKind::F32) => quote! { Float },
+        Kind::Scalar(ScalarKind::F64) => quote! { Double },
+        Kind::Scalar(ScalarKind::String) => quote! { String },
+        Kind::Bytes | Kind::ByteArray(_) => quote! { Bytes },
+        Kind::Enum(_) => quote! { Enum },
+        Kind::Timestamp => quote! { Timestamp },
+        Kind::Duration => quote! { Duration },
+        Kind::Message => quote! { Message },
+        Kind::Phantom => quote! { Other },
+        Kind::Option(inner) => return field_kind(inner),
+        Kind::Vec(inner) => {
+            let inner = field_kind(inner);
+            quote! { Repeated(&#inner) }
+        }
+        Kind::Map(_, key, value) => {
+            let key = match key {
+                KeyKind::String => quote! { String },
+                KeyKind::Bool => quote! { Bool },
+                KeyKind::I32 => quote! { Int32 },
+                KeyKind::I64 => quote! { Int64 },
+                KeyKind::U32 => quote! { Uint32 },
+                KeyKind::U64 => quote! { Uint64 },
+                KeyKind::I128 | KeyKind::U128 => quote! { Other },
+            };
+            let value = field_kind(value);
+            quote! { Map(&::prost_canonical_serde::CanonicalFieldKind::#key, &#value) }
+        }
+    };
+    quote! { ::prost_canonical_serde::CanonicalFieldKind::#variant }
+}
+
+/// Builds the statement adding the `children` of each merge field `field`
+/// contributes, matching its entries in the message's field table.
+fn field_merge_children(field: &FieldInfo) -> proc_macro2::TokenStream {
+    if let Some(oneof_type) = &field.oneof_type {
+        return quote! {
+            children.extend(<#oneof_type as ::prost_canonical_serde::ProstOneof>::merge_children());
+        };
+    }
+    if let Some(message_ty) = &field.flatten {
+        return quote! {
+            children.extend(<#message_ty as ::prost_canonical_serde::ProstMessage>::merge_children());
+        };
+    }
+    let children = if field.with.is_some() {
+        quote! { || ::core::option::Option::None }
+    } else {
+        message_children(&field.kind, &field.ty, field.option_inner.as_ref())
+    };
+    quote! {
+        children.push(#children);
+    }
+}
+
+/// Returns the `children` function for a merge field: the message type's own
+/// merge fields for singular messages, and `None` for everything a merge
+/// replaces.
+fn message_children(
+    kind: &Kind,
+    ty: &Type,
+    option_inner: Option<&Type>,
+) -> proc_macro2::TokenStream {
+    let message_ty = match kind {
+        Kind::Message => Some(ty),
+        Kind::Option(inner) if matches!(inner.as_ref(), Kind::Message) => option_inner,
+        _ => None,
+    };
+    if let Some(message_ty) = message_ty {
+        quote! { <#message_ty as ::prost_canonical_serde::CanonicalSerialize>::__merge_fields }
+    } else {
+        quote! { || ::core::option::Option::None }
+    }
+}
+
+fn serialize_field(field: &FieldInfo, input: &DeriveInput) -> proc_macro2::TokenStream {
     let ident = &field.ident;
     let json_name = LitStr::new(&field.json_name, ident.span());
 
+    if let Some(message_ty) = &field.flatten {
+        let serialize_fields =
+            quote! { <#message_ty as ::prost_canonical_serde::ProstMessage>::serialize_fields };
+        return if matches!(field.kind, Kind::Option(_)) {
+            quote! {
+                if let Some(value) = &self.#ident {
+                    #serialize_fields(value, map)?;
+                }
+            }
+        } else {
+            quote! {
+                #serialize_fields(&self.#ident, map)?;
+            }
+        };
+    }
+
+    if let Some(with) = &field.with {
+        let name = &input.ident;
+        let ty = &field.ty;
+        let (_, ty_generics, _) = input.generics.split_for_impl();
+        let mut with_generics = input.generics.clone();
+        with_generics.params.insert(0, syn::parse_quote!('__a));
+        let (impl_generics, with_ty_generics, where_clause) = with_generics.split_for_impl();
+        let default_check = presence_check_expr(field, &quote! { self.#ident });
+        return quote! {
+            if #default_check {
+                struct SerializeWith #impl_generics #where_clause {
+                    value: &'__a #ty,
+                    phantom: ::core::marker::PhantomData<fn() -> #name #ty_generics>,
+                }
+
+                impl #impl_generics ::serde::Serialize for SerializeWith #with_ty_generics #where_clause {
+                    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                    where
+                        S: ::serde::Serializer,
+                    {
+                        #with::serialize(self.value, serializer)
+                    }
+                }
+
+                map.serialize_entry(
+                    #json_name,
+                    &SerializeWith {
+                        value: &self.#ident,
+                        phantom: ::core::marker::PhantomData::<fn() -> Self>,
+                    },
+                )?;
+            }
+        };
+    }
+
     if field.is_oneof {
+        let oneof_type = &field.oneof_type;
         return quote! {
             if let Some(value) = &self.#ident {
-                ::prost_canonical_serde::ProstOneof::serialize_field(value, &mut map)?;
+                <#oneof_type as ::prost_canonical_serde::ProstOneof>::serialize_field(value, map)?;
             }
         };
     }
 
     match &field.kind {
+        Kind::Option(_) if field.timestamp_epoch => quote! {
+            if let Some(value) = ::core::option::Option::as_ref(&self.#ident) {
+                let value = ::prost_canonical_serde::EpochTimestamp(*value);
+                map.serialize_entry(#json_name, &value)?;
+            }
+        },
         Kind::Option(inner) => {
+            let value_ident = Ident::new("value", ident.span());
+            let value_expr = match inner.as_ref() {
+                Kind::Vec(_) | Kind::Map(_, _, _) => {
+                    container_value_expr(inner, &quote! { #value_ident })
+                }
+                _ => serialize_value_expr(inner, &value_ident, field.enum_path.as_ref()),
+            };
+            let serialize = quote! {
+                let value = #value_expr;
+                map.serialize_entry(#json_name, &value)?;
+            };
+            let serialize = match &field.omit_empty_message {
+                Some(message_ty) => quote! {
+                    if !::prost_canonical_serde::is_empty_message::<#message_ty>(value) {
+                        #serialize
+                    }
+                },
+                None => serialize,
+            };
+            quote! {
+                if let Some(value) = ::core::option::Option::as_ref(&self.#ident) {
+                    #serialize
+                }
+            }
+        }
+        Kind::Vec(_) | Kind::Map(_, _, _) => {
+            let value_expr = container_value_expr(&field.kind, &quote! { &self.#ident });
+            quote! {
+                if !self.#ident.is_empty() {
+                    let value = #value_expr;
+                    map.serialize_entry(#json_name, &value)?;
+                } else if ::prost_canonical_serde::empty_repeated_as_null() {
+                    map.serialize_entry(#json_name, &())?;
+                }
+            }
+        }
+        _ => {
             let value_expr = serialize_value_expr(
-                inner,
+                &field.kind,
                 &Ident::new("value", ident.span()),
                 field.enum_path.as_ref(),
             );
+            let field_expr = quote! { self.#ident };
+            let default_check = presence_check_expr(field, &field_expr);
             quote! {
-                if let Some(value) = &self.#ident {
+                if #default_check {
+                    let value = &self.#ident;
                     let value = #value_expr;
                     map.serialize_entry(#json_name, &value)?;
                 }
             }
         }
-        Kind::Vec(inner) => {
-            let value_stmt = if let Kind::Enum(path) = inner.as_ref() {
-                quote! {
-                    let value = ::prost_canonical_serde::CanonicalEnumSeq::<#path>::new(&self.#ident);
-                    map.serialize_entry(#json_name, &value)?;
-                }
+    }
+}
+
+/// Returns the message visitor methods used under the
+/// `null_message_as_default` option, which deserializes messages through
+/// `deserialize_option`: `null` reads as a message without keys.
+fn null_message_methods() -> proc_macro2::TokenStream {
+    quote! {
+        fn visit_none<E>(self) -> Result<Self::Value, E>
+        where
+            E: ::serde::de::Error,
+        {
+            self.visit_map(::serde::de::value::MapDeserializer::new(
+                ::core::iter::empty::<((), ())>(),
+            ))
+        }
+
+        fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: ::serde::Deserializer<'de>,
+        {
+            deserializer.deserialize_map(self)
+        }
+    }
+}
+
+/// Reads a key naming a member of the oneof field `ident` into it. A message
+/// may set only one member, while a merge may replace the one already set.
+fn oneof_check(ident: &Ident, oneof_type: &Type) -> proc_macro2::TokenStream {
+    let merge_target = merge_target_ident();
+    let oneof = quote! { <#oneof_type as ::prost_canonical_serde::ProstOneof> };
+    quote! {
+        if #merge_target.is_some() {
+            if #oneof::try_merge(key, &mut map, &mut #ident)? {
+                continue;
+            }
+        } else {
+            match #oneof::try_deserialize(key, &mut map)? {
+                ::prost_canonical_serde::OneofMatch::Matched(Some(value)) => {
+                    if #ident.is_some() {
+                        return Err(::serde::de::Error::custom("multiple oneof fields set"));
+                    }
+                    #ident = Some(value);
+                    continue;
+                }
+                ::prost_canonical_serde::OneofMatch::Matched(None) => {
+                    continue;
+                }
+                ::prost_canonical_serde::OneofMatch::NoMatch => {}
+            }
+        }
+    }
+}
+
+/// Builds compile-time checks that no oneof member shares a JSON key with
+/// another field or with a member of another oneof.
+///
+/// Member names are declared on the oneof enum, so unlike plain fields they
+/// cannot be compared while expanding the message.
+fn oneof_key_checks(
+    oneof_fields: &[(Ident, &Type)],
+    claimed_keys: &BTreeMap<String, &Ident>,
+) -> Vec<proc_macro2::TokenStream> {
+    let mut checks = Vec::new();
+    for (index, (ident, oneof_type)) in oneof_fields.iter().enumerate() {
+        let members = quote! { <#oneof_type as ::prost_canonical_serde::ProstOneof>::FIELD_NAMES };
+        let keys = claimed_keys.keys();
+        let message = format!("a member of oneof `{ident}` uses a JSON key another field accepts");
+        checks.push(quote_spanned! {ident.span()=>
+            const _: () = ::core::assert!(
+                !::prost_canonical_serde::keys_overlap(#members, &[#(#keys),*]),
+                #message,
+            );
+        });
+        for (other, other_type) in &oneof_fields[..index] {
+            let message = format!(
+                "a member of oneof `{ident}` uses a JSON key of a member of oneof `{other}`"
+            );
+            checks.push(quote_spanned! {ident.span()=>
+                const _: () = ::core::assert!(
+                    !::prost_canonical_serde::keys_overlap(
+                        #members,
+                        <#other_type as ::prost_canonical_serde::ProstOneof>::FIELD_NAMES,
+                    ),
+                    #message,
+                );
+            });
+        }
+    }
+    checks
+}
+
+/// Returns the `std` guard, buffer declaration, and unknown-key handling for
+/// a message's visitor.
+///
+/// Keys no field claims are buffered and handed to each flattened field.
+fn flatten_buffer(
+    has_flatten: bool,
+) -> (
+    Option<proc_macro2::TokenStream>,
+    Option<proc_macro2::TokenStream>,
+    proc_macro2::TokenStream,
+) {
+    if !has_flatten {
+        return (
+            None,
+            None,
+            quote! {
+                let _ = map.next_value::<::serde::de::IgnoredAny>()?;
+            },
+        );
+    }
+    let flattened = flattened_ident();
+    (
+        Some(quote! { ::prost_canonical_serde::__require_std!("flatten"); }),
+        Some(quote! {
+            let mut #flattened = ::prost_canonical_serde::__serde_json::Map::new();
+        }),
+        quote! {
+            let value = map.next_value::<::prost_canonical_serde::__serde_json::Value>()?;
+            #flattened.insert(::prost_canonical_serde::__alloc::string::String::from(key), value);
+        },
+    )
+}
+
+/// The visitor's buffer of unclaimed keys, with mixed-site hygiene so a field
+/// named `flattened` neither shadows it nor is shadowed by it.
+fn flattened_ident() -> Ident {
+    Ident::new("flattened", proc_macro2::Span::mixed_site())
+}
+
+/// Builds every flattened field in turn, handing the buffer itself to the
+/// last one.
+fn flatten_field_exprs(fields: &[(&FieldInfo, &Type)]) -> Vec<proc_macro2::TokenStream> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, (field, message_ty))| {
+            flatten_field_expr(field, message_ty, index + 1 == fields.len())
+        })
+        .collect()
+}
+
+/// Builds a flattened field from the buffered keys its parent left unclaimed.
+///
+/// Earlier flattened fields read a copy of the buffer and the `last` one takes
+/// it. An `Option` field stays `None` unless one of the message's own keys was
+/// present. When merging, a message already set is merged with the buffer.
+fn flatten_field_expr(
+    field: &FieldInfo,
+    message_ty: &Type,
+    last: bool,
+) -> proc_macro2::TokenStream {
+    let ident = &field.ident;
+    let json_name = &field.json_name;
+    let flattened = flattened_ident();
+    let merge_target = merge_target_ident();
+    let present = Ident::new("present", proc_macro2::Span::mixed_site());
+    let message = Ident::new("message", proc_macro2::Span::mixed_site());
+    let buffer = if last {
+        quote! { ::core::mem::take(&mut #flattened) }
+    } else {
+        quote! { #flattened.clone() }
+    };
+    let buffer = quote! { ::prost_canonical_serde::__serde_json::Value::Object(#buffer) };
+    let map_err = quote! { .map_err(|err| ::prost_canonical_serde::flattened_field_error(#json_name, &err))? };
+    let from_buffer = quote! {
+        ::prost_canonical_serde::from_canonical_value::<#message_ty>(#buffer)#map_err
+    };
+    let merge_buffer = quote! {
+        <#message_ty as ::prost_canonical_serde::CanonicalDeserialize>::__merge_canonical(
+            #message,
+            #buffer,
+        )#map_err;
+    };
+    if matches!(field.kind, Kind::Option(_)) {
+        quote! {
+            let #present = <#message_ty as ::prost_canonical_serde::ProstMessage>::FIELDS
+                .iter()
+                .any(|field| {
+                    #flattened.contains_key(field.proto_name)
+                        || #flattened.contains_key(field.json_name)
+                });
+            if #present {
+                if let (true, Some(#message)) = (#merge_target.is_some(), #ident.as_mut()) {
+                    #merge_buffer
+                } else {
+                    #ident = Some(#from_buffer);
+                }
+            }
+        }
+    } else {
+        quote! {
+            if #merge_target.is_some() {
+                let #message = &mut #ident;
+                #merge_buffer
             } else {
-                quote! {
-                    let value = ::prost_canonical_serde::CanonicalSeq::new(&self.#ident);
-                    map.serialize_entry(#json_name, &value)?;
-                }
-            };
-
-            quote! {
-                if !self.#ident.is_empty() {
-                    #value_stmt
-                }
+                #ident = #from_buffer;
             }
         }
-        Kind::Map(_, _, value_kind) => {
-            let value_stmt = if let Kind::Enum(path) = value_kind.as_ref() {
-                quote! {
-                    let value = ::prost_canonical_serde::CanonicalEnumMapRef::<#path, _>::new(&self.#ident);
-                    map.serialize_entry(#json_name, &value)?;
-                }
-            } else {
-                quote! {
-                    let value = ::prost_canonical_serde::CanonicalMapRef::new(&self.#ident);
-                    map.serialize_entry(#json_name, &value)?;
-                }
-            };
+    }
+}
 
-            quote! {
-                if !self.#ident.is_empty() {
-                    #value_stmt
-                }
+/// Wraps a repeated or map field reference for canonical serialization.
+fn container_value_expr(kind: &Kind, value: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    match kind {
+        Kind::Vec(inner) => {
+            if let Kind::Enum(path) = inner.as_ref() {
+                quote! { ::prost_canonical_serde::CanonicalEnumSeq::<#path>::new(#value) }
+            } else {
+                quote! { ::prost_canonical_serde::CanonicalSeq::new(#value) }
             }
         }
-        _ => {
-            let value_expr = serialize_value_expr(
-                &field.kind,
-                &Ident::new("value", ident.span()),
-                field.enum_path.as_ref(),
-            );
-            let field_expr = quote! { self.#ident };
-            let default_check = default_check_expr(&field.kind, &field_expr);
-            quote! {
-                if #default_check {
-                    let value = &self.#ident;
-                    let value = #value_expr;
-                    map.serialize_entry(#json_name, &value)?;
-                }
+        Kind::Map(_, _, value_kind) => {
+            if let Kind::Enum(path) = value_kind.as_ref() {
+                quote! { ::prost_canonical_serde::CanonicalEnumMapFieldRef::<#path, _>::new(#value) }
+            } else {
+                quote! { ::prost_canonical_serde::CanonicalMapFieldRef::new(#value) }
             }
         }
+        _ => quote! { ::prost_canonical_serde::Canonical::new(#value) },
     }
 }
 
+/// Declares the local a field is read into. When merging, it starts out
+/// holding the target's value, so fields the input does not name keep it.
 fn init_field(field: &FieldInfo) -> proc_macro2::TokenStream {
     let ident = &field.ident;
+    let merge_target = merge_target_ident();
+    let default = field_default_expr(field);
+    quote! {
+        let mut #ident = #default;
+        if let ::core::option::Option::Some(#merge_target) = #merge_target.as_deref_mut() {
+            ::core::mem::swap(&mut #ident, &mut #merge_target.#ident);
+        }
+    }
+}
 
+/// Returns the value a field holds when the input does not set it.
+fn field_default_expr(field: &FieldInfo) -> proc_macro2::TokenStream {
+    if let Some(default) = &field.default {
+        return quote! { #default };
+    }
+    if field.skip {
+        return quote! { ::core::default::Default::default() };
+    }
     if field.is_oneof {
-        return quote! {
-            let mut #ident = ::core::option::Option::None;
-        };
+        return quote! { ::core::option::Option::None };
     }
-
     match &field.kind {
-        Kind::Option(_) => quote! {
-            let mut #ident = ::core::option::Option::None;
-        },
-        Kind::Vec(_) => quote! {
-            let mut #ident = ::alloc::vec::Vec::new();
+        Kind::Option(_) if field.boxed => quote! {
+            ::prost_canonical_serde::__alloc::boxed::Box::new(::core::option::Option::None)
         },
-        Kind::Map(map_kind, _, _) => {
-            let map_new = map_new_expr(map_kind);
-            quote! {
-                let mut #ident = #map_new;
-            }
-        }
-        _ => {
-            let default_expr = default_value_expr(&field.kind);
-            quote! {
-                let mut #ident = #default_expr;
-            }
-        }
+        Kind::Option(_) => quote! { ::core::option::Option::None },
+        _ => default_value_expr(&field.kind),
     }
 }
 
-fn deserialize_match_arm(field: &FieldInfo) -> syn::Result<proc_macro2::TokenStream> {
+/// The visitor's merge target, with mixed-site hygiene so a field named
+/// `merge_target` neither shadows it nor is shadowed by it.
+fn merge_target_ident() -> Ident {
+    Ident::new("merge_target", proc_macro2::Span::mixed_site())
+}
+
+fn deserialize_match_arm(
+    field: &FieldInfo,
+    input: &DeriveInput,
+) -> syn::Result<proc_macro2::TokenStream> {
     let ident = &field.ident;
     let json_name = LitStr::new(&field.json_name, ident.span());
     let proto_name = LitStr::new(&field.proto_name, ident.span());
@@ -579,99 +1486,278 @@ fn deserialize_match_arm(field: &FieldInfo) -> syn::Result<proc_macro2::TokenStr
         quote! { #json_name | #proto_name }
     };
 
+    // A field may itself be called `value`, so the binding must not be able
+    // to shadow it.
+    let value = Ident::new("value", proc_macro2::Span::mixed_site());
+    // `null` resets the field, which matters when merging onto a set value.
+    let default = field_default_expr(field);
+    let merge_target = merge_target_ident();
+
+    if let Some(with) = &field.with {
+        return Ok(with_match_arm(field, input, with, &match_pat));
+    }
+
     match &field.kind {
         Kind::Option(inner) => {
             let inner_ty = field
                 .option_inner
                 .as_ref()
                 .ok_or_else(|| syn::Error::new(ident.span(), "missing Option inner type"))?;
+            let target = if field.boxed {
+                quote! { *#ident }
+            } else {
+                quote! { #ident }
+            };
+            if let Kind::Vec(_) | Kind::Map(_, _, _) = inner.as_ref() {
+                let wrapper_ty = container_wrapper_ty(inner, inner_ty, ident)?;
+                let next_value =
+                    next_value_expr(&quote! { ::core::option::Option<#wrapper_ty> }, &json_name);
+                return Ok(quote! {
+                    #match_pat => {
+                        #target = #next_value.map(|value| value.0);
+                    }
+                });
+            }
+            if field.accept_byte_array {
+                let next_value = next_value_expr(
+                    &quote! {
+                        ::core::option::Option<::prost_canonical_serde::CanonicalBytesOrArray>
+                    },
+                    &json_name,
+                );
+                return Ok(quote! {
+                    #match_pat => {
+                        #target = #next_value.map(|value| value.0);
+                    }
+                });
+            }
+            if field.timestamp_epoch {
+                let next_value = next_value_expr(
+                    &quote! {
+                        ::core::option::Option<::prost_canonical_serde::EpochTimestamp>
+                    },
+                    &json_name,
+                );
+                return Ok(quote! {
+                    #match_pat => {
+                        #target = #next_value.map(|value| value.0);
+                    }
+                });
+            }
             if is_prost_value_type(inner_ty) {
+                let next_value = next_value_expr(
+                    &quote! { ::prost_canonical_serde::CanonicalValue<#inner_ty> },
+                    &json_name,
+                );
                 return Ok(quote! {
                     #match_pat => {
-                        #ident = Some(
-                            map.next_value::<::prost_canonical_serde::CanonicalValue<#inner_ty>>()?
-                                .0,
-                        );
+                        #target = Some(#next_value.0);
                     }
                 });
             }
-            let value_expr = if let Kind::Enum(path) = inner.as_ref() {
+            let wrapper_ty = if let Kind::Enum(path) = inner.as_ref() {
                 let path = field.enum_path.as_ref().unwrap_or(path);
-                quote! {
-                    map.next_value::<::prost_canonical_serde::CanonicalEnumOption<#path>>()?.0
-                }
+                quote! { ::prost_canonical_serde::CanonicalEnumOption<#path> }
             } else {
-                quote! {
-                    map.next_value::<::prost_canonical_serde::CanonicalOption<#inner_ty>>()?.0
-                }
+                quote! { ::prost_canonical_serde::CanonicalOption<#inner_ty> }
             };
-            Ok(quote! {
-                #match_pat => {
-                    #ident = #value_expr;
-                }
-            })
-        }
-        Kind::Vec(inner) => {
-            if let Kind::Enum(path) = inner.as_ref() {
+            let next_value = next_value_expr(&wrapper_ty, &json_name);
+            if matches!(inner.as_ref(), Kind::Message) {
+                let existing = if field.boxed {
+                    quote! { (*#ident).as_mut() }
+                } else {
+                    quote! { #ident.as_mut() }
+                };
                 return Ok(quote! {
                     #match_pat => {
-                        #ident = map
-                            .next_value::<::prost_canonical_serde::CanonicalEnumVec<#path>>()?
-                            .0;
+                        if let (true, Some(#value)) = (#merge_target.is_some(), #existing) {
+                            if !map.next_value_seed(::prost_canonical_serde::MergeSeed::field(
+                                #value,
+                                #json_name,
+                            ))? {
+                                #target = ::core::option::Option::None;
+                            }
+                        } else {
+                            #target = #next_value.0;
+                        }
                     }
                 });
             }
-            let inner_ty = field
-                .vec_inner
-                .as_ref()
-                .ok_or_else(|| syn::Error::new(ident.span(), "missing Vec inner type"))?;
             Ok(quote! {
                 #match_pat => {
-                    #ident = map
-                        .next_value::<::prost_canonical_serde::CanonicalVec<#inner_ty>>()?
-                        .0;
+                    #target = #next_value.0;
                 }
             })
         }
-        Kind::Map(_, _, value_kind) => {
-            let value_expr = if let Kind::Enum(path) = value_kind.as_ref() {
-                quote! {
-                    map.next_value::<::prost_canonical_serde::CanonicalEnumMap<#path, #ty>>()?.0
-                }
-            } else {
-                quote! {
-                    map.next_value::<::prost_canonical_serde::CanonicalMap<#ty>>()?.0
-                }
-            };
+        Kind::Vec(_) | Kind::Map(_, _, _) => {
+            let wrapper_ty = container_wrapper_ty(&field.kind, ty, ident)?;
+            let next_value = next_value_expr(&wrapper_ty, &json_name);
             Ok(quote! {
                 #match_pat => {
-                    #ident = #value_expr;
+                    #ident = #next_value.0;
                 }
             })
         }
         Kind::Enum(path) => {
             let path = field.enum_path.as_ref().unwrap_or(path);
+            let next_value = next_value_expr(
+                &quote! { ::prost_canonical_serde::CanonicalEnumOption<#path> },
+                &json_name,
+            );
             Ok(quote! {
                 #match_pat => {
-                    if let Some(value) = map
-                        .next_value::<::prost_canonical_serde::CanonicalEnumOption<#path>>()?
-                        .0
-                    {
-                        #ident = value;
+                    if let Some(#value) = #next_value.0 {
+                        #ident = #value;
+                    } else {
+                        #ident = #default;
                     }
                 }
             })
         }
-        _ => Ok(quote! {
-            #match_pat => {
-                if let Some(value) = map
-                    .next_value::<::prost_canonical_serde::CanonicalOption<#ty>>()?
-                    .0
+        Kind::Bytes if field.accept_byte_array => {
+            let next_value = next_value_expr(
+                &quote! {
+                    ::core::option::Option<::prost_canonical_serde::CanonicalBytesOrArray>
+                },
+                &json_name,
+            );
+            Ok(quote! {
+                #match_pat => {
+                    if let Some(#value) = #next_value {
+                        #ident = #value.0;
+                    } else {
+                        #ident = #default;
+                    }
+                }
+            })
+        }
+        Kind::Message if !is_prost_value_type(ty) => {
+            let next_value = next_value_expr(
+                &quote! { ::prost_canonical_serde::CanonicalOption<#ty> },
+                &json_name,
+            );
+            Ok(quote! {
+                #match_pat => {
+                    if #merge_target.is_some() {
+                        if !map.next_value_seed(::prost_canonical_serde::MergeSeed::field(
+                            &mut #ident,
+                            #json_name,
+                        ))? {
+                            #ident = #default;
+                        }
+                    } else if let Some(#value) = #next_value.0 {
+                        #ident = #value;
+                    } else {
+                        #ident = #default;
+                    }
+                }
+            })
+        }
+        _ => {
+            let next_value = next_value_expr(
+                &quote! { ::prost_canonical_serde::CanonicalOption<#ty> },
+                &json_name,
+            );
+            Ok(quote! {
+                #match_pat => {
+                    if let Some(#value) = #next_value.0 {
+                        #ident = #value;
+                    } else {
+                        #ident = #default;
+                    }
+                }
+            })
+        }
+    }
+}
+
+/// Builds the match arm reading a field encoded `with` a custom module.
+fn with_match_arm(
+    field: &FieldInfo,
+    input: &DeriveInput,
+    with: &Path,
+    match_pat: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let ident = &field.ident;
+    let json_name = LitStr::new(&field.json_name, ident.span());
+    let ty = &field.ty;
+    let value = Ident::new("value", proc_macro2::Span::mixed_site());
+    let default = field_default_expr(field);
+    let name = &input.ident;
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    let mut de_generics = input.generics.clone();
+    de_generics.params.insert(0, syn::parse_quote!('de));
+    let (impl_generics, de_ty_generics, where_clause) = de_generics.split_for_impl();
+    // `null` resets the field to its default, as for canonical fields.
+    let next_value = next_value_expr(
+        &quote! { ::core::option::Option<DeserializeWith #de_ty_generics> },
+        &json_name,
+    );
+    quote! {
+        #match_pat => {
+            struct DeserializeWith #impl_generics #where_clause {
+                value: #ty,
+                phantom: ::core::marker::PhantomData<fn() -> (#name #ty_generics, &'de ())>,
+            }
+
+            impl #impl_generics ::serde::Deserialize<'de> for DeserializeWith #de_ty_generics #where_clause {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: ::serde::Deserializer<'de>,
                 {
-                    #ident = value;
+                    #with::deserialize(deserializer).map(|value| DeserializeWith {
+                        value,
+                        phantom: ::core::marker::PhantomData,
+                    })
                 }
             }
-        }),
+
+            if let Some(#value) = #next_value {
+                #ident = #value.value;
+            } else {
+                #ident = #default;
+            }
+        }
+    }
+}
+
+/// Returns the deserialization wrapper for a repeated or map field type.
+fn container_wrapper_ty(
+    kind: &Kind,
+    ty: &Type,
+    ident: &Ident,
+) -> syn::Result<proc_macro2::TokenStream> {
+    match kind {
+        Kind::Vec(inner) => {
+            if let Kind::Enum(path) = inner.as_ref() {
+                return Ok(quote! { ::prost_canonical_serde::CanonicalEnumVec<#path> });
+            }
+            let inner_ty = extract_generic(ty, "Vec", 0)
+                .ok_or_else(|| syn::Error::new(ident.span(), "missing Vec inner type"))?;
+            Ok(quote! { ::prost_canonical_serde::CanonicalVec<#inner_ty> })
+        }
+        Kind::Map(_, _, value_kind) => {
+            if let Kind::Enum(path) = value_kind.as_ref() {
+                Ok(quote! { ::prost_canonical_serde::CanonicalEnumMap<#path, #ty> })
+            } else {
+                Ok(quote! { ::prost_canonical_serde::CanonicalMap<#ty> })
+            }
+        }
+        _ => Err(syn::Error::new(
+            ident.span(),
+            "expected a repeated or map field type",
+        )),
+    }
+}
+
+/// Reads the next map value, naming the field in the errors its visitors
+/// raise.
+fn next_value_expr(
+    wrapper_ty: &proc_macro2::TokenStream,
+    json_name: &LitStr,
+) -> proc_macro2::TokenStream {
+    quote! {
+        map.next_value_seed(::prost_canonical_serde::PathSeed::<#wrapper_ty>::field(#json_name))?
     }
 }
 
@@ -690,11 +1776,49 @@ fn serialize_value_expr(
     }
 }
 
-fn map_new_expr(kind: &MapKind) -> proc_macro2::TokenStream {
-    match kind {
-        MapKind::Hash => quote! { ::std::collections::HashMap::new() },
-        MapKind::BTree => quote! { ::alloc::collections::BTreeMap::new() },
+/// Emits a compile-time check that the `bytes-base64` feature is enabled
+/// when `kind` contains a `bytes` value.
+fn bytes_guard(kind: &Kind, ty: &Type) -> Option<proc_macro2::TokenStream> {
+    fn uses_bytes(kind: &Kind) -> bool {
+        match kind {
+            Kind::Bytes | Kind::ByteArray(_) => true,
+            Kind::Vec(inner) | Kind::Map(_, _, inner) | Kind::Option(inner) => uses_bytes(inner),
+            _ => false,
+        }
+    }
+
+    uses_bytes(kind).then(|| {
+        quote::quote_spanned! {ty.span()=>
+            ::prost_canonical_serde::__require_bytes_base64!();
+        }
+    })
+}
+
+/// Emits a compile-time check that the `any` feature is enabled when `ty`
+/// mentions `prost_types::Any`, including inside `Option`, `Vec`, or a map.
+fn any_guard(ty: &Type) -> Option<proc_macro2::TokenStream> {
+    fn mentions_any(ty: &Type) -> bool {
+        let Type::Path(path) = ty else { return false };
+        let segments = &path.path.segments;
+        if segments.last().is_some_and(|seg| seg.ident == "Any")
+            && segments.iter().any(|seg| seg.ident == "prost_types")
+        {
+            return true;
+        }
+        segments.iter().any(|seg| match &seg.arguments {
+            syn::PathArguments::AngleBracketed(args) => args.args.iter().any(|arg| match arg {
+                syn::GenericArgument::Type(ty) => mentions_any(ty),
+                _ => false,
+            }),
+            _ => false,
+        })
     }
+
+    mentions_any(ty).then(|| {
+        quote::quote_spanned! {ty.span()=>
+            ::prost_canonical_serde::__require_any!();
+        }
+    })
 }
 
 fn default_value_expr(kind: &Kind) -> proc_macro2::TokenStream {
@@ -703,16 +1827,34 @@ fn default_value_expr(kind: &Kind) -> proc_macro2::TokenStream {
         Kind::Scalar(ScalarKind::I32 | ScalarKind::U32 | ScalarKind::I64 | ScalarKind::U64)
         | Kind::Enum(_) => quote! { 0 },
         Kind::Scalar(ScalarKind::F32 | ScalarKind::F64) => quote! { 0.0 },
-        Kind::Scalar(ScalarKind::String) => quote! { ::alloc::string::String::new() },
-        Kind::Bytes | Kind::Vec(_) => quote! { ::alloc::vec::Vec::new() },
-        Kind::Map(map_kind, _, _) => map_new_expr(map_kind),
+        Kind::Scalar(ScalarKind::String) => {
+            quote! { ::prost_canonical_serde::__alloc::string::String::new() }
+        }
+        Kind::Bytes | Kind::Vec(_) => quote! { ::prost_canonical_serde::__alloc::vec::Vec::new() },
+        // `Default` is only implemented for arrays of up to 32 elements.
+        Kind::ByteArray(len) => quote! { [0u8; #len] },
         Kind::Timestamp => quote! { ::prost_types::Timestamp::default() },
         Kind::Duration => quote! { ::prost_types::Duration::default() },
-        Kind::Message => quote! { ::core::default::Default::default() },
+        // `Default` rather than `new`, so maps with any hasher work.
+        Kind::Map(_, _, _) | Kind::Message => quote! { ::core::default::Default::default() },
+        Kind::Phantom => quote! { ::core::marker::PhantomData },
         Kind::Option(_) => quote! { None },
     }
 }
 
+/// Returns whether a field holding `field` is written: it must differ from
+/// its `default` attribute when one is set, so the value reads back the same,
+/// and from the protobuf zero value otherwise.
+fn presence_check_expr(
+    field: &FieldInfo,
+    value: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    match &field.default {
+        Some(default) if !matches!(field.kind, Kind::Option(_)) => quote! { #value != #default },
+        _ => default_check_expr(&field.kind, value),
+    }
+}
+
 fn default_check_expr(kind: &Kind, field: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
     match kind {
         Kind::Scalar(ScalarKind::Bool) => quote! { #field },
@@ -722,7 +1864,9 @@ fn default_check_expr(kind: &Kind, field: &proc_macro2::TokenStream) -> proc_mac
         Kind::Scalar(ScalarKind::String) | Kind::Bytes | Kind::Vec(_) | Kind::Map(_, _, _) => {
             quote! { !#field.is_empty() }
         }
-        Kind::Timestamp | Kind::Duration | Kind::Message => quote! { true },
+        // A fixed-width array is never empty, so it is always written.
+        Kind::ByteArray(_) | Kind::Timestamp | Kind::Duration | Kind::Message => quote! { true },
+        Kind::Phantom => quote! { false },
         Kind::Option(_) => quote! { #field.is_some() },
     }
 }
@@ -739,10 +1883,25 @@ fn is_prost_value_type(ty: &Type) -> bool {
         .any(|seg| seg.ident == "prost_types")
 }
 
+/// Returns the wrapped type of a single-field tuple struct, whose canonical
+/// impls forward to that field.
+fn newtype_field(fields: &Fields) -> syn::Result<Option<&Type>> {
+    match fields {
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => Ok(Some(&unnamed.unnamed[0].ty)),
+        Fields::Unnamed(_) => Err(syn::Error::new(
+            fields.span(),
+            "tuple structs must have exactly one field",
+        )),
+        Fields::Named(_) | Fields::Unit => Ok(None),
+    }
+}
+
 fn extract_fields(fields: &Fields) -> syn::Result<Vec<FieldInfo>> {
     match fields {
         Fields::Named(named) => named.named.iter().map(FieldInfo::from_field).collect(),
-        Fields::Unnamed(_) | Fields::Unit => Err(syn::Error::new(
+        // A unit struct is a message without fields, written as `{}`.
+        Fields::Unit => Ok(Vec::new()),
+        Fields::Unnamed(_) => Err(syn::Error::new(
             fields.span(),
             "CanonicalSerialize requires named fields",
         )),
@@ -849,10 +2008,24 @@ fn is_oneof_enum(data: &syn::DataEnum) -> bool {
 }
 
 fn classify_type(ty: &Type) -> syn::Result<Kind> {
+    if is_phantom_data(ty) {
+        return Ok(Kind::Phantom);
+    }
+
     if let Some(inner) = extract_generic(ty, "Option", 0) {
         return Ok(Kind::Option(Box::new(classify_type(inner)?)));
     }
 
+    // prost boxes recursive message fields, either as `Option<Box<T>>` or,
+    // in hand-written code, as `Box<Option<T>>`.
+    if let Some(inner) = extract_generic(ty, "Box", 0) {
+        let kind = classify_type(inner)?;
+        if let Kind::Option(_) | Kind::Timestamp | Kind::Duration = kind {
+            return Ok(kind);
+        }
+        return Ok(Kind::Message);
+    }
+
     if let Some(inner) = extract_generic(ty, "Vec", 0) {
         if is_u8(inner) {
             return Ok(Kind::Bytes);
@@ -860,6 +2033,12 @@ fn classify_type(ty: &Type) -> syn::Result<Kind> {
         return Ok(Kind::Vec(Box::new(classify_type(inner)?)));
     }
 
+    if let Type::Array(array) = ty {
+        if is_u8(&array.elem) {
+            return Ok(Kind::ByteArray(Box::new(array.len.clone())));
+        }
+    }
+
     if let Some((map_kind, key, value)) = extract_map_types(ty) {
         let key_kind = classify_key(key)?;
         let value_kind = classify_type(value)?;
@@ -919,8 +2098,21 @@ fn classify_key(ty: &Type) -> syn::Result<KeyKind> {
     if is_u64(ty) {
         return Ok(KeyKind::U64);
     }
+    if path_ends_with_ident(ty, "i128") {
+        return Ok(KeyKind::I128);
+    }
+    if path_ends_with_ident(ty, "u128") {
+        return Ok(KeyKind::U128);
+    }
 
-    Err(syn::Error::new(ty.span(), "unsupported map key type"))
+    let key_ty = quote!(#ty).to_string();
+    Err(syn::Error::new(
+        ty.span(),
+        format!(
+            "unsupported map key type `{key_ty}`: protobuf map keys must be string, bool, or \
+             integer types (float and message keys are not valid)"
+        ),
+    ))
 }
 
 fn apply_enum(kind: Kind, enum_path: Path) -> Kind {
@@ -932,6 +2124,9 @@ fn apply_enum(kind: Kind, enum_path: Path) -> Kind {
         },
         Kind::Option(inner) => match *inner {
             Kind::Scalar(ScalarKind::I32) => Kind::Option(Box::new(Kind::Enum(enum_path))),
+            other @ (Kind::Vec(_) | Kind::Map(_, _, _)) => {
+                Kind::Option(Box::new(apply_enum(other, enum_path)))
+            }
             other => Kind::Option(Box::new(other)),
         },
         Kind::Map(map_kind, key_kind, value_kind) => match *value_kind {
@@ -972,6 +2167,8 @@ fn extract_map_types(ty: &Type) -> Option<(MapKind, &Type, &Type)> {
         MapKind::Hash
     } else if segment.ident == "BTreeMap" {
         MapKind::BTree
+    } else if segment.ident == "IndexMap" {
+        MapKind::Index
     } else {
         return None;
     };
@@ -1025,6 +2222,10 @@ fn is_string(ty: &Type) -> bool {
     path_ends_with_ident(ty, "String")
 }
 
+fn is_phantom_data(ty: &Type) -> bool {
+    path_ends_with_ident(ty, "PhantomData")
+}
+
 fn is_timestamp(ty: &Type) -> bool {
     path_ends_with(ty, &["prost_types", "Timestamp"])
 }
@@ -1097,17 +2298,36 @@ fn to_json_name(name: &str) -> String {
 }
 
 #[derive(Clone)]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "The flags describe independent properties of the parsed field."
+)]
 struct FieldInfo {
     ident: Ident,
     ty: Type,
     kind: Kind,
     enum_path: Option<Path>,
     is_oneof: bool,
+    skip: bool,
+    /// `Box<Option<T>>`; the option lives behind the box.
+    boxed: bool,
+    /// An `Option<Timestamp>` encoded as epoch seconds.
+    timestamp_epoch: bool,
+    /// `bytes` that may also be given as a JSON array of numbers.
+    accept_byte_array: bool,
     json_name: String,
     proto_name: String,
     oneof_type: Option<Type>,
     option_inner: Option<Type>,
-    vec_inner: Option<Type>,
+    /// Initial value used when the field is absent from the input.
+    default: Option<syn::Expr>,
+    /// Module whose `serialize`/`deserialize` replace the canonical encoding.
+    with: Option<Path>,
+    /// The message type whose fields are inlined into the parent.
+    flatten: Option<Type>,
+    /// The message type of an `Option` message field that is left out when
+    /// it would serialize as `{}`.
+    omit_empty_message: Option<Type>,
 }
 
 impl FieldInfo {
@@ -1117,25 +2337,119 @@ impl FieldInfo {
             .clone()
             .ok_or_else(|| syn::Error::new(field.span(), "expected named field"))?;
         let (is_oneof, enum_path) = parse_prost_attrs(&field.attrs)?;
-        let (proto_name_attr, json_name_attr) = parse_canonical_attrs(&field.attrs)?;
-        let mut kind = classify_type(&field.ty)?;
+        let attrs = parse_canonical_attrs(&field.attrs, AttrTarget::Field)?;
+        let mut kind = classify_type(&field.ty)
+            .map_err(|err| syn::Error::new(err.span(), format!("field `{ident}`: {err}")))?;
         let mut oneof_type = None;
-        let option_inner = extract_generic(&field.ty, "Option", 0).cloned();
-        let vec_inner = extract_generic(&field.ty, "Vec", 0).cloned();
+        let boxed_option = extract_generic(&field.ty, "Box", 0)
+            .filter(|inner| extract_generic(inner, "Option", 0).is_some());
+        let option_inner = extract_generic(boxed_option.unwrap_or(&field.ty), "Option", 0).cloned();
 
         if let Some(enum_path) = enum_path.clone() {
             kind = apply_enum(kind, enum_path);
         }
 
         if is_oneof {
+            if let Some(default) = &attrs.default {
+                return Err(syn::Error::new(
+                    default.span(),
+                    "`default` is not supported on oneof fields",
+                ));
+            }
+            if let Some(with) = &attrs.with {
+                return Err(syn::Error::new(
+                    with.span(),
+                    "`with` is not supported on oneof fields",
+                ));
+            }
             if let Some(inner) = extract_generic(&field.ty, "Option", 0) {
-                oneof_type = Some(inner.clone());
+                oneof_type = Some(attrs.oneof_type.clone().unwrap_or_else(|| inner.clone()));
                 kind = Kind::Option(Box::new(Kind::Message));
             }
+        } else if let Some(oneof_type) = &attrs.oneof_type {
+            return Err(syn::Error::new(
+                oneof_type.span(),
+                "`oneof_type` requires a `#[prost(oneof = \"...\")]` field",
+            ));
         }
 
-        let proto_name = proto_name_attr.unwrap_or_else(|| ident.to_string());
-        let json_name = json_name_attr.unwrap_or_else(|| to_json_name(&proto_name));
+        let skip = attrs.skip || matches!(kind, Kind::Phantom);
+        let accept_byte_array = attrs.accept_byte_array;
+        if accept_byte_array
+            && !matches!(&kind, Kind::Bytes)
+            && !matches!(&kind, Kind::Option(inner) if matches!(inner.as_ref(), Kind::Bytes))
+        {
+            return Err(syn::Error::new(
+                field.ty.span(),
+                "`accept_byte_array` requires a `Vec<u8>` or `Option<Vec<u8>>` field",
+            ));
+        }
+        let timestamp_epoch = attrs.timestamp_epoch;
+        if timestamp_epoch
+            && !matches!(&kind, Kind::Option(inner) if matches!(inner.as_ref(), Kind::Timestamp))
+        {
+            return Err(syn::Error::new(
+                field.ty.span(),
+                "`timestamp_epoch` requires an `Option<Timestamp>` field",
+            ));
+        }
+        let flatten = if attrs.flatten {
+            let message_ty = match &kind {
+                Kind::Message if !is_oneof => Some(&field.ty),
+                Kind::Option(inner)
+                    if !is_oneof
+                        && boxed_option.is_none()
+                        && matches!(inner.as_ref(), Kind::Message) =>
+                {
+                    option_inner.as_ref()
+                }
+                _ => None,
+            };
+            let Some(message_ty) = message_ty else {
+                return Err(syn::Error::new(
+                    field.ty.span(),
+                    "`flatten` requires a message or `Option` message field",
+                ));
+            };
+            if let Some(with) = &attrs.with {
+                return Err(syn::Error::new(
+                    with.span(),
+                    "`with` cannot be combined with `flatten`",
+                ));
+            }
+            Some(message_ty.clone())
+        } else {
+            None
+        };
+        let omit_empty_message = if attrs.omit_empty_message {
+            let message_ty = match &kind {
+                Kind::Option(inner) if !is_oneof && matches!(inner.as_ref(), Kind::Message) => {
+                    option_inner.as_ref()
+                }
+                _ => None,
+            };
+            let Some(message_ty) = message_ty else {
+                return Err(syn::Error::new(
+                    field.ty.span(),
+                    "`omit_empty_message` requires an `Option` message field",
+                ));
+            };
+            if attrs.flatten || attrs.with.is_some() {
+                return Err(syn::Error::new(
+                    field.ty.span(),
+                    "`omit_empty_message` cannot be combined with `flatten` or `with`",
+                ));
+            }
+            Some(
+                extract_generic(message_ty, "Box", 0)
+                    .unwrap_or(message_ty)
+                    .clone(),
+            )
+        } else {
+            None
+        };
+        let proto_name = attrs.proto_name.unwrap_or_else(|| ident.to_string());
+        let json_name = attrs.json_name.unwrap_or_else(|| to_json_name(&proto_name));
 
         Ok(Self {
             ident,
@@ -1143,18 +2457,75 @@ impl FieldInfo {
             kind,
             enum_path,
             is_oneof,
+            skip,
+            boxed: boxed_option.is_some(),
+            timestamp_epoch,
+            accept_byte_array,
             json_name,
             proto_name,
             oneof_type,
             option_inner,
-            vec_inner,
+            default: attrs.default,
+            with: attrs.with,
+            flatten,
+            omit_empty_message,
         })
     }
 }
 
-fn parse_canonical_attrs(attrs: &[Attribute]) -> syn::Result<(Option<String>, Option<String>)> {
-    let mut proto_name = None;
-    let mut json_name = None;
+/// Options parsed from `#[prost_canonical_serde(...)]` attributes.
+#[derive(Default)]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "Each flag mirrors a bare attribute flag such as `skip`."
+)]
+struct CanonicalAttrs {
+    proto_name: Option<String>,
+    json_name: Option<String>,
+    skip: bool,
+    allow_quoted_enum_numbers: bool,
+    enum_numbers: bool,
+    message_name: Option<String>,
+    oneof_type: Option<Type>,
+    default: Option<syn::Expr>,
+    with: Option<Path>,
+    flatten: bool,
+    try_from_value: bool,
+    canonical_fields: bool,
+    timestamp_epoch: bool,
+    accept_byte_array: bool,
+    omit_empty_message: bool,
+    aliases: Vec<(String, i32)>,
+}
+
+/// The item a `#[prost_canonical_serde(...)]` attribute is written on.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AttrTarget {
+    Message,
+    Enum,
+    EnumValue,
+    Oneof,
+    Field,
+    OneofMember,
+}
+
+impl AttrTarget {
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Message => "a message",
+            Self::Enum => "an enum",
+            Self::EnumValue => "an enum value",
+            Self::Oneof => "a oneof enum",
+            Self::Field => "a field",
+            Self::OneofMember => "a oneof member",
+        }
+    }
+}
+
+fn parse_canonical_attrs(attrs: &[Attribute], target: AttrTarget) -> syn::Result<CanonicalAttrs> {
+    use AttrTarget::{Enum, Field, Message, OneofMember};
+
+    let mut parsed = CanonicalAttrs::default();
 
     for attr in attrs {
         if !attr.path().is_ident("prost_canonical_serde") {
@@ -1162,24 +2533,124 @@ fn parse_canonical_attrs(attrs: &[Attribute]) -> syn::Result<(Option<String>, Op
         }
 
         attr.parse_nested_meta(|meta| {
+            let applies = |allowed: &[AttrTarget]| {
+                if allowed.contains(&target) {
+                    return Ok(());
+                }
+                let key = meta.path.get_ident().map(ToString::to_string);
+                Err(meta.error(format_args!(
+                    "`{}` is not supported on {}",
+                    key.unwrap_or_default(),
+                    target.describe()
+                )))
+            };
             if meta.path.is_ident("proto_name") {
-                let value: LitStr = meta.value()?.parse()?;
-                proto_name = Some(value.value());
+                applies(&[Field, OneofMember])?;
+                parsed.proto_name = Some(parse_name(&meta, "proto_name")?);
             } else if meta.path.is_ident("json_name") {
+                applies(&[Field, OneofMember])?;
+                parsed.json_name = Some(parse_name(&meta, "json_name")?);
+            } else if meta.path.is_ident("skip") {
+                applies(&[Field])?;
+                parsed.skip = true;
+            } else if meta.path.is_ident("allow_quoted_enum_numbers") {
+                applies(&[Enum])?;
+                parsed.allow_quoted_enum_numbers = true;
+            } else if meta.path.is_ident("try_from_value") {
+                applies(&[Message])?;
+                parsed.try_from_value = true;
+            } else if meta.path.is_ident("canonical_fields") {
+                applies(&[Message])?;
+                parsed.canonical_fields = true;
+            } else if meta.path.is_ident("flatten") {
+                applies(&[Field])?;
+                parsed.flatten = true;
+            } else if meta.path.is_ident("enum_numbers") {
+                applies(&[Enum])?;
+                parsed.enum_numbers = true;
+            } else if meta.path.is_ident("accept_byte_array") {
+                applies(&[Field])?;
+                parsed.accept_byte_array = true;
+            } else if meta.path.is_ident("omit_empty_message") {
+                applies(&[Field])?;
+                parsed.omit_empty_message = true;
+            } else if meta.path.is_ident("timestamp_epoch") {
+                applies(&[Field])?;
+                parsed.timestamp_epoch = true;
+            } else if meta.path.is_ident("message_name") {
+                applies(&[Message])?;
+                parsed.message_name = Some(parse_name(&meta, "message_name")?);
+            } else if meta.path.is_ident("default") {
+                applies(&[Field])?;
+                let value: LitStr = meta.value()?.parse()?;
+                parsed.default = Some(value.parse()?);
+            } else if meta.path.is_ident("oneof_type") {
+                applies(&[Field])?;
                 let value: LitStr = meta.value()?.parse()?;
-                json_name = Some(value.value());
+                parsed.oneof_type = Some(value.parse()?);
+            } else if meta.path.is_ident("with") {
+                applies(&[Field])?;
+                let value: LitStr = meta.value()?.parse()?;
+                parsed.with = Some(value.parse()?);
+            } else if meta.path.is_ident("alias") {
+                applies(&[Enum])?;
+                parsed.aliases.push(parse_alias(&meta)?);
+            } else {
+                return Err(meta.error("unknown `prost_canonical_serde` attribute"));
             }
             Ok(())
         })?;
     }
 
-    Ok((proto_name, json_name))
+    Ok(parsed)
+}
+
+/// Parses a `name = "..."` attribute value, rejecting empty names.
+fn parse_name(meta: &syn::meta::ParseNestedMeta<'_>, attr: &str) -> syn::Result<String> {
+    let value: LitStr = meta.value()?.parse()?;
+    if value.value().is_empty() {
+        return Err(syn::Error::new(
+            value.span(),
+            format!("`{attr}` must not be empty"),
+        ));
+    }
+    Ok(value.value())
+}
+
+/// Parses an `alias(name = "...", number = N)` attribute.
+fn parse_alias(meta: &syn::meta::ParseNestedMeta<'_>) -> syn::Result<(String, i32)> {
+    let mut name = None;
+    let mut number = None;
+    meta.parse_nested_meta(|inner| {
+        if inner.path.is_ident("name") {
+            name = Some(parse_name(&inner, "name")?);
+        } else if inner.path.is_ident("number") {
+            let input = inner.value()?;
+            let negative = input.parse::<Option<syn::Token![-]>>()?.is_some();
+            let value: syn::LitInt = input.parse()?;
+            let magnitude: i64 = value.base10_parse()?;
+            let signed = if negative { -magnitude } else { magnitude };
+            number =
+                Some(i32::try_from(signed).map_err(|_| {
+                    syn::Error::new(value.span(), "enum numbers must fit in an i32")
+                })?);
+        } else {
+            return Err(inner.error("expected `name` or `number`"));
+        }
+        Ok(())
+    })?;
+    match (name, number) {
+        (Some(name), Some(number)) => Ok((name, number)),
+        _ => Err(meta.error("`alias` requires both `name` and `number`")),
+    }
 }
 
 #[derive(Clone)]
 enum Kind {
     Scalar(ScalarKind),
     Bytes,
+    /// Fixed-width `[u8; N]` bytes, holding the array length.
+    ByteArray(Box<syn::Expr>),
     Vec(Box<Kind>),
     Map(MapKind, KeyKind, Box<Kind>),
     Option(Box<Kind>),
@@ -1187,6 +2658,8 @@ enum Kind {
     Timestamp,
     Duration,
     Message,
+    /// `PhantomData` markers, which are never part of the JSON.
+    Phantom,
 }
 
 #[derive(Clone)]
@@ -1209,10 +2682,14 @@ enum KeyKind {
     I64,
     U32,
     U64,
+    /// Not a protobuf key type; accepted for hand-written messages.
+    I128,
+    U128,
 }
 
 #[derive(Clone)]
 enum MapKind {
     Hash,
     BTree,
+    Index,
 }
@@ -0,0 +1,38 @@
+//! Deserialization throughput for a message with a large map field.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use prost_canonical_serde_example::KitchenSink;
+use std::hint::black_box;
+
+fn large_map_message() -> KitchenSink {
+    KitchenSink {
+        string_to_int: (0..10_000)
+            .map(|index| (format!("key{index}"), index))
+            .collect(),
+        int_to_string: (0..10_000)
+            .map(|index| (index, format!("value{index}")))
+            .collect(),
+        ..KitchenSink::default()
+    }
+}
+
+fn deserialize_large_map(c: &mut Criterion) {
+    let message = large_map_message();
+    let json = serde_json::to_string(&message).expect("serialize");
+    let value = serde_json::to_value(&message).expect("serialize");
+
+    let mut group = c.benchmark_group("large_map");
+    group.bench_function("from_str", |b| {
+        b.iter(|| serde_json::from_str::<KitchenSink>(black_box(&json)).expect("deserialize"));
+    });
+    // `serde_json::Value` maps report their length, so map fields are reserved up front.
+    group.bench_function("from_value", |b| {
+        b.iter(|| {
+            serde_json::from_value::<KitchenSink>(black_box(value.clone())).expect("deserialize")
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, deserialize_large_map);
+criterion_main!(benches);
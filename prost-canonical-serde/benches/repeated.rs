@@ -0,0 +1,44 @@
+//! Deserialization throughput for large repeated scalar fields.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use prost_canonical_serde::{CanonicalSeq, CanonicalVec};
+use serde::de::DeserializeOwned;
+use std::hint::black_box;
+
+const LEN: u32 = 100_000;
+
+fn bench_repeated<T>(c: &mut Criterion, name: &str, values: &[T])
+where
+    T: prost_canonical_serde::CanonicalSerialize,
+    CanonicalVec<T>: DeserializeOwned,
+{
+    let json = serde_json::to_string(&CanonicalSeq::new(values)).expect("serialize");
+    let value = serde_json::to_value(CanonicalSeq::new(values)).expect("serialize");
+
+    let mut group = c.benchmark_group(name);
+    group.bench_function("from_str", |b| {
+        b.iter(|| serde_json::from_str::<CanonicalVec<T>>(black_box(&json)).expect("deserialize"));
+    });
+    // `serde_json::Value` arrays report their length, so the vector is reserved up front.
+    group.bench_function("from_value", |b| {
+        b.iter(|| {
+            serde_json::from_value::<CanonicalVec<T>>(black_box(value.clone()))
+                .expect("deserialize")
+        });
+    });
+    group.finish();
+}
+
+fn deserialize_repeated(c: &mut Criterion) {
+    let int64s: Vec<i64> = (0..LEN).map(|index| i64::from(index) * 1_000_003).collect();
+    bench_repeated(c, "repeated_int64", &int64s);
+
+    let doubles: Vec<f64> = (0..LEN).map(|index| f64::from(index) / 7.0).collect();
+    bench_repeated(c, "repeated_double", &doubles);
+
+    let bytes: Vec<Vec<u8>> = (0..LEN).map(|index| index.to_le_bytes().to_vec()).collect();
+    bench_repeated(c, "repeated_bytes", &bytes);
+}
+
+criterion_group!(benches, deserialize_repeated);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+//! Deserialization throughput for messages dominated by string-encoded fields.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use prost_canonical_serde_example::demo::Example;
+use prost_canonical_serde_example::{KitchenSink, Nested};
+use prost_types::Timestamp;
+use std::hint::black_box;
+
+fn string_heavy_messages() -> Vec<Example> {
+    (0..10_000)
+        .map(|index| Example {
+            name: format!("example number {index}"),
+            count: index,
+            payload: format!("payload bytes for {index}").into_bytes(),
+            created_at: Some(Timestamp {
+                seconds: 1_700_000_000 + index,
+                nanos: 123_456_000,
+            }),
+        })
+        .collect()
+}
+
+fn string_fields_message() -> KitchenSink {
+    KitchenSink {
+        repeated_nested: (0..10_000)
+            .map(|id| Nested {
+                id,
+                note: format!("a reasonably long note for nested message {id}"),
+            })
+            .collect(),
+        ..KitchenSink::default()
+    }
+}
+
+fn deserialize_strings(c: &mut Criterion) {
+    let examples = serde_json::to_string(&string_heavy_messages()).expect("serialize");
+    let nested = serde_json::to_string(&string_fields_message()).expect("serialize");
+
+    let mut group = c.benchmark_group("strings");
+    // Bytes and timestamps are parsed straight from the borrowed input.
+    group.bench_function("parsed_from_str", |b| {
+        b.iter(|| serde_json::from_str::<Vec<Example>>(black_box(&examples)).expect("deserialize"));
+    });
+    // `string` fields still need one allocation each for the owned result.
+    group.bench_function("string_fields", |b| {
+        b.iter(|| serde_json::from_str::<KitchenSink>(black_box(&nested)).expect("deserialize"));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, deserialize_strings);
+criterion_main!(benches);
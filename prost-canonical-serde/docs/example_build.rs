@@ -2,16 +2,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("cargo:rerun-if-changed=proto/example.proto");
     println!("cargo:rerun-if-changed=proto/kitchen_sink.proto");
     let mut config = prost_build::Config::new();
-    config.type_attribute(
-        ".",
-        "#[derive(::prost_canonical_serde::CanonicalSerialize, ::prost_canonical_serde::CanonicalDeserialize)]",
-    );
 
     let fds = config.load_fds(
         &["proto/example.proto", "proto/kitchen_sink.proto"],
         &["proto"],
     )?;
+    prost_canonical_serde_build::add_canonical_derives(&mut config, &fds);
     prost_canonical_serde_build::add_json_name_attributes(&mut config, &fds);
+    // Opts a message into `CanonicalFields` and `canonical_field_names()`.
+    config.message_attribute(
+        ".kitchen_sink.KitchenSink",
+        "#[prost_canonical_serde(canonical_fields)]",
+    );
     config.out_dir("src");
     config.compile_fds(fds)?;
 
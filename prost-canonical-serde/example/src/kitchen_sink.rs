@@ -16,6 +16,7 @@ pub struct Nested {
     ::prost_canonical_serde::CanonicalSerialize,
     ::prost_canonical_serde::CanonicalDeserialize
 )]
+#[prost_canonical_serde(canonical_fields)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct KitchenSink {
     #[prost(int32, tag = "1")]
@@ -66,6 +67,9 @@ pub struct KitchenSink {
     #[prost(int32, optional, tag = "17")]
     #[prost_canonical_serde(proto_name = "optional_int32", json_name = "optionalInt32")]
     pub optional_int32: ::core::option::Option<i32>,
+    #[prost(enumeration = "Status", repeated, tag = "18")]
+    #[prost_canonical_serde(proto_name = "statuses", json_name = "statuses")]
+    pub statuses: ::prost::alloc::vec::Vec<i32>,
     #[prost(oneof = "kitchen_sink::Choice", tags = "14, 15")]
     pub choice: ::core::option::Option<kitchen_sink::Choice>,
 }
@@ -92,6 +96,31 @@ pub mod kitchen_sink {
     ::prost_canonical_serde::CanonicalSerialize,
     ::prost_canonical_serde::CanonicalDeserialize
 )]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ScalarFlavors {
+    #[prost(fixed32, tag = "1")]
+    #[prost_canonical_serde(proto_name = "fixed32_field", json_name = "fixed32Field")]
+    pub fixed32_field: u32,
+    #[prost(fixed64, tag = "2")]
+    #[prost_canonical_serde(proto_name = "fixed64_field", json_name = "fixed64Field")]
+    pub fixed64_field: u64,
+    #[prost(sfixed32, tag = "3")]
+    #[prost_canonical_serde(proto_name = "sfixed32_field", json_name = "sfixed32Field")]
+    pub sfixed32_field: i32,
+    #[prost(sfixed64, tag = "4")]
+    #[prost_canonical_serde(proto_name = "sfixed64_field", json_name = "sfixed64Field")]
+    pub sfixed64_field: i64,
+    #[prost(sint32, tag = "5")]
+    #[prost_canonical_serde(proto_name = "sint32_field", json_name = "sint32Field")]
+    pub sint32_field: i32,
+    #[prost(sint64, tag = "6")]
+    #[prost_canonical_serde(proto_name = "sint64_field", json_name = "sint64Field")]
+    pub sint64_field: i64,
+}
+#[derive(
+    ::prost_canonical_serde::CanonicalSerialize,
+    ::prost_canonical_serde::CanonicalDeserialize
+)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum Status {
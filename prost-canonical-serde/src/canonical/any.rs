@@ -0,0 +1,263 @@
+//! `google.protobuf.Any` holding a well-known type.
+//!
+//! Any other payload would need a registry from type URLs to message types,
+//! so it is reported as an error instead.
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use prost::Message;
+use serde::ser::SerializeMap;
+use serde::{Deserializer, Serializer, de, ser};
+
+use super::PathSeed;
+use super::wrappers::{Canonical, CanonicalValue};
+use crate::{CanonicalDeserialize, CanonicalSerialize};
+
+/// Expands to a `match` on a well-known type's full name that evaluates
+/// `$body` with `$ty` naming the prost type used for it.
+macro_rules! match_well_known {
+    ($type_name:expr, $ty:ident => $body:expr, _ => $fallback:expr $(,)?) => {
+        match $type_name {
+            "google.protobuf.Timestamp" => {
+                type $ty = prost_types::Timestamp;
+                $body
+            }
+            "google.protobuf.Duration" => {
+                type $ty = prost_types::Duration;
+                $body
+            }
+            "google.protobuf.FieldMask" => {
+                type $ty = prost_types::FieldMask;
+                $body
+            }
+            "google.protobuf.Struct" => {
+                type $ty = prost_types::Struct;
+                $body
+            }
+            "google.protobuf.Value" => {
+                type $ty = prost_types::Value;
+                $body
+            }
+            "google.protobuf.ListValue" => {
+                type $ty = prost_types::ListValue;
+                $body
+            }
+            "google.protobuf.Empty" => {
+                type $ty = ();
+                $body
+            }
+            "google.protobuf.DoubleValue" => {
+                type $ty = f64;
+                $body
+            }
+            "google.protobuf.FloatValue" => {
+                type $ty = f32;
+                $body
+            }
+            "google.protobuf.Int64Value" => {
+                type $ty = i64;
+                $body
+            }
+            "google.protobuf.UInt64Value" => {
+                type $ty = u64;
+                $body
+            }
+            "google.protobuf.Int32Value" => {
+                type $ty = i32;
+                $body
+            }
+            "google.protobuf.UInt32Value" => {
+                type $ty = u32;
+                $body
+            }
+            "google.protobuf.BoolValue" => {
+                type $ty = bool;
+                $body
+            }
+            "google.protobuf.StringValue" => {
+                type $ty = String;
+                $body
+            }
+            #[cfg(feature = "bytes-base64")]
+            "google.protobuf.BytesValue" => {
+                type $ty = Vec<u8>;
+                $body
+            }
+            _ => $fallback,
+        }
+    };
+}
+
+/// Returns the full message name at the end of a type URL.
+fn type_name(type_url: &str) -> &str {
+    type_url.rsplit_once('/').map_or(type_url, |(_, name)| name)
+}
+
+fn unsupported(type_url: &str) -> String {
+    format!("google.protobuf.Any is only supported for well-known types, not {type_url:?}")
+}
+
+/// Written as `{"@type": ..., "value": ...}` with the payload's canonical
+/// JSON under `value`. A default `Any` is written as `{}`.
+impl CanonicalSerialize for prost_types::Any {
+    fn serialize_canonical<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.type_url.is_empty() && self.value.is_empty() {
+            return serializer.serialize_map(Some(0))?.end();
+        }
+        match_well_known!(
+            type_name(&self.type_url),
+            T => serialize_payload::<T, S>(self, serializer),
+            _ => Err(ser::Error::custom(unsupported(&self.type_url))),
+        )
+    }
+}
+
+fn serialize_payload<T, S>(any: &prost_types::Any, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Message + Default + CanonicalSerialize,
+    S: Serializer,
+{
+    let payload = T::decode(any.value.as_slice())
+        .map_err(|err| ser::Error::custom(format!("invalid {:?} payload: {err}", any.type_url)))?;
+    let mut map = serializer.serialize_map(Some(2))?;
+    map.serialize_entry("@type", &any.type_url)?;
+    map.serialize_entry("value", &Canonical::new(&payload))?;
+    map.end()
+}
+
+/// Reads `{"@type": ..., "value": ...}` for a well-known type and stores the
+/// payload's protobuf encoding. `{}` is a default `Any`, and any key besides
+/// `"@type"` and `"value"` is an error.
+///
+/// Without the `std` feature, `"@type"` must come before `"value"`; with it,
+/// an earlier `"value"` is buffered until the type is known.
+impl CanonicalDeserialize for prost_types::Any {
+    fn deserialize_canonical<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = prost_types::Any;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("google.protobuf.Any object")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                use serde::de::Error;
+
+                let mut type_url: Option<String> = None;
+                let mut value: Option<Vec<u8>> = None;
+                #[cfg(feature = "std")]
+                let mut buffered: Option<serde_json::Value> = None;
+                while let Some(key) = map.next_key::<Cow<'de, str>>()? {
+                    match key.as_ref() {
+                        "@type" => {
+                            if type_url.is_some() {
+                                return Err(A::Error::custom("duplicate \"@type\" in Any"));
+                            }
+                            let url = map.next_value::<String>()?;
+                            if !is_well_known(&url) {
+                                return Err(A::Error::custom(unsupported(&url)));
+                            }
+                            type_url = Some(url);
+                        }
+                        "value" => {
+                            let duplicate = value.is_some();
+                            #[cfg(feature = "std")]
+                            let duplicate = duplicate || buffered.is_some();
+                            if duplicate {
+                                return Err(A::Error::custom("duplicate \"value\" in Any"));
+                            }
+                            if let Some(url) = &type_url {
+                                value = Some(match_well_known!(
+                                    type_name(url),
+                                    T => read_payload::<T, A>(&mut map),
+                                    _ => Err(A::Error::custom(unsupported(url))),
+                                )?);
+                            } else {
+                                #[cfg(feature = "std")]
+                                {
+                                    buffered = Some(map.next_value()?);
+                                }
+                                #[cfg(not(feature = "std"))]
+                                return Err(A::Error::custom(
+                                    "\"@type\" must come before \"value\" in Any",
+                                ));
+                            }
+                        }
+                        // A well-known type payload sits under `value`, so
+                        // no other key can belong to it.
+                        other => return Err(A::Error::unknown_field(other, &["@type", "value"])),
+                    }
+                }
+
+                let Some(type_url) = type_url else {
+                    let has_value = value.is_some();
+                    #[cfg(feature = "std")]
+                    let has_value = has_value || buffered.is_some();
+                    if has_value {
+                        return Err(A::Error::custom("missing \"@type\" in Any"));
+                    }
+                    return Ok(prost_types::Any::default());
+                };
+                #[cfg(feature = "std")]
+                if let Some(buffered) = buffered {
+                    value = Some(
+                        match_well_known!(
+                            type_name(&type_url),
+                            T => decode_buffered::<T>(buffered),
+                            _ => Err(unsupported(&type_url)),
+                        )
+                        .map_err(A::Error::custom)?,
+                    );
+                }
+                Ok(prost_types::Any {
+                    type_url,
+                    value: value.unwrap_or_default(),
+                })
+            }
+        }
+
+        deserializer.deserialize_map(Visitor)
+    }
+}
+
+fn is_well_known(type_url: &str) -> bool {
+    match_well_known!(
+        type_name(type_url),
+        _T => true,
+        _ => false,
+    )
+}
+
+fn read_payload<'de, T, A>(map: &mut A) -> Result<Vec<u8>, A::Error>
+where
+    T: Message + CanonicalDeserialize,
+    A: de::MapAccess<'de>,
+{
+    let payload = map.next_value_seed(PathSeed::<CanonicalValue<T>>::field("value"))?;
+    Ok(payload.0.encode_to_vec())
+}
+
+#[cfg(feature = "std")]
+fn decode_buffered<T>(value: serde_json::Value) -> Result<Vec<u8>, String>
+where
+    T: Message + CanonicalDeserialize,
+{
+    super::from_canonical_value::<T>(value)
+        .map(|payload| payload.encode_to_vec())
+        .map_err(|err| alloc::string::ToString::to_string(&err))
+}
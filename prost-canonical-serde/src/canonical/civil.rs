@@ -0,0 +1,266 @@
+//! Conversions between Unix time and UTC calendar date-times.
+//!
+//! With the `chrono` feature (on by default) these delegate to chrono.
+//! Without it, a small proleptic Gregorian implementation is used instead so
+//! `Timestamp` support does not need the dependency.
+
+use super::CanonicalError;
+
+/// A UTC calendar date and time of day, without fractional seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CivilDateTime {
+    pub(crate) year: i64,
+    pub(crate) month: u32,
+    pub(crate) day: u32,
+    pub(crate) hour: u32,
+    pub(crate) minute: u32,
+    pub(crate) second: u32,
+}
+
+/// Returns the UTC date-time `seconds` after the Unix epoch.
+#[cfg(feature = "chrono")]
+pub(crate) fn from_unix_seconds(seconds: i64) -> Option<CivilDateTime> {
+    use chrono::{Datelike, TimeZone, Timelike, Utc};
+
+    let datetime = Utc.timestamp_opt(seconds, 0).single()?;
+    Some(CivilDateTime {
+        year: i64::from(datetime.year()),
+        month: datetime.month(),
+        day: datetime.day(),
+        hour: datetime.hour(),
+        minute: datetime.minute(),
+        second: datetime.second(),
+    })
+}
+
+/// Returns the UTC date-time `seconds` after the Unix epoch.
+#[cfg(not(feature = "chrono"))]
+#[expect(
+    clippy::unnecessary_wraps,
+    reason = "Keeps the signature of the chrono-backed version, which can fail."
+)]
+pub(crate) fn from_unix_seconds(seconds: i64) -> Option<CivilDateTime> {
+    Some(fallback::from_unix_seconds(seconds))
+}
+
+/// Parses an RFC 3339 date-time into Unix seconds and nanoseconds.
+#[cfg(feature = "chrono")]
+pub(crate) fn parse_rfc3339(value: &str) -> Result<(i64, u32), CanonicalError> {
+    use alloc::string::ToString;
+    use chrono::{DateTime, Timelike, Utc};
+
+    let datetime =
+        DateTime::parse_from_rfc3339(value).map_err(|err| CanonicalError::new(err.to_string()))?;
+    let utc = datetime.with_timezone(&Utc);
+    Ok((utc.timestamp(), utc.nanosecond()))
+}
+
+/// Parses an RFC 3339 date-time into Unix seconds and nanoseconds.
+#[cfg(not(feature = "chrono"))]
+pub(crate) fn parse_rfc3339(value: &str) -> Result<(i64, u32), CanonicalError> {
+    fallback::parse_rfc3339(value)
+}
+
+#[cfg(any(test, not(feature = "chrono")))]
+mod fallback {
+    use super::{CanonicalError, CivilDateTime};
+
+    const SECONDS_PER_DAY: i64 = 86_400;
+
+    /// Days since 1970-01-01 for a proleptic Gregorian date.
+    ///
+    /// This is Howard Hinnant's `days_from_civil`, which works in 400-year
+    /// eras so that every era has the same number of days.
+    fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month = i64::from(month);
+        let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    /// The inverse of [`days_from_civil`].
+    #[expect(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        reason = "Month and day are always small positive numbers."
+    )]
+    fn civil_from_days(days: i64) -> (i64, u32, u32) {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        (year, month as u32, day as u32)
+    }
+
+    fn days_in_month(year: i64, month: u32) -> u32 {
+        match month {
+            2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "The time of day is a non-negative number below 86,400."
+    )]
+    pub(super) fn from_unix_seconds(seconds: i64) -> CivilDateTime {
+        let (year, month, day) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY));
+        let time_of_day = seconds.rem_euclid(SECONDS_PER_DAY) as u32;
+        CivilDateTime {
+            year,
+            month,
+            day,
+            hour: time_of_day / 3600,
+            minute: time_of_day / 60 % 60,
+            second: time_of_day % 60,
+        }
+    }
+
+    /// Reads exactly `len` ASCII digits from the front of `input`.
+    fn take_digits(input: &mut &[u8], len: usize) -> Result<u32, CanonicalError> {
+        let digits = input
+            .get(..len)
+            .filter(|digits| digits.iter().all(u8::is_ascii_digit))
+            .ok_or_else(|| CanonicalError::new("invalid timestamp"))?;
+        *input = &input[len..];
+        Ok(digits
+            .iter()
+            .fold(0, |acc, digit| acc * 10 + u32::from(digit - b'0')))
+    }
+
+    fn expect_byte(input: &mut &[u8], accepted: &[u8]) -> Result<u8, CanonicalError> {
+        match input.split_first() {
+            Some((byte, rest)) if accepted.contains(byte) => {
+                *input = rest;
+                Ok(*byte)
+            }
+            _ => Err(CanonicalError::new("invalid timestamp")),
+        }
+    }
+
+    pub(super) fn parse_rfc3339(value: &str) -> Result<(i64, u32), CanonicalError> {
+        let mut input = value.as_bytes();
+        let year = i64::from(take_digits(&mut input, 4)?);
+        expect_byte(&mut input, b"-")?;
+        let month = take_digits(&mut input, 2)?;
+        expect_byte(&mut input, b"-")?;
+        let day = take_digits(&mut input, 2)?;
+        expect_byte(&mut input, b"Tt ")?;
+        let hour = take_digits(&mut input, 2)?;
+        expect_byte(&mut input, b":")?;
+        let minute = take_digits(&mut input, 2)?;
+        expect_byte(&mut input, b":")?;
+        let second = take_digits(&mut input, 2)?;
+        if !(1..=12).contains(&month)
+            || !(1..=days_in_month(year, month)).contains(&day)
+            || hour > 23
+            || minute > 59
+            || second > 59
+        {
+            return Err(CanonicalError::new("timestamp field out of range"));
+        }
+
+        let mut nanos = 0;
+        if expect_byte(&mut input, b".").is_ok() {
+            let len = input
+                .iter()
+                .take_while(|byte| byte.is_ascii_digit())
+                .count();
+            if len == 0 {
+                return Err(CanonicalError::new("invalid timestamp"));
+            }
+            // Digits past nanosecond precision are truncated.
+            let kept = len.min(9);
+            nanos = take_digits(&mut &input[..kept], kept)?;
+            for _ in kept..9 {
+                nanos *= 10;
+            }
+            input = &input[len..];
+        }
+
+        let offset_seconds = match expect_byte(&mut input, b"Zz+-")? {
+            b'Z' | b'z' => 0,
+            sign => {
+                let offset_hour = take_digits(&mut input, 2)?;
+                expect_byte(&mut input, b":")?;
+                let offset_minute = take_digits(&mut input, 2)?;
+                if offset_hour > 23 || offset_minute > 59 {
+                    return Err(CanonicalError::new("timestamp offset out of range"));
+                }
+                let offset = i64::from(offset_hour * 3600 + offset_minute * 60);
+                if sign == b'-' { -offset } else { offset }
+            }
+        };
+        if !input.is_empty() {
+            return Err(CanonicalError::new("trailing characters in timestamp"));
+        }
+
+        let seconds = days_from_civil(year, month, day) * SECONDS_PER_DAY
+            + i64::from(hour * 3600 + minute * 60 + second)
+            - offset_seconds;
+        Ok((seconds, nanos))
+    }
+
+    #[cfg(all(test, feature = "chrono"))]
+    mod tests {
+        #[test]
+        fn from_unix_seconds_matches_chrono() {
+            // 0001-01-01T00:00:00Z through 9999-12-31T23:59:59Z.
+            let mut seconds = -62_135_596_800;
+            while seconds <= 253_402_300_799 {
+                assert_eq!(
+                    Some(super::from_unix_seconds(seconds)),
+                    super::super::from_unix_seconds(seconds),
+                    "{seconds}"
+                );
+                seconds += 86_399 * 37 + 12_345;
+            }
+        }
+
+        #[test]
+        fn parse_rfc3339_matches_chrono() {
+            for value in [
+                "1970-01-01T00:00:00Z",
+                "0001-01-01T00:00:00Z",
+                "9999-12-31T23:59:59.999999999Z",
+                "2000-02-29T12:34:56.5+01:00",
+                "1900-03-01T00:00:00-00:00",
+                "2006-01-02T15:04:05.1234567891-07:30",
+                "2024-12-31t23:59:59z",
+            ] {
+                assert_eq!(
+                    super::parse_rfc3339(value).ok(),
+                    super::super::parse_rfc3339(value).ok(),
+                    "{value}"
+                );
+            }
+            for invalid in [
+                "1900-02-29T00:00:00Z",
+                "2006-01-02T15:04:05",
+                "2006-01-02T15:04:05.Z",
+                "2006-01-02T24:00:00Z",
+                "2006-01-02T15:04:05+1:00",
+                "2006-1-02T15:04:05Z",
+            ] {
+                assert!(super::parse_rfc3339(invalid).is_err(), "{invalid}");
+                assert!(super::super::parse_rfc3339(invalid).is_err(), "{invalid}");
+            }
+        }
+    }
+}
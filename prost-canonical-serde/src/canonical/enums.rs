@@ -7,6 +7,11 @@ use core::marker::PhantomData;
 use prost_types::NullValue;
 use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
 
+use super::PathSeed;
+use super::map::parse_canonical_integer;
+use super::number::visit_arbitrary_precision;
+use super::options;
+use super::wrappers::vec_for_access;
 use crate::ProstEnum;
 
 /// Wraps an optional enum number for canonical protobuf JSON deserialization.
@@ -48,7 +53,12 @@ impl<E: ProstEnum + 'static> Serialize for CanonicalEnum<'_, E> {
         if is_null_value_enum::<E>() && self.value == 0 {
             return serializer.serialize_unit();
         }
-        if let Some(enum_value) = E::from_i32(self.value) {
+        let options = options::current();
+        if options.enum_as_quoted_number {
+            serializer.collect_str(&self.value)
+        } else if E::SERIALIZE_AS_NUMBER || options.enum_as_number {
+            serializer.serialize_i32(self.value)
+        } else if let Some(enum_value) = E::from_i32(self.value) {
             serializer.serialize_str(enum_value.as_str_name())
         } else {
             serializer.serialize_i32(self.value)
@@ -66,7 +76,7 @@ impl<'de, E: ProstEnum + 'static> Deserialize<'de> for CanonicalEnumValue<E> {
     {
         struct Visitor<E>(PhantomData<E>);
 
-        impl<E: ProstEnum + 'static> de::Visitor<'_> for Visitor<E> {
+        impl<'de, E: ProstEnum + 'static> de::Visitor<'de> for Visitor<E> {
             type Value = CanonicalEnumValue<E>;
 
             fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -90,9 +100,15 @@ impl<'de, E: ProstEnum + 'static> Deserialize<'de> for CanonicalEnumValue<E> {
                 if is_null_value_enum::<E>() && value == "NULL_VALUE" {
                     return Ok(CanonicalEnumValue(0, PhantomData));
                 }
-                E::from_str_name(value)
-                    .map(|enum_value| CanonicalEnumValue(enum_value.as_i32(), PhantomData))
-                    .ok_or_else(|| Err::custom("invalid enum string"))
+                if let Some(enum_value) = E::from_str_name(value) {
+                    return Ok(CanonicalEnumValue(enum_value.as_i32(), PhantomData));
+                }
+                if (E::ALLOW_QUOTED_NUMBERS || options::current().enum_as_quoted_number)
+                    && let Some(number) = parse_canonical_integer::<i32>(value)
+                {
+                    return Ok(CanonicalEnumValue(number, PhantomData));
+                }
+                Err(Err::custom("invalid enum string"))
             }
 
             fn visit_string<Err>(self, value: String) -> Result<Self::Value, Err>
@@ -126,6 +142,13 @@ impl<'de, E: ProstEnum + 'static> Deserialize<'de> for CanonicalEnumValue<E> {
                     i32::try_from(value).map_err(|_| Err::custom("enum number out of range"))?;
                 Ok(CanonicalEnumValue(value, PhantomData))
             }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                visit_arbitrary_precision(self, map)
+            }
         }
 
         deserializer.deserialize_any(Visitor(PhantomData))
@@ -184,8 +207,10 @@ impl<'de, E: ProstEnum + 'static> Deserialize<'de> for CanonicalEnumVec<E> {
             where
                 A: de::SeqAccess<'de>,
             {
-                let mut values = Vec::new();
-                while let Some(value) = seq.next_element::<CanonicalEnumValue<E>>()? {
+                let mut values = vec_for_access(&seq);
+                while let Some(value) =
+                    seq.next_element_seed(PathSeed::<CanonicalEnumValue<E>>::index(values.len()))?
+                {
                     values.push(value.0);
                 }
                 Ok(CanonicalEnumVec(values, PhantomData))
@@ -204,6 +229,13 @@ impl<'de, E: ProstEnum + 'static> Deserialize<'de> for CanonicalEnumVec<E> {
             {
                 self.visit_unit()
             }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                visit_arbitrary_precision(self, map)
+            }
         }
 
         deserializer.deserialize_any(Visitor(PhantomData))
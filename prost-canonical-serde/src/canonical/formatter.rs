@@ -0,0 +1,100 @@
+use alloc::string::String;
+use core::fmt::Write as _;
+use std::io;
+
+use serde_json::ser::Formatter;
+
+/// A `serde_json` formatter that renders floating point numbers the way the
+/// protobuf C++ implementation's JSON printer does.
+///
+/// Doubles use the shortest of 15 or 17 significant digits that round-trips,
+/// and floats the shortest of 6 or 9, printed in `%g` style (`1`, `0.1`,
+/// `1e+300`, `1e-07`). Canonical floats are strings unless the
+/// `floats_as_numbers` option is set; `to_string_with_canonical_formatter`
+/// sets it for you.
+///
+/// Those float strings follow Go's protojson instead, so the same value can
+/// render differently with and without this formatter: `1e-7` against
+/// `1e-07`, or `5e-324` against `4.94065645841247e-324`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CanonicalFormatter;
+
+impl Formatter for CanonicalFormatter {
+    fn write_f32<W>(&mut self, writer: &mut W, value: f32) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        if !value.is_finite() {
+            return writer.write_all(b"null");
+        }
+        let short = format!("{value:.5e}");
+        let formatted = if short.parse::<f32>() == Ok(value) {
+            format_g(&short, 6)
+        } else {
+            format_g(&format!("{value:.8e}"), 9)
+        };
+        writer.write_all(formatted.as_bytes())
+    }
+
+    fn write_f64<W>(&mut self, writer: &mut W, value: f64) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        if !value.is_finite() {
+            return writer.write_all(b"null");
+        }
+        let short = format!("{value:.14e}");
+        let formatted = if short.parse::<f64>() == Ok(value) {
+            format_g(&short, 15)
+        } else {
+            format_g(&format!("{value:.16e}"), 17)
+        };
+        writer.write_all(formatted.as_bytes())
+    }
+}
+
+/// Rewrites Rust's `{:e}` output with `precision` significant digits the way
+/// C's `%.{precision}g` would print it.
+fn format_g(scientific: &str, precision: i32) -> String {
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .expect("`{:e}` output has an exponent");
+    let exponent: i32 = exponent.parse().expect("`{:e}` exponent is an integer");
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", mantissa),
+    };
+    let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+    let digits = digits.trim_end_matches('0');
+    let digits = if digits.is_empty() { "0" } else { digits };
+
+    let mut out = String::from(sign);
+    if exponent < -4 || exponent >= precision {
+        out.push_str(&digits[..1]);
+        if digits.len() > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        let exponent_sign = if exponent < 0 { '-' } else { '+' };
+        let _ = write!(out, "e{exponent_sign}{:02}", exponent.unsigned_abs());
+    } else if exponent < 0 {
+        out.push_str("0.");
+        for _ in 0..(-exponent - 1) {
+            out.push('0');
+        }
+        out.push_str(digits);
+    } else {
+        let integer_len = usize::try_from(exponent).expect("non-negative exponent") + 1;
+        if digits.len() <= integer_len {
+            out.push_str(digits);
+            for _ in digits.len()..integer_len {
+                out.push('0');
+            }
+        } else {
+            out.push_str(&digits[..integer_len]);
+            out.push('.');
+            out.push_str(&digits[integer_len..]);
+        }
+    }
+    out
+}
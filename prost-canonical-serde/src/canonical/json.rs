@@ -0,0 +1,296 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use std::io;
+
+use super::CanonicalError;
+use super::formatter::CanonicalFormatter;
+use super::options::{self, with_options};
+use super::wrappers::Canonical;
+use crate::{CanonicalDeserialize, CanonicalSerialize, MergeField};
+
+/// Parses canonical JSON from a string.
+///
+/// # Errors
+/// Returns an `empty input` error if `input` is empty or only whitespace,
+/// and otherwise any error from parsing `T`.
+pub fn from_canonical_str<T>(input: &str) -> Result<T, serde_json::Error>
+where
+    T: CanonicalDeserialize,
+{
+    from_canonical_slice(input.as_bytes())
+}
+
+/// Parses canonical JSON from bytes.
+///
+/// # Errors
+/// Returns an `empty input` error if `input` is empty or only whitespace,
+/// and otherwise any error from parsing `T`.
+pub fn from_canonical_slice<T>(input: &[u8]) -> Result<T, serde_json::Error>
+where
+    T: CanonicalDeserialize,
+{
+    if input
+        .iter()
+        .all(|byte| matches!(byte, b' ' | b'\t' | b'\n' | b'\r'))
+    {
+        return Err(serde::de::Error::custom(CanonicalError::new("empty input")));
+    }
+    let mut deserializer = serde_json::Deserializer::from_slice(input);
+    let value = T::deserialize_canonical(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Writes `value` as canonical JSON to `writer` without building a `String`.
+///
+/// # Errors
+/// Returns an error if serialization fails or the writer reports an I/O error.
+pub fn to_canonical_writer<W, T>(writer: W, value: &T) -> Result<(), serde_json::Error>
+where
+    W: io::Write,
+    T: CanonicalSerialize + ?Sized,
+{
+    let mut serializer = serde_json::Serializer::new(writer);
+    value.serialize_canonical(&mut serializer)
+}
+
+/// Writes `value` as pretty-printed canonical JSON to `writer`.
+///
+/// # Errors
+/// Returns an error if serialization fails or the writer reports an I/O error.
+pub fn to_canonical_writer_pretty<W, T>(writer: W, value: &T) -> Result<(), serde_json::Error>
+where
+    W: io::Write,
+    T: CanonicalSerialize + ?Sized,
+{
+    let mut serializer =
+        serde_json::Serializer::with_formatter(writer, serde_json::ser::PrettyFormatter::new());
+    value.serialize_canonical(&mut serializer)
+}
+
+/// Serializes `value` as canonical JSON followed by a single `\n`, ready to
+/// be written as a file or a JSON Lines record.
+///
+/// # Errors
+/// Returns an error if serialization fails.
+pub fn to_string_with_newline<T>(value: &T) -> Result<String, serde_json::Error>
+where
+    T: CanonicalSerialize + ?Sized,
+{
+    let mut out = serde_json::to_string(&Canonical::new(value))?;
+    out.push('\n');
+    Ok(out)
+}
+
+/// Converts `value` to a canonical JSON `serde_json::Value`.
+///
+/// For in-memory merging or reshaping this is cheaper than serializing to a
+/// string and parsing it back.
+///
+/// # Errors
+/// Returns an error if serialization fails.
+pub fn to_canonical_value<T>(value: &T) -> Result<serde_json::Value, serde_json::Error>
+where
+    T: CanonicalSerialize + ?Sized,
+{
+    serde_json::to_value(Canonical::new(value))
+}
+
+/// Builds a `T` from a canonical JSON `serde_json::Value`.
+///
+/// # Errors
+/// Returns an error if `value` is not valid canonical JSON for `T`.
+pub fn from_canonical_value<T>(value: serde_json::Value) -> Result<T, serde_json::Error>
+where
+    T: CanonicalDeserialize,
+{
+    T::deserialize_canonical(value)
+}
+
+/// Converts a message to the canonical JSON object of its set fields.
+///
+/// # Errors
+/// Returns an error if serialization fails or `value` does not serialize to
+/// a JSON object.
+pub fn to_canonical_map<T>(
+    value: &T,
+) -> Result<serde_json::Map<String, serde_json::Value>, serde_json::Error>
+where
+    T: CanonicalSerialize + ?Sized,
+{
+    match to_canonical_value(value)? {
+        serde_json::Value::Object(map) => Ok(map),
+        other => Err(serde::ser::Error::custom(format!(
+            "expected a JSON object, found {other}"
+        ))),
+    }
+}
+
+/// Applies a partial canonical JSON object onto an existing message.
+///
+/// Keys in `patch` may use JSON or proto field names. Only the fields the
+/// patch names are read; every other field, including `skip` fields, keeps
+/// its value untouched. Singular message fields present on both sides are
+/// merged recursively; every other field, including repeated, map, and
+/// well-known type fields, is replaced by the patch value, and `null` resets
+/// a field to its default. Setting one member of a oneof clears whichever
+/// other member was set. Keys that name no field are treated the same as
+/// when deserializing.
+///
+/// # Errors
+/// Returns an error if `T` is not a derived message, if `patch` is not a
+/// JSON object, or if `patch` is not valid canonical JSON for `T`; `target`
+/// is left unchanged in that case.
+pub fn merge_from_canonical_value<T>(
+    target: &mut T,
+    patch: serde_json::Value,
+) -> Result<(), serde_json::Error>
+where
+    T: CanonicalSerialize + CanonicalDeserialize,
+{
+    if T::__merge_fields().is_none() {
+        return Err(serde::de::Error::custom("merge target must be a message"));
+    }
+    if !patch.is_object() {
+        return Err(serde::de::Error::custom(
+            "merge patch must be a JSON object",
+        ));
+    }
+    // Reading the patch on its own first raises any error it holds before
+    // `target` is touched.
+    T::deserialize_canonical(&patch)?;
+    target.__merge_canonical(patch)
+}
+
+/// Converts a message to canonical JSON holding only the fields named by
+/// `mask`.
+///
+/// Each path is a dot-separated list of proto field names (JSON names are
+/// accepted too), such as `nested.note`; a path stops at a singular message
+/// field to select only some of its fields, and a selected field that is not
+/// set is left out. An empty mask selects every field.
+///
+/// # Errors
+/// Returns an error if `T` is not a derived message, if serialization fails,
+/// or if a path names a field that does not exist or descends into a field
+/// that is not a singular message.
+pub fn to_canonical_value_masked<T>(
+    value: &T,
+    mask: &prost_types::FieldMask,
+) -> Result<serde_json::Value, serde_json::Error>
+where
+    T: CanonicalSerialize,
+{
+    let Some(fields) = T::__merge_fields() else {
+        return Err(serde::ser::Error::custom("masked value must be a message"));
+    };
+    let map = to_canonical_map(value)?;
+    if mask.paths.is_empty() {
+        return Ok(serde_json::Value::Object(map));
+    }
+    let paths: Vec<Vec<&str>> = mask
+        .paths
+        .iter()
+        .map(|path| path.split('.').collect())
+        .collect();
+    let paths: Vec<&[&str]> = paths.iter().map(Vec::as_slice).collect();
+    for path in &paths {
+        check_mask_path(&fields, path)?;
+    }
+    mask_object(&fields, map, &paths).map(serde_json::Value::Object)
+}
+
+/// Checks `path` against the message schema alone, so an invalid path is
+/// rejected whether or not the fields it names are set.
+fn check_mask_path(fields: &[MergeField], path: &[&str]) -> Result<(), serde_json::Error> {
+    let Some((name, rest)) = path.split_first() else {
+        return Ok(());
+    };
+    let Some(field) = fields
+        .iter()
+        .find(|field| field.proto_name == *name || field.json_name == *name)
+    else {
+        return Err(serde::ser::Error::custom(format!(
+            "unknown field mask path `{name}`"
+        )));
+    };
+    if rest.is_empty() {
+        return Ok(());
+    }
+    let Some(children) = (field.children)() else {
+        return Err(serde::ser::Error::custom(format!(
+            "field mask path descends into `{name}`, which is not a message"
+        )));
+    };
+    check_mask_path(&children, rest)
+}
+
+fn mask_object(
+    fields: &[MergeField],
+    mut map: serde_json::Map<String, serde_json::Value>,
+    paths: &[&[&str]],
+) -> Result<serde_json::Map<String, serde_json::Value>, serde_json::Error> {
+    let mut masked = serde_json::Map::new();
+    let mut selected = Vec::new();
+    for path in paths {
+        let Some((name, _)) = path.split_first() else {
+            continue;
+        };
+        let Some(field) = fields
+            .iter()
+            .find(|field| field.proto_name == *name || field.json_name == *name)
+        else {
+            return Err(serde::ser::Error::custom(format!(
+                "unknown field mask path `{name}`"
+            )));
+        };
+        if selected.contains(&field.json_name) {
+            continue;
+        }
+        selected.push(field.json_name);
+        let rest: Vec<&[&str]> = paths
+            .iter()
+            .filter_map(|path| match path.split_first() {
+                Some((first, rest)) if *first == field.proto_name || *first == field.json_name => {
+                    Some(rest)
+                }
+                _ => None,
+            })
+            .collect();
+        let Some(value) = map.remove(field.json_name) else {
+            continue;
+        };
+        if rest.iter().any(|rest| rest.is_empty()) {
+            masked.insert(field.json_name.to_string(), value);
+            continue;
+        }
+        let (Some(children), serde_json::Value::Object(value)) = ((field.children)(), value) else {
+            return Err(serde::ser::Error::custom(format!(
+                "field mask path descends into `{name}`, which is not a message"
+            )));
+        };
+        masked.insert(
+            field.json_name.to_string(),
+            serde_json::Value::Object(mask_object(&children, value, &rest)?),
+        );
+    }
+    Ok(masked)
+}
+
+/// Serializes `value` to a string with floats rendered by
+/// [`CanonicalFormatter`], matching the protobuf C++ output byte for byte.
+///
+/// # Errors
+/// Returns an error if serialization fails.
+pub fn to_string_with_canonical_formatter<T>(value: &T) -> Result<String, serde_json::Error>
+where
+    T: CanonicalSerialize + ?Sized,
+{
+    let mut out = Vec::new();
+    let mut serializer = serde_json::Serializer::with_formatter(&mut out, CanonicalFormatter);
+    with_options(options::current().floats_as_numbers(true), || {
+        value.serialize_canonical(&mut serializer)
+    })?;
+    String::from_utf8(out).map_err(serde::ser::Error::custom)
+}
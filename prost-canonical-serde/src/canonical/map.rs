@@ -1,6 +1,8 @@
 use alloc::collections::BTreeMap;
+use alloc::format;
 use alloc::string::String;
 use alloc::string::ToString;
+use alloc::vec::Vec;
 use core::marker::PhantomData;
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
@@ -8,12 +10,21 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
 #[cfg(feature = "std")]
 use std::collections::HashMap;
 
-use super::CanonicalError;
 use super::enums::{CanonicalEnum, CanonicalEnumValue};
-use super::wrappers::CanonicalValue;
+use super::options;
+use super::wrappers::{CanonicalValue, reserve_hint};
+use super::{CanonicalError, PathSeed};
 use crate::ProstEnum;
 
 /// Key conversion helper for canonical protobuf JSON maps.
+///
+/// Protobuf only allows `string`, `bool`, and the 32- and 64-bit integer
+/// types as map keys, so `String`, `bool`, `i32`, `i64`, `u32`, and `u64` are
+/// the only spec-valid implementations. `i128` and `u128` are also provided
+/// for hand-written messages; their keys use the same decimal form. Implement
+/// this trait for your own newtype keys to use them in a `CanonicalMap`;
+/// writing them with `CanonicalMapRef` or a derived message also needs
+/// `ToString`.
 #[expect(
     clippy::missing_errors_doc,
     reason = "Implementations describe key parsing failures in their error strings."
@@ -41,42 +52,88 @@ impl CanonicalMapKey for bool {
 
 impl CanonicalMapKey for i32 {
     fn from_key(value: &str) -> Result<Self, CanonicalError> {
-        value
-            .parse()
-            .map_err(|_| CanonicalError::new("invalid i32 map key"))
+        parse_integer_key(value, "i32")
     }
 }
 
 impl CanonicalMapKey for i64 {
     fn from_key(value: &str) -> Result<Self, CanonicalError> {
-        value
-            .parse()
-            .map_err(|_| CanonicalError::new("invalid i64 map key"))
+        parse_integer_key(value, "i64")
     }
 }
 
 impl CanonicalMapKey for u32 {
     fn from_key(value: &str) -> Result<Self, CanonicalError> {
-        value
-            .parse()
-            .map_err(|_| CanonicalError::new("invalid u32 map key"))
+        parse_integer_key(value, "u32")
     }
 }
 
 impl CanonicalMapKey for u64 {
     fn from_key(value: &str) -> Result<Self, CanonicalError> {
-        value
-            .parse()
-            .map_err(|_| CanonicalError::new("invalid u64 map key"))
+        parse_integer_key(value, "u64")
     }
 }
 
-/// Map type abstraction to handle both hash and btree maps.
+impl CanonicalMapKey for i128 {
+    fn from_key(value: &str) -> Result<Self, CanonicalError> {
+        parse_integer_key(value, "i128")
+    }
+}
+
+impl CanonicalMapKey for u128 {
+    fn from_key(value: &str) -> Result<Self, CanonicalError> {
+        parse_integer_key(value, "u128")
+    }
+}
+
+/// Parses an integer map key, which must be written the way protobuf prints
+/// it.
+fn parse_integer_key<T: core::str::FromStr>(
+    value: &str,
+    type_name: &str,
+) -> Result<T, CanonicalError> {
+    parse_canonical_integer(value)
+        .ok_or_else(|| CanonicalError::new(format!("invalid {type_name} map key {value:?}")))
+}
+
+/// Parses an integer written the way protobuf prints it: an optional `-` and
+/// decimal digits without a `+`, leading zeros, or surrounding whitespace.
+pub(crate) fn parse_canonical_integer<T: core::str::FromStr>(value: &str) -> Option<T> {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    let canonical = !digits.is_empty()
+        && digits.bytes().all(|byte| byte.is_ascii_digit())
+        && (digits == "0" || !digits.starts_with('0'))
+        && value != "-0";
+    canonical.then(|| value.parse().ok()).flatten()
+}
+
+/// Map type abstraction to handle hash, btree, and (with the `indexmap`
+/// feature) index maps.
 pub trait CanonicalMapType: Default {
     type Key: CanonicalMapKey;
     type Value;
 
+    /// Whether iteration already yields entries in a stable order.
+    const ORDERED: bool = false;
+
     fn insert(&mut self, key: Self::Key, value: Self::Value);
+
+    /// Reserves room for at least `additional` more entries. Maps without a
+    /// notion of capacity ignore this.
+    fn reserve(&mut self, additional: usize) {
+        let _ = additional;
+    }
+}
+
+/// Creates an empty map with room for the entries `map` says it holds.
+fn map_for_access<'de, M, A>(map: &A) -> M
+where
+    M: CanonicalMapType,
+    A: de::MapAccess<'de>,
+{
+    let mut values = M::default();
+    values.reserve(reserve_hint(map.size_hint()));
+    values
 }
 
 #[cfg(feature = "std")]
@@ -91,6 +148,10 @@ where
     fn insert(&mut self, key: Self::Key, value: Self::Value) {
         HashMap::insert(self, key, value);
     }
+
+    fn reserve(&mut self, additional: usize) {
+        HashMap::reserve(self, additional);
+    }
 }
 
 impl<K, V> CanonicalMapType for BTreeMap<K, V>
@@ -100,12 +161,93 @@ where
     type Key = K;
     type Value = V;
 
+    const ORDERED: bool = true;
+
     fn insert(&mut self, key: Self::Key, value: Self::Value) {
         BTreeMap::insert(self, key, value);
     }
 }
 
+#[cfg(feature = "indexmap")]
+impl<K, V, S> CanonicalMapType for indexmap::IndexMap<K, V, S>
+where
+    K: CanonicalMapKey + Eq + core::hash::Hash,
+    S: core::hash::BuildHasher + Default,
+{
+    type Key = K;
+    type Value = V;
+
+    /// Entries are emitted in insertion order, which `sort_map_keys` leaves
+    /// untouched.
+    const ORDERED: bool = true;
+
+    fn insert(&mut self, key: Self::Key, value: Self::Value) {
+        indexmap::IndexMap::insert(self, key, value);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        indexmap::IndexMap::reserve(self, additional);
+    }
+}
+
+/// Serializes map entries in the order the map yields them.
+fn serialize_entries<'a, K, V, T, S>(
+    entries: impl IntoIterator<Item = (&'a K, &'a V)>,
+    wrap: impl Fn(&'a V) -> T,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    K: ToString + 'a,
+    V: 'a,
+    T: Serialize,
+    S: Serializer,
+{
+    use serde::ser::SerializeMap;
+
+    let mut map = serializer.serialize_map(None)?;
+    for (key, value) in entries {
+        map.serialize_entry(&key.to_string(), &wrap(value))?;
+    }
+    map.end()
+}
+
+/// Serializes map entries, sorting them by their JSON key text when the
+/// `sort_map_keys` option is set and the map is not already ordered.
+fn serialize_sorted_entries<'a, M, K, V, T, S>(
+    values: &'a M,
+    wrap: impl Fn(&'a V) -> T,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    &'a M: core::iter::IntoIterator<Item = (&'a K, &'a V)>,
+    M: CanonicalMapType,
+    K: ToString + 'a,
+    V: 'a,
+    T: Serialize,
+    S: Serializer,
+{
+    use serde::ser::SerializeMap;
+
+    if M::ORDERED || !options::current().sort_map_keys {
+        return serialize_entries(values, wrap, serializer);
+    }
+    let mut entries = values
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect::<Vec<_>>();
+    entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    let mut map = serializer.serialize_map(Some(entries.len()))?;
+    for (key, value) in entries {
+        map.serialize_entry(&key, &wrap(value))?;
+    }
+    map.end()
+}
+
 /// Wraps a map reference with canonical JSON serialization.
+///
+/// Entries are written in the order the map yields them; this wrapper ignores
+/// the `sort_map_keys` option. Map fields of derived messages use
+/// [`CanonicalMapFieldRef`], which honors it.
 pub struct CanonicalMapRef<'a, M> {
     values: &'a M,
 }
@@ -126,14 +268,38 @@ where
     where
         S: Serializer,
     {
-        use serde::ser::SerializeMap;
+        serialize_entries(self.values, super::wrappers::Canonical::new, serializer)
+    }
+}
 
-        let mut map = serializer.serialize_map(None)?;
-        for (key, value) in self.values {
-            let value = super::wrappers::Canonical::new(value);
-            map.serialize_entry(&key.to_string(), &value)?;
-        }
-        map.end()
+/// Wraps a map field reference with canonical JSON serialization, sorting
+/// entries by key when the `sort_map_keys` option is set.
+///
+/// Keys are ordered by their JSON text, so integer key `10` comes before `2`.
+/// Maps that are already ordered, such as `BTreeMap` and `IndexMap`, are
+/// written as they are.
+pub struct CanonicalMapFieldRef<'a, M> {
+    values: &'a M,
+}
+
+impl<'a, M> CanonicalMapFieldRef<'a, M> {
+    pub fn new(values: &'a M) -> Self {
+        Self { values }
+    }
+}
+
+impl<M, K, V> Serialize for CanonicalMapFieldRef<'_, M>
+where
+    for<'b> &'b M: core::iter::IntoIterator<Item = (&'b K, &'b V)>,
+    M: CanonicalMapType,
+    K: CanonicalMapKey + ToString,
+    V: crate::CanonicalSerialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_sorted_entries(self.values, super::wrappers::Canonical::new, serializer)
     }
 }
 
@@ -166,10 +332,12 @@ where
             where
                 A: de::MapAccess<'de>,
             {
-                let mut values = M::default();
-                while let Some(key) = map.next_key::<String>()? {
-                    let key = M::Key::from_key(&key).map_err(de::Error::custom)?;
-                    let value = map.next_value::<CanonicalValue<M::Value>>()?.0;
+                let mut values: M = map_for_access(&map);
+                while let Some(name) = map.next_key::<String>()? {
+                    let key = M::Key::from_key(&name).map_err(de::Error::custom)?;
+                    let value = map
+                        .next_value_seed(PathSeed::<CanonicalValue<M::Value>>::key(&name))?
+                        .0;
                     values.insert(key, value);
                 }
                 Ok(CanonicalMap(values))
@@ -195,6 +363,10 @@ where
 }
 
 /// Wraps a map reference with enum values for canonical JSON serialization.
+///
+/// Entries are written in the order the map yields them; this wrapper ignores
+/// the `sort_map_keys` option. See [`CanonicalEnumMapFieldRef`] for the
+/// variant that honors it.
 pub struct CanonicalEnumMapRef<'a, E, M> {
     values: &'a M,
     _marker: PhantomData<E>,
@@ -219,14 +391,47 @@ where
     where
         S: Serializer,
     {
-        use serde::ser::SerializeMap;
+        serialize_entries(
+            self.values,
+            |value| CanonicalEnum::<E>::new(*value),
+            serializer,
+        )
+    }
+}
+
+/// Wraps a map field reference with enum values for canonical JSON
+/// serialization, sorting entries by key when the `sort_map_keys` option is
+/// set, as [`CanonicalMapFieldRef`] does.
+pub struct CanonicalEnumMapFieldRef<'a, E, M> {
+    values: &'a M,
+    _marker: PhantomData<E>,
+}
 
-        let mut map = serializer.serialize_map(None)?;
-        for (key, value) in self.values {
-            let value = CanonicalEnum::<E>::new(*value);
-            map.serialize_entry(&key.to_string(), &value)?;
+impl<'a, E, M> CanonicalEnumMapFieldRef<'a, E, M> {
+    pub fn new(values: &'a M) -> Self {
+        Self {
+            values,
+            _marker: PhantomData,
         }
-        map.end()
+    }
+}
+
+impl<E, M, K> Serialize for CanonicalEnumMapFieldRef<'_, E, M>
+where
+    for<'b> &'b M: core::iter::IntoIterator<Item = (&'b K, &'b i32)>,
+    M: CanonicalMapType,
+    K: CanonicalMapKey + ToString,
+    E: ProstEnum + 'static,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_sorted_entries(
+            self.values,
+            |value| CanonicalEnum::<E>::new(*value),
+            serializer,
+        )
     }
 }
 
@@ -259,10 +464,12 @@ where
             where
                 A: de::MapAccess<'de>,
             {
-                let mut values = M::default();
-                while let Some(key) = map.next_key::<String>()? {
-                    let key = M::Key::from_key(&key).map_err(de::Error::custom)?;
-                    let value = map.next_value::<CanonicalEnumValue<E>>()?.0;
+                let mut values: M = map_for_access(&map);
+                while let Some(name) = map.next_key::<String>()? {
+                    let key = M::Key::from_key(&name).map_err(de::Error::custom)?;
+                    let value = map
+                        .next_value_seed(PathSeed::<CanonicalEnumValue<E>>::key(&name))?
+                        .0;
                     values.insert(key, value);
                 }
                 Ok(CanonicalEnumMap(values, PhantomData))
@@ -4,11 +4,22 @@
 //! prost-generated types and then use `serde_json` directly. This module exists
 //! for advanced cases, such as wrapping values when manual control is needed.
 
+#[cfg(feature = "any")]
+mod any;
+mod civil;
 mod enums;
 mod error;
+#[cfg(feature = "std")]
+mod formatter;
+#[cfg(feature = "std")]
+mod json;
 mod map;
 mod number;
+mod options;
+mod path;
 mod scalar;
+#[cfg(feature = "std")]
+mod stream;
 mod wkt;
 mod wrappers;
 
@@ -16,8 +27,35 @@ pub use enums::{
     CanonicalEnum, CanonicalEnumOption, CanonicalEnumSeq, CanonicalEnumValue, CanonicalEnumVec,
 };
 pub use error::CanonicalError;
+#[cfg(feature = "std")]
+pub use formatter::CanonicalFormatter;
+#[cfg(feature = "std")]
+pub use json::{
+    from_canonical_slice, from_canonical_str, from_canonical_value, merge_from_canonical_value,
+    to_canonical_map, to_canonical_value, to_canonical_value_masked, to_canonical_writer,
+    to_canonical_writer_pretty, to_string_with_canonical_formatter, to_string_with_newline,
+};
 pub use map::{
-    CanonicalEnumMap, CanonicalEnumMapRef, CanonicalMap, CanonicalMapKey, CanonicalMapRef,
-    CanonicalMapType,
+    CanonicalEnumMap, CanonicalEnumMapFieldRef, CanonicalEnumMapRef, CanonicalMap,
+    CanonicalMapFieldRef, CanonicalMapKey, CanonicalMapRef, CanonicalMapType,
+};
+#[cfg(feature = "std")]
+pub use options::with_options;
+pub use options::{
+    CanonicalOptions, DurationUnit, MessageOptions, NonFiniteValue, TimestampUnit,
+    empty_repeated_as_null, message_options, reject_null_oneof_members, resolve_field_key,
+    unwrap_message_name,
+};
+pub use path::{MergeSeed, PathSeed};
+pub(crate) use path::{PathSegment, with_flattened_segment, with_path_segment};
+#[cfg(feature = "bytes-base64")]
+pub use scalar::CanonicalBytesOrArray;
+#[cfg(feature = "std")]
+pub use stream::{CanonicalArrayStream, CanonicalStreamError};
+pub use wkt::EpochTimestamp;
+#[cfg(feature = "std")]
+pub use wrappers::CanonicalSeed;
+pub use wrappers::{
+    Canonical, CanonicalOption, CanonicalSeq, CanonicalValue, CanonicalVec, canonical_with,
+    is_empty_message,
 };
-pub use wrappers::{Canonical, CanonicalOption, CanonicalSeq, CanonicalValue, CanonicalVec};
@@ -1,13 +1,21 @@
-use serde::Serializer;
+use core::fmt::{self, Write as _};
+use core::ops::Neg;
+
+use alloc::string::String;
+use serde::{Serializer, de};
 
 use super::CanonicalError;
+use super::options;
 
 pub(crate) fn serialize_float64<S>(value: f64, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    if value.is_finite() {
-        serializer.collect_str(&value)
+    if value.is_finite() && options::current().floats_as_numbers {
+        serializer.serialize_f64(value)
+    } else if value.is_finite() {
+        let buffer = format_float(value, 1e-6, 1e21).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(buffer.as_str())
     } else if value.is_nan() {
         serializer.serialize_str("NaN")
     } else if value.is_sign_positive() {
@@ -21,8 +29,11 @@ pub(crate) fn serialize_float32<S>(value: f32, serializer: S) -> Result<S::Ok, S
 where
     S: Serializer,
 {
-    if value.is_finite() {
-        serializer.collect_str(&value)
+    if value.is_finite() && options::current().floats_as_numbers {
+        serializer.serialize_f32(value)
+    } else if value.is_finite() {
+        let buffer = format_float(value, 1e-6, 1e21).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(buffer.as_str())
     } else if value.is_nan() {
         serializer.serialize_str("NaN")
     } else if value.is_sign_positive() {
@@ -32,11 +43,145 @@ where
     }
 }
 
+/// Formats a finite `double` as text, exactly as a `double` field is
+/// written.
+pub(crate) fn format_double(value: f64) -> Result<String, fmt::Error> {
+    format_float(value, 1e-6, 1e21).map(|buffer| String::from(buffer.as_str()))
+}
+
+/// Formats a finite float the way Go's protojson does, which follows
+/// JavaScript's `Number.prototype.toString`.
+///
+/// This is not the rendering of the C++ implementation, which
+/// `CanonicalFormatter` reproduces for numeric output; the two differ on
+/// values such as `1e-7` and `1.2345678901234568e20`. Digits are always the
+/// shortest that round-trip. Magnitudes in
+/// `[lower, upper)` (and zero) use plain decimal notation; everything else
+/// switches to exponential notation with an explicitly signed exponent, so
+/// `1e21` becomes `1e+21` and `1e-7` stays `1e-7`. The bounds are taken in the
+/// value's own type so `f32` thresholds are compared without widening.
+fn format_float<F>(value: F, lower: F, upper: F) -> Result<FloatBuffer, fmt::Error>
+where
+    F: Copy + Default + PartialOrd + Neg<Output = F> + fmt::Display + fmt::LowerExp,
+{
+    let zero = F::default();
+    let magnitude = if value < zero { -value } else { value };
+    let mut buffer = FloatBuffer::new();
+    if magnitude != zero && (magnitude < lower || magnitude >= upper) {
+        write!(buffer, "{value:e}")?;
+        buffer.sign_exponent()?;
+    } else {
+        write!(buffer, "{value}")?;
+    }
+    Ok(buffer)
+}
+
+/// Fixed-capacity formatting target for [`format_float`].
+///
+/// Plain notation is only used below `1e21` and exponential notation keeps
+/// at most 17 significant digits, so no output comes close to the capacity.
+/// Formatting on the stack keeps float serialization allocation-free and lets
+/// it use `serialize_str`, which every serializer supports, instead of
+/// `collect_str`.
+struct FloatBuffer {
+    bytes: [u8; Self::CAPACITY],
+    len: usize,
+}
+
+impl FloatBuffer {
+    const CAPACITY: usize = 48;
+
+    fn new() -> Self {
+        Self {
+            bytes: [0; Self::CAPACITY],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // Only ASCII is ever written, so the prefix stays valid UTF-8.
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+
+    /// Inserts `+` after the `e` of a non-negative exponent.
+    fn sign_exponent(&mut self) -> fmt::Result {
+        let Some(e) = self.bytes[..self.len].iter().position(|&b| b == b'e') else {
+            return Ok(());
+        };
+        if self.bytes[e + 1] == b'-' {
+            return Ok(());
+        }
+        if self.len == Self::CAPACITY {
+            return Err(fmt::Error);
+        }
+        self.bytes.copy_within(e + 1..self.len, e + 2);
+        self.bytes[e + 1] = b'+';
+        self.len += 1;
+        Ok(())
+    }
+}
+
+impl fmt::Write for FloatBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        let dest = self.bytes.get_mut(self.len..end).ok_or(fmt::Error)?;
+        dest.copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Key under which `serde_json`'s `arbitrary_precision` feature hands the text
+/// of a number that is not a plain integer to `deserialize_any` visitors, as
+/// the only entry of a map.
+pub(crate) const ARBITRARY_PRECISION_KEY: &str = "$serde_json::private::Number";
+
+/// `visit_map` for number visitors: unwraps an `arbitrary_precision` number
+/// and rejects any other map.
+pub(crate) fn visit_arbitrary_precision<'de, V, A>(
+    visitor: V,
+    mut map: A,
+) -> Result<V::Value, A::Error>
+where
+    V: de::Visitor<'de>,
+    A: de::MapAccess<'de>,
+{
+    match map.next_key::<String>()? {
+        Some(key) if key == ARBITRARY_PRECISION_KEY => visit_number_text(visitor, map),
+        _ => Err(de::Error::invalid_type(de::Unexpected::Map, &visitor)),
+    }
+}
+
+/// Reads the number text after an [`ARBITRARY_PRECISION_KEY`] key and hands
+/// `visitor` the integer or float a parser without the feature would have.
+pub(crate) fn visit_number_text<'de, V, A>(visitor: V, mut map: A) -> Result<V::Value, A::Error>
+where
+    V: de::Visitor<'de>,
+    A: de::MapAccess<'de>,
+{
+    let text = map.next_value::<String>()?;
+    if let Ok(value) = text.parse::<u64>() {
+        return visitor.visit_u64(value);
+    }
+    if let Ok(value) = text.parse::<i64>() {
+        return visitor.visit_i64(value);
+    }
+    match text.parse::<f64>() {
+        Ok(value) if value.is_finite() => visitor.visit_f64(value),
+        _ => Err(de::Error::custom("number out of range")),
+    }
+}
+
 pub(crate) fn parse_float(value: &str) -> Result<f64, CanonicalError> {
     match value {
         "NaN" => Ok(f64::NAN),
         "Infinity" => Ok(f64::INFINITY),
         "-Infinity" => Ok(f64::NEG_INFINITY),
+        _ if options::current().lenient_float_tokens
+            && let Some(special) = parse_lenient_special(value) =>
+        {
+            Ok(special)
+        }
         _ => {
             let parsed = value
                 .parse::<f64>()
@@ -49,6 +194,21 @@ pub(crate) fn parse_float(value: &str) -> Result<f64, CanonicalError> {
     }
 }
 
+/// Matches `nan`, `inf`, and `infinity` without regard to ASCII case, with an
+/// optional sign on the infinities.
+fn parse_lenient_special(value: &str) -> Option<f64> {
+    if value.eq_ignore_ascii_case("nan") {
+        return Some(f64::NAN);
+    }
+    let (sign, magnitude) = match value.as_bytes().first() {
+        Some(b'+') => (1.0, &value[1..]),
+        Some(b'-') => (-1.0, &value[1..]),
+        _ => (1.0, value),
+    };
+    (magnitude.eq_ignore_ascii_case("inf") || magnitude.eq_ignore_ascii_case("infinity"))
+        .then_some(sign * f64::INFINITY)
+}
+
 fn is_integral(value: f64) -> bool {
     if !value.is_finite() {
         return false;
@@ -223,12 +383,15 @@ pub(crate) fn f32_from_f64(value: f64) -> Result<f32, CanonicalError> {
             f32::NEG_INFINITY
         });
     }
-    if value < f64::from(f32::MIN) || value > f64::from(f32::MAX) {
-        return Err(CanonicalError::new("float out of range"));
-    }
+    // The shortest digits for `f32::MAX` (`3.4028235e+38`) lie slightly above
+    // it as an `f64`, so accept anything that still rounds to a finite `f32`.
     #[expect(
         clippy::cast_possible_truncation,
-        reason = "Range checks ensure the cast preserves the f32 value."
+        reason = "Narrowing rounds to the nearest f32; overflow is rejected below."
     )]
-    Ok(value as f32)
+    let narrowed = value as f32;
+    if narrowed.is_infinite() {
+        return Err(CanonicalError::new("float out of range"));
+    }
+    Ok(narrowed)
 }
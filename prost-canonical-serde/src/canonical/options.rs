@@ -0,0 +1,429 @@
+#[cfg(feature = "std")]
+use core::cell::Cell;
+
+use serde::de;
+
+use super::wrappers::CanonicalValue;
+use crate::CanonicalDeserialize;
+
+/// Opt-in, non-canonical encoding options.
+///
+/// Every option defaults to the canonical protobuf JSON behavior. Options are
+/// carried by a value with `Canonical::with_options` or `CanonicalSeed`, or
+/// installed for the duration of a closure with [`with_options`], and are
+/// read by the canonical impls while they run. Without the `std` feature
+/// there is no way to install options, so the defaults always apply.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "Each flag is an independent opt-in toggle set through a builder method."
+)]
+pub struct CanonicalOptions {
+    pub(crate) duration_unit: DurationUnit,
+    pub(crate) timestamp_unit: TimestampUnit,
+    pub(crate) case_insensitive_keys: bool,
+    pub(crate) sort_map_keys: bool,
+    pub(crate) floats_as_numbers: bool,
+    pub(crate) unwrap_message_name: bool,
+    pub(crate) enum_as_number: bool,
+    pub(crate) enum_as_quoted_number: bool,
+    pub(crate) prefer_string_numbers: bool,
+    pub(crate) empty_repeated_as_null: bool,
+    pub(crate) lenient_float_tokens: bool,
+    pub(crate) reject_null_oneof_members: bool,
+    pub(crate) null_message_as_default: bool,
+    pub(crate) value_non_finite: NonFiniteValue,
+    pub(crate) relaxed_timestamp_bounds: bool,
+    pub(crate) min_timestamp: Option<prost_types::Timestamp>,
+    pub(crate) max_timestamp: Option<prost_types::Timestamp>,
+    pub(crate) max_duration: Option<prost_types::Duration>,
+}
+
+impl CanonicalOptions {
+    /// Returns the canonical defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how `google.protobuf.Duration` values are encoded.
+    #[must_use]
+    pub fn duration_as(mut self, unit: DurationUnit) -> Self {
+        self.duration_unit = unit;
+        self
+    }
+
+    /// Sets how `google.protobuf.Timestamp` values are encoded.
+    ///
+    /// With an epoch unit, the canonical RFC 3339 string is still accepted
+    /// on input.
+    #[must_use]
+    pub fn timestamp_as(mut self, unit: TimestampUnit) -> Self {
+        self.timestamp_unit = unit;
+        self
+    }
+
+    /// Matches message field names without regard to ASCII case on input.
+    ///
+    /// Exact matches are always preferred, so this only changes which inputs
+    /// are accepted, never how a canonical document is read.
+    #[must_use]
+    pub fn case_insensitive_keys(mut self, enabled: bool) -> Self {
+        self.case_insensitive_keys = enabled;
+        self
+    }
+
+    /// Emits `HashMap` fields with their entries sorted by key.
+    ///
+    /// Keys are compared by their JSON text, so integer key `10` comes
+    /// before `2`. `BTreeMap` and `IndexMap` fields keep their own order.
+    /// This covers derived map fields and `CanonicalMapFieldRef`; a plain
+    /// `CanonicalMapRef` always keeps the map's own order.
+    #[must_use]
+    pub fn sort_map_keys(mut self, enabled: bool) -> Self {
+        self.sort_map_keys = enabled;
+        self
+    }
+
+    /// Emits finite `float`/`double` values as JSON numbers rather than
+    /// strings, so a serializer's formatter controls their rendering.
+    #[must_use]
+    pub fn floats_as_numbers(mut self, enabled: bool) -> Self {
+        self.floats_as_numbers = enabled;
+        self
+    }
+
+    /// Accepts a message wrapped in a single key naming its type, such as
+    /// `{"KitchenSink": {...}}`, in addition to the bare object.
+    ///
+    /// The name is the struct name unless overridden with the derive's
+    /// `message_name` attribute. The check applies at every message level, so
+    /// a nested field such as `{"nested": {"Nested": {...}}}` is unwrapped
+    /// too. Once inside a wrapper the option is off, and messages within it
+    /// are read as bare objects.
+    #[must_use]
+    pub fn unwrap_message_name(mut self, enabled: bool) -> Self {
+        self.unwrap_message_name = enabled;
+        self
+    }
+
+    /// Emits every enum value as its JSON number instead of its name, as the
+    /// derive's `enum_numbers` attribute does for a single enum type.
+    ///
+    /// This covers enum fields in every shape: singular, repeated, and map
+    /// values. Map keys are never enums, so they are unaffected. Names and
+    /// numbers are both accepted on input either way.
+    #[must_use]
+    pub fn enum_as_number(mut self, enabled: bool) -> Self {
+        self.enum_as_number = enabled;
+        self
+    }
+
+    /// Emits enum values as quoted numbers such as `"2"` instead of their
+    /// names, and accepts quoted numbers for every enum on input.
+    #[must_use]
+    pub fn enum_as_quoted_number(mut self, enabled: bool) -> Self {
+        self.enum_as_quoted_number = enabled;
+        self
+    }
+
+    /// Reads JSON numbers inside `google.protobuf.Value` (and so `Struct`
+    /// and `ListValue`) as `string_value`s instead of `number_value`s.
+    ///
+    /// A `number_value` is an `f64`, so integers beyond 2^53 cannot be stored
+    /// exactly and are rejected. In this mode integers that fit in an `i64`
+    /// or `u64` keep every digit. Other numbers, including larger integers,
+    /// are read as an `f64` and kept as the text a `double` field holding it
+    /// is written as, such as `"0.1"`, `"100"` or `"1e+300"`.
+    #[must_use]
+    pub fn prefer_string_numbers(mut self, enabled: bool) -> Self {
+        self.prefer_string_numbers = enabled;
+        self
+    }
+
+    /// Emits empty repeated and map fields as `null` instead of omitting them,
+    /// for consumers that expect every such key to be present.
+    ///
+    /// `null` is read back as an empty field either way.
+    #[must_use]
+    pub fn empty_repeated_as_null(mut self, enabled: bool) -> Self {
+        self.empty_repeated_as_null = enabled;
+        self
+    }
+
+    /// Accepts non-canonical spellings of the special float values on input:
+    /// `nan`, `inf`, and `infinity` in any ASCII case, with an optional sign
+    /// on the infinities (so `"+Infinity"` is accepted too).
+    ///
+    /// Output always uses the canonical `"NaN"`, `"Infinity"`, and
+    /// `"-Infinity"`.
+    #[must_use]
+    pub fn lenient_float_tokens(mut self, enabled: bool) -> Self {
+        self.lenient_float_tokens = enabled;
+        self
+    }
+
+    /// Rejects an explicit `null` for a oneof member on input.
+    ///
+    /// By default `{"member": null}` is read like an absent key and leaves
+    /// the oneof unset. Under a strict reading of the spec a member cannot be
+    /// "set to null", so this reports an error for the member instead.
+    #[must_use]
+    pub fn reject_null_oneof_members(mut self, enabled: bool) -> Self {
+        self.reject_null_oneof_members = enabled;
+        self
+    }
+
+    /// Reads a JSON `null` in place of a message as the default message.
+    ///
+    /// A message field that is `null` is already read as unset. Elsewhere,
+    /// such as at the top level, as a repeated element, or as a map value,
+    /// `null` is not a valid message and conformance requires rejecting it;
+    /// this accepts it instead, for producers that write `null` for an empty
+    /// message.
+    #[must_use]
+    pub fn null_message_as_default(mut self, enabled: bool) -> Self {
+        self.null_message_as_default = enabled;
+        self
+    }
+
+    /// Sets how a non-finite `google.protobuf.Value` number is written.
+    ///
+    /// Such a `Value` has no JSON representation, so by default serializing
+    /// it fails. The fallbacks are meant for best-effort output such as
+    /// logging, and are not read back as numbers.
+    #[must_use]
+    pub fn value_non_finite_as(mut self, fallback: NonFiniteValue) -> Self {
+        self.value_non_finite = fallback;
+        self
+    }
+
+    /// Lets RFC 3339 `Timestamp` values outside the canonical range survive
+    /// a round trip, such as sentinels before year 1 or with `nanos` outside
+    /// `0..1_000_000_000`.
+    ///
+    /// Timestamps the canonical string can represent are still written that
+    /// way. Any other value is written as its raw fields,
+    /// `{"seconds": "<int64>", "nanos": <int32>}`, which is also accepted on
+    /// input in this mode. The epoch units are unaffected.
+    #[must_use]
+    pub fn relaxed_timestamp_bounds(mut self, enabled: bool) -> Self {
+        self.relaxed_timestamp_bounds = enabled;
+        self
+    }
+
+    /// Rejects parsed timestamps earlier than `min`, on top of the canonical
+    /// year 1 to 9999 range.
+    #[must_use]
+    pub fn min_timestamp(mut self, min: prost_types::Timestamp) -> Self {
+        self.min_timestamp = Some(min);
+        self
+    }
+
+    /// Rejects parsed timestamps later than `max`, on top of the canonical
+    /// year 1 to 9999 range.
+    #[must_use]
+    pub fn max_timestamp(mut self, max: prost_types::Timestamp) -> Self {
+        self.max_timestamp = Some(max);
+        self
+    }
+
+    /// Rejects parsed durations whose magnitude exceeds that of `max`, on top
+    /// of the canonical 10,000 year range.
+    #[must_use]
+    pub fn max_duration(mut self, max: prost_types::Duration) -> Self {
+        self.max_duration = Some(max);
+        self
+    }
+}
+
+/// Encoding used for `google.protobuf.Duration` values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DurationUnit {
+    /// The canonical string form, such as `"1.5s"`.
+    #[default]
+    Seconds,
+    /// A JSON number of milliseconds, such as `1500`.
+    Milliseconds,
+    /// A JSON number of microseconds.
+    Microseconds,
+    /// A JSON number of nanoseconds.
+    Nanoseconds,
+}
+
+/// Encoding used for `google.protobuf.Timestamp` values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampUnit {
+    /// The canonical RFC 3339 string, such as `"1970-01-01T00:00:01.500Z"`.
+    #[default]
+    Rfc3339,
+    /// A JSON number of seconds since the Unix epoch, such as `1.5`.
+    EpochSeconds,
+    /// A JSON number of milliseconds since the Unix epoch, such as `1500`.
+    EpochMilliseconds,
+}
+
+/// Output for a `google.protobuf.Value` holding `NaN` or an infinity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonFiniteValue {
+    /// Fail serialization, as the canonical mapping requires.
+    #[default]
+    Error,
+    /// Write `null`.
+    Null,
+    /// Write `"NaN"`, `"Infinity"`, or `"-Infinity"`, like a `double` field.
+    String,
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static CURRENT: Cell<CanonicalOptions> = Cell::new(CanonicalOptions::default());
+    /// Whether `CURRENT` holds anything but the defaults, so the common case
+    /// reads one flag instead of copying the whole struct.
+    static CUSTOMIZED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` with `options` applied to canonical serialization on this thread.
+///
+/// The previous options are restored when `f` returns or unwinds. Only work
+/// done synchronously inside `f` sees the options: a `Canonical` that is
+/// serialized after `f` returns, or on another thread or task, gets whatever
+/// options are installed at that point. Use `Canonical::with_options` or
+/// `CanonicalSeed` to keep options with a value instead.
+#[cfg(feature = "std")]
+pub fn with_options<R>(options: CanonicalOptions, f: impl FnOnce() -> R) -> R {
+    struct Restore(CanonicalOptions, bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CURRENT.with(|current| current.set(self.0));
+            CUSTOMIZED.with(|customized| customized.set(self.1));
+        }
+    }
+
+    let customized = options != CanonicalOptions::default();
+    let _restore = Restore(
+        CURRENT.with(|current| current.replace(options)),
+        CUSTOMIZED.with(|flag| flag.replace(customized)),
+    );
+    f()
+}
+
+/// Returns the options installed for the current thread.
+#[cfg(feature = "std")]
+#[inline]
+pub(crate) fn current() -> CanonicalOptions {
+    if CUSTOMIZED.with(Cell::get) {
+        CURRENT.with(Cell::get)
+    } else {
+        CanonicalOptions::default()
+    }
+}
+
+/// Returns the options installed for the current thread.
+#[cfg(not(feature = "std"))]
+pub(crate) fn current() -> CanonicalOptions {
+    CanonicalOptions::default()
+}
+
+/// The options a derived message visitor consults, read once per message.
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub struct MessageOptions {
+    pub case_insensitive_keys: bool,
+    pub unwrap_message_name: bool,
+    pub null_message_as_default: bool,
+}
+
+/// Returns the options a derived message visitor consults.
+#[doc(hidden)]
+pub fn message_options() -> MessageOptions {
+    let options = current();
+    MessageOptions {
+        case_insensitive_keys: options.case_insensitive_keys,
+        unwrap_message_name: options.unwrap_message_name,
+        null_message_as_default: options.null_message_as_default,
+    }
+}
+
+/// Maps `key` onto one of a message's field names when `case_insensitive` is
+/// set; otherwise returns `key` unchanged.
+#[doc(hidden)]
+pub fn resolve_field_key<'k>(
+    key: &'k str,
+    names: &[&[&'static str]],
+    case_insensitive: bool,
+) -> &'k str {
+    if !case_insensitive {
+        return key;
+    }
+    let mut candidates = names.iter().flat_map(|names| names.iter());
+    if candidates.clone().any(|name| *name == key) {
+        return key;
+    }
+    candidates
+        .find(|name| name.eq_ignore_ascii_case(key))
+        .map_or(key, |name| name)
+}
+
+/// Whether an empty repeated or map field should be emitted as `null`.
+#[doc(hidden)]
+pub fn empty_repeated_as_null() -> bool {
+    current().empty_repeated_as_null
+}
+
+/// Whether an explicit `null` for a oneof member is an error.
+#[doc(hidden)]
+pub fn reject_null_oneof_members() -> bool {
+    current().reject_null_oneof_members
+}
+
+/// Reads the value under the key naming the message's type, which the
+/// `unwrap_message_name` option accepts in place of the bare object, as the
+/// whole `message`.
+///
+/// When `merge` is set the value is merged onto `message` instead of
+/// replacing it.
+#[doc(hidden)]
+pub fn unwrap_message_name<'de, T, A>(
+    message_name: &str,
+    message: &mut T,
+    merge: bool,
+    map: &mut A,
+) -> Result<(), A::Error>
+where
+    T: CanonicalDeserialize,
+    A: de::MapAccess<'de>,
+{
+    let mut read = |map: &mut A| {
+        if merge {
+            map.next_value_seed(Onto(&mut *message))
+        } else {
+            map.next_value::<CanonicalValue<T>>()
+                .map(|value| *message = value.0)
+        }
+    };
+    #[cfg(feature = "std")]
+    with_options(current().unwrap_message_name(false), || read(map))?;
+    #[cfg(not(feature = "std"))]
+    read(map)?;
+    if map.next_key::<de::IgnoredAny>()?.is_some() {
+        return Err(de::Error::custom(format_args!(
+            "expected `{message_name}` to be the only key"
+        )));
+    }
+    Ok(())
+}
+
+/// Merges a value onto the message it points to.
+struct Onto<'a, T>(&'a mut T);
+
+impl<'de, T: CanonicalDeserialize> de::DeserializeSeed<'de> for Onto<'_, T> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        self.0.__merge_canonical(deserializer)
+    }
+}
@@ -0,0 +1,537 @@
+use alloc::string::ToString;
+use core::cell::Cell;
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de;
+
+use crate::CanonicalDeserialize;
+
+/// One step of the JSON path to a value.
+#[derive(Clone, Copy)]
+pub(crate) enum PathSegment<'a> {
+    /// A message field, by its JSON name.
+    Field(&'a str),
+    /// An element of a repeated field.
+    Index(usize),
+    /// An entry of a map field, by its JSON key.
+    Key(&'a str),
+}
+
+impl fmt::Display for PathSegment<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Field(name)
+                if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                f.write_str(name)
+            }
+            // Quoted, so a name holding `.`, `[` or `"` cannot be mistaken for
+            // more than one segment.
+            Self::Field(name) | Self::Key(name) => write!(f, "[{name:?}]"),
+            Self::Index(index) => write!(f, "[{index}]"),
+        }
+    }
+}
+
+/// Starts the message of an error that names the path it occurred at.
+const PATH_PREFIX: &str = "error in field \"";
+
+/// Prefixes `err` with one more segment of the JSON path it occurred at.
+///
+/// Errors that already carry a path are merged into a single one, such as
+/// `nested.items[2]["key"]`. Only the segment is inserted; the rest of the
+/// message is kept as it is.
+pub(crate) fn with_path_segment<E: de::Error>(segment: PathSegment<'_>, err: &E) -> E {
+    let message = err.to_string();
+    match message.strip_prefix(PATH_PREFIX) {
+        // Bare field names never start with `[`, so a bracketed segment
+        // follows directly and anything else after a `.`.
+        Some(rest) if rest.starts_with('[') => {
+            E::custom(format_args!("{PATH_PREFIX}{segment}{rest}"))
+        }
+        Some(rest) => E::custom(format_args!("{PATH_PREFIX}{segment}.{rest}")),
+        None => E::custom(format_args!("{PATH_PREFIX}{segment}\": {message}")),
+    }
+}
+
+/// Raises `err`, from a message flattened into its parent at `segment`, as an
+/// error of the parent.
+///
+/// The flattened message's keys sit directly in the parent, so an error that
+/// already names one of them keeps its path; any other error is placed under
+/// `segment`.
+pub(crate) fn with_flattened_segment<E: de::Error>(
+    segment: PathSegment<'_>,
+    err: &impl fmt::Display,
+) -> E {
+    let err = E::custom(err);
+    if is_path_error(&err) {
+        err
+    } else {
+        with_path_segment(segment, &err)
+    }
+}
+
+/// Deserializes a `T` that sits under `segment` of the enclosing value,
+/// naming that segment in the errors `T`'s visitors raise.
+///
+/// Errors the deserializer raises on its own, such as syntax errors or an
+/// unexpected end of input, are passed through untouched so callers can still
+/// classify them.
+#[doc(hidden)]
+pub struct PathSeed<'a, T> {
+    segment: PathSegment<'a>,
+    marker: PhantomData<T>,
+}
+
+impl<'a, T> PathSeed<'a, T> {
+    fn new(segment: PathSegment<'a>) -> Self {
+        Self {
+            segment,
+            marker: PhantomData,
+        }
+    }
+
+    /// Reads the message field with JSON name `name`.
+    pub fn field(name: &'a str) -> Self {
+        Self::new(PathSegment::Field(name))
+    }
+
+    /// Reads element `index` of a repeated field.
+    pub fn index(index: usize) -> Self {
+        Self::new(PathSegment::Index(index))
+    }
+
+    /// Reads the map entry with JSON key `key`.
+    pub fn key(key: &'a str) -> Self {
+        Self::new(PathSegment::Key(key))
+    }
+}
+
+impl<'de, T: de::Deserialize<'de>> de::DeserializeSeed<'de> for PathSeed<'_, T> {
+    type Value = T;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserialize_at(self.segment, PhantomData, deserializer)
+    }
+}
+
+/// Merges the value of a message field onto the message it already holds,
+/// naming the field in the errors the message's visitors raise.
+///
+/// Yields `false` without touching the message when the value is `null`, so
+/// the caller can reset the field.
+#[doc(hidden)]
+pub struct MergeSeed<'a, T> {
+    target: &'a mut T,
+    segment: PathSegment<'a>,
+}
+
+impl<'a, T> MergeSeed<'a, T> {
+    /// Merges onto `target` the value of the message field with JSON name
+    /// `name`.
+    pub fn field(target: &'a mut T, name: &'a str) -> Self {
+        Self {
+            target,
+            segment: PathSegment::Field(name),
+        }
+    }
+}
+
+impl<'de, T: CanonicalDeserialize> de::DeserializeSeed<'de> for MergeSeed<'_, T> {
+    type Value = bool;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserialize_at(self.segment, MergeVisitor(self.target), deserializer)
+    }
+}
+
+struct MergeVisitor<'a, T>(&'a mut T);
+
+impl<'de, T: CanonicalDeserialize> de::DeserializeSeed<'de> for MergeVisitor<'_, T> {
+    type Value = bool;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de, T: CanonicalDeserialize> de::Visitor<'de> for MergeVisitor<'_, T> {
+    type Value = bool;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("map or null")
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(false)
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(false)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        self.0.__merge_canonical(deserializer).map(|()| true)
+    }
+}
+
+/// Reads `seed` from a value that sits under `segment` of the enclosing
+/// value, naming that segment in the errors its visitors raise.
+fn deserialize_at<'de, S, D>(
+    segment: PathSegment<'_>,
+    seed: S,
+    deserializer: D,
+) -> Result<S::Value, D::Error>
+where
+    S: de::DeserializeSeed<'de>,
+    D: de::Deserializer<'de>,
+{
+    let origin = Cell::new(Origin::Unknown);
+    seed.deserialize(Tracked {
+        inner: deserializer,
+        origin: &origin,
+    })
+    .map_err(|err| match origin.get() {
+        Origin::Deserializer => err,
+        Origin::Nested if !is_path_error(&err) => err,
+        Origin::Unknown | Origin::Visitor | Origin::Nested => with_path_segment(segment, &err),
+    })
+}
+
+/// Whether `err` already names the field it occurred in, as errors raised
+/// below another `PathSeed` do.
+fn is_path_error<E: de::Error>(err: &E) -> bool {
+    err.to_string().starts_with(PATH_PREFIX)
+}
+
+/// Where the first error seen while deserializing a value came from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    /// No error was seen, so it was raised after the deserializer finished.
+    Unknown,
+    /// A visitor returned it, or the deserializer built it from a visitor's
+    /// expectation, as for an invalid type.
+    Visitor,
+    /// The deserializer raised it without consulting a visitor.
+    Deserializer,
+    /// It surfaced while reading an element, entry or variant of the value.
+    Nested,
+}
+
+/// Forwards to `inner`, recording in `origin` where an error came from.
+struct Tracked<'a, T> {
+    inner: T,
+    origin: &'a Cell<Origin>,
+}
+
+impl<'a, T> Tracked<'a, T> {
+    fn wrap<U>(&self, inner: U) -> Tracked<'a, U> {
+        Tracked {
+            inner,
+            origin: self.origin,
+        }
+    }
+}
+
+/// Records `source` as the origin of the error, unless an earlier step
+/// already claimed it.
+fn note(origin: &Cell<Origin>, source: Origin) {
+    if origin.get() == Origin::Unknown {
+        origin.set(source);
+    }
+}
+
+/// Passes `result` through, noting `source` as the origin if it failed.
+fn record<V, E>(origin: &Cell<Origin>, source: Origin, result: Result<V, E>) -> Result<V, E> {
+    if result.is_err() {
+        note(origin, source);
+    }
+    result
+}
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: de::Visitor<'de>,
+            {
+                let visitor = self.wrap(visitor);
+                let result = self.inner.$method($($arg,)* visitor);
+                record(self.origin, Origin::Deserializer, result)
+            }
+        )*
+    };
+}
+
+impl<'de, D: de::Deserializer<'de>> de::Deserializer<'de> for Tracked<'_, D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty);)*) => {
+        $(
+            fn $method<E>(self, value: $ty) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                let result = self.inner.$method(value);
+                record(self.origin, Origin::Visitor, result)
+            }
+        )*
+    };
+}
+
+impl<'de, V: de::Visitor<'de>> de::Visitor<'de> for Tracked<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Deserializers consult the expectation only to build an error, such
+        // as an invalid type, on the visitor's behalf.
+        note(self.origin, Origin::Visitor);
+        self.inner.expecting(formatter)
+    }
+
+    forward_visit! {
+        visit_bool(bool);
+        visit_i8(i8);
+        visit_i16(i16);
+        visit_i32(i32);
+        visit_i64(i64);
+        visit_i128(i128);
+        visit_u8(u8);
+        visit_u16(u16);
+        visit_u32(u32);
+        visit_u64(u64);
+        visit_u128(u128);
+        visit_f32(f32);
+        visit_f64(f64);
+        visit_char(char);
+        visit_str(&str);
+        visit_borrowed_str(&'de str);
+        visit_string(alloc::string::String);
+        visit_bytes(&[u8]);
+        visit_borrowed_bytes(&'de [u8]);
+        visit_byte_buf(alloc::vec::Vec<u8>);
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let result = self.inner.visit_none();
+        record(self.origin, Origin::Visitor, result)
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let result = self.inner.visit_unit();
+        record(self.origin, Origin::Visitor, result)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let deserializer = self.wrap(deserializer);
+        let result = self.inner.visit_some(deserializer);
+        record(self.origin, Origin::Visitor, result)
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let deserializer = self.wrap(deserializer);
+        let result = self.inner.visit_newtype_struct(deserializer);
+        record(self.origin, Origin::Visitor, result)
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let seq = self.wrap(seq);
+        let result = self.inner.visit_seq(seq);
+        record(self.origin, Origin::Visitor, result)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        let map = self.wrap(map);
+        let result = self.inner.visit_map(map);
+        record(self.origin, Origin::Visitor, result)
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::EnumAccess<'de>,
+    {
+        let data = self.wrap(data);
+        let result = self.inner.visit_enum(data);
+        record(self.origin, Origin::Visitor, result)
+    }
+}
+
+impl<'de, A: de::SeqAccess<'de>> de::SeqAccess<'de> for Tracked<'_, A> {
+    type Error = A::Error;
+
+    fn next_element_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, Self::Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        let result = self.inner.next_element_seed(seed);
+        record(self.origin, Origin::Nested, result)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, A: de::MapAccess<'de>> de::MapAccess<'de> for Tracked<'_, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        let result = self.inner.next_key_seed(seed);
+        record(self.origin, Origin::Nested, result)
+    }
+
+    fn next_value_seed<S>(&mut self, seed: S) -> Result<S::Value, Self::Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        let result = self.inner.next_value_seed(seed);
+        record(self.origin, Origin::Nested, result)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, 'de, A: de::EnumAccess<'de>> de::EnumAccess<'de> for Tracked<'a, A> {
+    type Error = A::Error;
+    type Variant = Tracked<'a, A::Variant>;
+
+    fn variant_seed<S>(self, seed: S) -> Result<(S::Value, Self::Variant), Self::Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        let origin = self.origin;
+        let result = self.inner.variant_seed(seed);
+        record(origin, Origin::Nested, result).map(|(value, variant)| {
+            (
+                value,
+                Tracked {
+                    inner: variant,
+                    origin,
+                },
+            )
+        })
+    }
+}
+
+impl<'de, A: de::VariantAccess<'de>> de::VariantAccess<'de> for Tracked<'_, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        let result = self.inner.unit_variant();
+        record(self.origin, Origin::Nested, result)
+    }
+
+    fn newtype_variant_seed<S>(self, seed: S) -> Result<S::Value, Self::Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        let result = self.inner.newtype_variant_seed(seed);
+        record(self.origin, Origin::Nested, result)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let visitor = self.wrap(visitor);
+        let result = self.inner.tuple_variant(len, visitor);
+        record(self.origin, Origin::Nested, result)
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let visitor = self.wrap(visitor);
+        let result = self.inner.struct_variant(fields, visitor);
+        record(self.origin, Origin::Nested, result)
+    }
+}
@@ -1,7 +1,12 @@
+#[cfg(feature = "bytes-base64")]
+use alloc::format;
 use alloc::string::String;
 use alloc::string::ToString;
+#[cfg(feature = "bytes-base64")]
 use alloc::vec::Vec;
+#[cfg(feature = "bytes-base64")]
 use base64::Engine;
+#[cfg(feature = "bytes-base64")]
 use base64::prelude::BASE64_STANDARD;
 use core::fmt;
 use serde::{Deserialize, Deserializer, Serializer, de};
@@ -10,9 +15,15 @@ use super::number::{
     f32_from_f64, f32_from_i64_exact, f32_from_u64_exact, f64_from_i64_exact, f64_from_u64_exact,
     i32_from_f64, i32_from_str, i64_from_f64, i64_from_str, parse_float, serialize_float32,
     serialize_float64, u32_from_f64, u32_from_str, u64_from_f64, u64_from_str,
+    visit_arbitrary_precision,
 };
+#[cfg(feature = "bytes-base64")]
+use super::wrappers::vec_for_access;
 use crate::{CanonicalDeserialize, CanonicalSerialize};
 
+/// Error for a JSON boolean where an integer or float field is expected.
+const BOOL_FOR_NUMBER: &str = "expected number or numeric string, found boolean";
+
 impl CanonicalSerialize for bool {
     fn serialize_canonical<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -47,7 +58,7 @@ impl CanonicalDeserialize for i32 {
     {
         struct Visitor;
 
-        impl de::Visitor<'_> for Visitor {
+        impl<'de> de::Visitor<'de> for Visitor {
             type Value = i32;
 
             fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -95,6 +106,20 @@ impl CanonicalDeserialize for i32 {
             {
                 self.visit_str(&value)
             }
+
+            fn visit_bool<Err>(self, _value: bool) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                Err(Err::custom(BOOL_FOR_NUMBER))
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                visit_arbitrary_precision(self, map)
+            }
         }
 
         deserializer.deserialize_any(Visitor)
@@ -117,7 +142,7 @@ impl CanonicalDeserialize for u32 {
     {
         struct Visitor;
 
-        impl de::Visitor<'_> for Visitor {
+        impl<'de> de::Visitor<'de> for Visitor {
             type Value = u32;
 
             fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -168,6 +193,20 @@ impl CanonicalDeserialize for u32 {
             {
                 self.visit_str(&value)
             }
+
+            fn visit_bool<Err>(self, _value: bool) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                Err(Err::custom(BOOL_FOR_NUMBER))
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                visit_arbitrary_precision(self, map)
+            }
         }
 
         deserializer.deserialize_any(Visitor)
@@ -190,7 +229,7 @@ impl CanonicalDeserialize for i64 {
     {
         struct Visitor;
 
-        impl de::Visitor<'_> for Visitor {
+        impl<'de> de::Visitor<'de> for Visitor {
             type Value = i64;
 
             fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -231,6 +270,20 @@ impl CanonicalDeserialize for i64 {
             {
                 self.visit_str(&value)
             }
+
+            fn visit_bool<Err>(self, _value: bool) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                Err(Err::custom(BOOL_FOR_NUMBER))
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                visit_arbitrary_precision(self, map)
+            }
         }
 
         deserializer.deserialize_any(Visitor)
@@ -253,7 +306,7 @@ impl CanonicalDeserialize for u64 {
     {
         struct Visitor;
 
-        impl de::Visitor<'_> for Visitor {
+        impl<'de> de::Visitor<'de> for Visitor {
             type Value = u64;
 
             fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -297,6 +350,20 @@ impl CanonicalDeserialize for u64 {
             {
                 self.visit_str(&value)
             }
+
+            fn visit_bool<Err>(self, _value: bool) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                Err(Err::custom(BOOL_FOR_NUMBER))
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                visit_arbitrary_precision(self, map)
+            }
         }
 
         deserializer.deserialize_any(Visitor)
@@ -319,7 +386,7 @@ impl CanonicalDeserialize for f32 {
     {
         struct Visitor;
 
-        impl de::Visitor<'_> for Visitor {
+        impl<'de> de::Visitor<'de> for Visitor {
             type Value = f32;
 
             fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -364,6 +431,20 @@ impl CanonicalDeserialize for f32 {
             {
                 self.visit_str(&value)
             }
+
+            fn visit_bool<Err>(self, _value: bool) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                Err(Err::custom(BOOL_FOR_NUMBER))
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                visit_arbitrary_precision(self, map)
+            }
         }
 
         deserializer.deserialize_any(Visitor)
@@ -386,7 +467,7 @@ impl CanonicalDeserialize for f64 {
     {
         struct Visitor;
 
-        impl de::Visitor<'_> for Visitor {
+        impl<'de> de::Visitor<'de> for Visitor {
             type Value = f64;
 
             fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -430,6 +511,20 @@ impl CanonicalDeserialize for f64 {
             {
                 self.visit_str(&value)
             }
+
+            fn visit_bool<Err>(self, _value: bool) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                Err(Err::custom(BOOL_FOR_NUMBER))
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                visit_arbitrary_precision(self, map)
+            }
         }
 
         deserializer.deserialize_any(Visitor)
@@ -450,10 +545,50 @@ impl CanonicalDeserialize for String {
     where
         D: Deserializer<'de>,
     {
+        // `String`'s own visitor already copies straight out of borrowed
+        // input, so the single allocation for the result is all it costs.
         String::deserialize(deserializer)
     }
 }
 
+/// Deserializes a string and hands it to `parse` without taking ownership.
+///
+/// Borrowed input (and a deserializer's scratch buffer) is parsed in place,
+/// so types decoded from a string never allocate an intermediate `String`.
+pub(crate) fn deserialize_str_with<'de, D, T, E, F>(
+    deserializer: D,
+    parse: F,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    E: fmt::Display,
+    F: FnOnce(&str) -> Result<T, E>,
+{
+    struct Visitor<F>(F);
+
+    impl<T, E, F> de::Visitor<'_> for Visitor<F>
+    where
+        E: fmt::Display,
+        F: FnOnce(&str) -> Result<T, E>,
+    {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a string")
+        }
+
+        fn visit_str<Err>(self, value: &str) -> Result<Self::Value, Err>
+        where
+            Err: de::Error,
+        {
+            (self.0)(value).map_err(Err::custom)
+        }
+    }
+
+    deserializer.deserialize_str(Visitor(parse))
+}
+
+#[cfg(feature = "bytes-base64")]
 impl CanonicalSerialize for Vec<u8> {
     fn serialize_canonical<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -464,14 +599,97 @@ impl CanonicalSerialize for Vec<u8> {
     }
 }
 
+#[cfg(feature = "bytes-base64")]
 impl CanonicalDeserialize for Vec<u8> {
     fn deserialize_canonical<'de, D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let value = String::deserialize(deserializer)?;
-        BASE64_STANDARD
-            .decode(value.as_bytes())
-            .map_err(de::Error::custom)
+        deserialize_str_with(deserializer, decode_base64::<D::Error>)
+    }
+}
+
+/// Fixed-width `bytes`, such as a 32-byte hash. The decoded length must
+/// match `N` exactly.
+#[cfg(feature = "bytes-base64")]
+impl<const N: usize> CanonicalSerialize for [u8; N] {
+    fn serialize_canonical<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let encoded = BASE64_STANDARD.encode(self);
+        serializer.serialize_str(&encoded)
+    }
+}
+
+#[cfg(feature = "bytes-base64")]
+impl<const N: usize> CanonicalDeserialize for [u8; N] {
+    fn deserialize_canonical<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize_str_with(deserializer, |value| {
+            let bytes = decode_base64::<D::Error>(value)?;
+            <[u8; N]>::try_from(bytes.as_slice()).map_err(|_| {
+                <D::Error as de::Error>::custom(format!(
+                    "expected {N} bytes, found {}",
+                    bytes.len()
+                ))
+            })
+        })
+    }
+}
+
+#[cfg(feature = "bytes-base64")]
+fn decode_base64<Err: de::Error>(value: &str) -> Result<Vec<u8>, Err> {
+    // Surrounding whitespace cannot be part of valid base64, so trimming
+    // it never changes the meaning of a well-formed value.
+    BASE64_STANDARD
+        .decode(value.trim_ascii().as_bytes())
+        .map_err(de::Error::custom)
+}
+
+/// Deserializes `bytes` from canonical base64 or, leniently, from a JSON
+/// array of byte values such as `[0, 1, 255]`.
+///
+/// Used by the derive's `accept_byte_array` field attribute.
+#[cfg(feature = "bytes-base64")]
+pub struct CanonicalBytesOrArray(pub Vec<u8>);
+
+#[cfg(feature = "bytes-base64")]
+impl<'de> Deserialize<'de> for CanonicalBytesOrArray {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = CanonicalBytesOrArray;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("base64 string or array of bytes")
+            }
+
+            fn visit_str<Err>(self, value: &str) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                decode_base64(value).map(CanonicalBytesOrArray)
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut bytes = vec_for_access(&seq);
+                while let Some(byte) = seq.next_element::<u8>()? {
+                    bytes.push(byte);
+                }
+                Ok(CanonicalBytesOrArray(bytes))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
     }
 }
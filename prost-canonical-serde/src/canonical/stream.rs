@@ -0,0 +1,372 @@
+use alloc::format;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use std::io::{self, Read as _};
+
+use serde::de::{self, Error as _};
+use serde_json::error::Category;
+
+use super::wrappers::CanonicalValue;
+use crate::CanonicalDeserialize;
+
+/// Reads a top-level JSON array one canonical element at a time.
+///
+/// Only the bytes of the element currently being decoded are buffered, so
+/// large arrays can be processed without loading the whole payload. Wrap
+/// unbuffered readers such as files or sockets in a `BufReader`.
+///
+/// Errors are [`CanonicalStreamError`]s, whose positions count from the start
+/// of the stream. After an error the iterator ends.
+pub struct CanonicalArrayStream<R, T> {
+    bytes: io::Bytes<R>,
+    peeked: Option<u8>,
+    state: StreamState,
+    buffer: Vec<u8>,
+    /// Position of the last byte read.
+    position: Position,
+    /// Position of the byte just before `buffer`.
+    element_start: Position,
+    _marker: PhantomData<T>,
+}
+
+/// A one-based line and column, counted in bytes as `serde_json` does.
+#[derive(Clone, Copy)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    /// Where a position reported by `serde_json` for a parse that started
+    /// just after `self` lies in the stream, when the parsed input had
+    /// `prefix` bytes on its first line before the stream's bytes.
+    fn offset(self, line: usize, column: usize, prefix: usize) -> Self {
+        if line <= 1 {
+            Self {
+                line: self.line,
+                column: (self.column + column).saturating_sub(prefix),
+            }
+        } else {
+            Self {
+                line: self.line + line - 1,
+                column,
+            }
+        }
+    }
+}
+
+/// Error returned by [`CanonicalArrayStream`].
+///
+/// Keeps the message and category `serde_json` gives the failure, with the
+/// position counted from the start of the stream rather than of the element
+/// that failed.
+#[derive(Debug)]
+pub struct CanonicalStreamError {
+    inner: serde_json::Error,
+    /// Zero when the error has no position, as for I/O errors.
+    line: usize,
+    column: usize,
+}
+
+impl CanonicalStreamError {
+    fn at(inner: serde_json::Error, position: Position) -> Self {
+        Self {
+            inner,
+            line: position.line,
+            column: position.column,
+        }
+    }
+
+    /// A failure to read the stream, which has no position.
+    fn io(err: io::Error) -> Self {
+        Self {
+            inner: serde_json::Error::io(err),
+            line: 0,
+            column: 0,
+        }
+    }
+
+    /// An error from parsing bytes of the stream that followed `start`, with
+    /// `prefix` extra bytes placed before them on the first line.
+    fn relative(inner: serde_json::Error, start: Position, prefix: usize) -> Self {
+        let position = start.offset(inner.line(), inner.column(), prefix);
+        Self::at(inner, position)
+    }
+
+    /// One-based line of the error in the stream, or 0 if it has no position.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// One-based column of the error in the stream, counted in bytes, or 0 if
+    /// it has no position.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// The category `serde_json` gives the error.
+    pub fn classify(&self) -> Category {
+        self.inner.classify()
+    }
+
+    /// Whether reading the underlying stream failed.
+    pub fn is_io(&self) -> bool {
+        self.inner.is_io()
+    }
+
+    /// Whether the input is not syntactically valid JSON.
+    pub fn is_syntax(&self) -> bool {
+        self.inner.is_syntax()
+    }
+
+    /// Whether the input is valid JSON but not a valid canonical array.
+    pub fn is_data(&self) -> bool {
+        self.inner.is_data()
+    }
+
+    /// Whether the stream ended before the array did.
+    pub fn is_eof(&self) -> bool {
+        self.inner.is_eof()
+    }
+}
+
+impl fmt::Display for CanonicalStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self.inner.to_string();
+        let suffix = format!(
+            " at line {} column {}",
+            self.inner.line(),
+            self.inner.column()
+        );
+        let message = match self.inner.line() {
+            0 => &message[..],
+            _ => message.strip_suffix(&suffix).unwrap_or(&message),
+        };
+        if self.line == 0 {
+            f.write_str(message)
+        } else {
+            write!(f, "{message} at line {} column {}", self.line, self.column)
+        }
+    }
+}
+
+impl std::error::Error for CanonicalStreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StreamState {
+    Start,
+    Elements,
+    Done,
+}
+
+impl<R: io::BufRead, T> CanonicalArrayStream<R, T> {
+    /// Creates a stream over `reader`, which must hold a single JSON array.
+    ///
+    /// Nothing is read until the first element is requested.
+    pub fn new(reader: R) -> Self {
+        let start = Position { line: 1, column: 0 };
+        Self {
+            bytes: reader.bytes(),
+            peeked: None,
+            state: StreamState::Start,
+            buffer: Vec::new(),
+            position: start,
+            element_start: start,
+            _marker: PhantomData,
+        }
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>, CanonicalStreamError> {
+        let byte = match self.peeked.take() {
+            Some(byte) => Some(byte),
+            None => self
+                .bytes
+                .next()
+                .transpose()
+                .map_err(CanonicalStreamError::io)?,
+        };
+        if byte == Some(b'\n') {
+            self.position.line += 1;
+            self.position.column = 0;
+        } else if byte.is_some() {
+            self.position.column += 1;
+        }
+        Ok(byte)
+    }
+
+    /// Puts back a byte other than a newline, to be returned by the next
+    /// `next_byte`.
+    fn unread(&mut self, byte: u8) {
+        debug_assert_ne!(byte, b'\n');
+        self.peeked = Some(byte);
+        self.position.column -= 1;
+    }
+
+    /// An error at the last byte read.
+    fn error(&self, message: &str) -> CanonicalStreamError {
+        CanonicalStreamError::at(serde_json::Error::custom(message), self.position)
+    }
+
+    /// The error `serde_json` raises for an array that holds the element in
+    /// `buffer` followed by `tail`, or `None` if that is still valid.
+    ///
+    /// Used when the element cannot continue, so the error has the message
+    /// and category `serde_json` would give the whole stream. Only `[` and
+    /// the element are parsed; the bracket takes the place of the byte before
+    /// the element, and the position is moved to where that byte sits.
+    fn framing_error(&self, tail: &[u8]) -> Option<CanonicalStreamError> {
+        let reader = b"[".chain(&self.buffer[..]).chain(tail);
+        let err = serde_json::from_reader::<_, de::IgnoredAny>(reader).err()?;
+        Some(CanonicalStreamError::relative(err, self.element_start, 1))
+    }
+
+    /// The end-of-input error for an array that stops after `buffer`.
+    fn truncated(&self) -> CanonicalStreamError {
+        self.framing_error(&[]).unwrap_or_else(|| {
+            CanonicalStreamError::at(
+                serde_json::Error::custom("EOF while parsing a list"),
+                self.position,
+            )
+        })
+    }
+
+    fn next_non_whitespace(&mut self) -> Result<Option<u8>, CanonicalStreamError> {
+        loop {
+            match self.next_byte()? {
+                Some(b' ' | b'\t' | b'\n' | b'\r') => {}
+                other => return Ok(other),
+            }
+        }
+    }
+
+    /// Consumes the opening bracket and reports whether the array is empty.
+    fn read_array_start(&mut self) -> Result<bool, CanonicalStreamError> {
+        match self.next_non_whitespace()? {
+            Some(b'[') => {}
+            Some(_) => return Err(self.error("expected JSON array")),
+            None => {
+                // The same error `serde_json` gives for input that is only
+                // whitespace, at the end of that whitespace.
+                let err = serde_json::from_slice::<de::IgnoredAny>(b"")
+                    .err()
+                    .unwrap_or_else(|| serde_json::Error::custom("EOF while parsing a value"));
+                return Err(CanonicalStreamError::at(err, self.position));
+            }
+        }
+        match self.next_non_whitespace()? {
+            Some(b']') => Ok(true),
+            Some(byte) => {
+                self.unread(byte);
+                Ok(false)
+            }
+            None => {
+                self.element_start = self.position;
+                Err(self.truncated())
+            }
+        }
+    }
+
+    /// Buffers the next element and reports whether it was the last one.
+    fn read_element(&mut self) -> Result<bool, CanonicalStreamError> {
+        self.buffer.clear();
+        self.element_start = self.position;
+        // The closing bracket expected for each open object or array.
+        let mut closers = Vec::new();
+        let mut in_string = false;
+        let mut escaped = false;
+
+        loop {
+            let Some(byte) = self.next_byte()? else {
+                return Err(self.truncated());
+            };
+            if in_string {
+                if escaped {
+                    escaped = false;
+                } else if byte == b'\\' {
+                    escaped = true;
+                } else if byte == b'"' {
+                    in_string = false;
+                }
+            } else {
+                match byte {
+                    b'"' => in_string = true,
+                    b'{' => closers.push(b'}'),
+                    b'[' => closers.push(b']'),
+                    b',' | b']' if closers.is_empty() => {
+                        if self.buffer.iter().all(u8::is_ascii_whitespace) {
+                            // `[1,]` is the one empty element `serde_json`
+                            // itself accepts.
+                            return Err(self
+                                .framing_error(&[byte])
+                                .unwrap_or_else(|| self.error("expected array element")));
+                        }
+                        return Ok(byte == b']');
+                    }
+                    b'}' | b']' if closers.last() == Some(&byte) => {
+                        closers.pop();
+                    }
+                    b'}' | b']' => {
+                        return Err(self
+                            .framing_error(&[byte])
+                            .unwrap_or_else(|| self.error("mismatched bracket in JSON array")));
+                    }
+                    _ => {}
+                }
+            }
+            self.buffer.push(byte);
+        }
+    }
+
+    fn read_array_end(&mut self) -> Result<(), CanonicalStreamError> {
+        match self.next_non_whitespace()? {
+            None => Ok(()),
+            Some(_) => Err(self.error("trailing characters after array")),
+        }
+    }
+}
+
+impl<R: io::BufRead, T: CanonicalDeserialize> CanonicalArrayStream<R, T> {
+    fn next_element(&mut self) -> Result<Option<T>, CanonicalStreamError> {
+        if self.state == StreamState::Start {
+            self.state = StreamState::Elements;
+            if self.read_array_start()? {
+                self.state = StreamState::Done;
+                self.read_array_end()?;
+                return Ok(None);
+            }
+        }
+        if self.state == StreamState::Done {
+            return Ok(None);
+        }
+
+        let last = self.read_element()?;
+        let value = match serde_json::from_slice::<CanonicalValue<T>>(&self.buffer) {
+            Ok(value) => value.0,
+            Err(err) => return Err(CanonicalStreamError::relative(err, self.element_start, 0)),
+        };
+        if last {
+            self.state = StreamState::Done;
+            self.read_array_end()?;
+        }
+        Ok(Some(value))
+    }
+}
+
+impl<R: io::BufRead, T: CanonicalDeserialize> Iterator for CanonicalArrayStream<R, T> {
+    type Item = Result<T, CanonicalStreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_element() {
+            Ok(value) => value.map(Ok),
+            Err(err) => {
+                self.state = StreamState::Done;
+                Some(Err(err))
+            }
+        }
+    }
+}
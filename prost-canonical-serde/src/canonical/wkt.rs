@@ -1,15 +1,20 @@
 use alloc::collections::BTreeMap;
+use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::fmt;
 use core::fmt::Write as _;
 
-use chrono::{DateTime, Datelike, TimeZone, Timelike, Utc};
-
 use serde::{Deserialize, Deserializer, Serialize, Serializer, de, ser};
 
 use super::CanonicalError;
-use super::number::{f64_from_i64_exact, f64_from_u64_exact};
+use super::civil;
+use super::number::{
+    ARBITRARY_PRECISION_KEY, f64_from_i64_exact, f64_from_u64_exact, format_double,
+    serialize_float64, visit_arbitrary_precision, visit_number_text,
+};
+use super::options::{self, DurationUnit, NonFiniteValue, TimestampUnit};
+use super::scalar::deserialize_str_with;
 use super::wrappers::{Canonical, CanonicalValue, CanonicalVec};
 use crate::{CanonicalDeserialize, CanonicalSerialize};
 
@@ -18,8 +23,20 @@ impl CanonicalSerialize for prost_types::Timestamp {
     where
         S: Serializer,
     {
-        let formatted = format_timestamp(self).map_err(ser::Error::custom)?;
-        serializer.serialize_str(&formatted)
+        let options = options::current();
+        match options.timestamp_unit {
+            TimestampUnit::Rfc3339
+                if options.relaxed_timestamp_bounds && !is_canonical_timestamp(self) =>
+            {
+                serialize_raw_timestamp(self, serializer)
+            }
+            TimestampUnit::Rfc3339 => {
+                let formatted = format_timestamp(self).map_err(ser::Error::custom)?;
+                serializer.serialize_str(&formatted)
+            }
+            TimestampUnit::EpochSeconds => EpochTimestamp(*self).serialize(serializer),
+            TimestampUnit::EpochMilliseconds => serialize_epoch_millis(self, serializer),
+        }
     }
 }
 
@@ -28,8 +45,232 @@ impl CanonicalDeserialize for prost_types::Timestamp {
     where
         D: Deserializer<'de>,
     {
-        let value = String::deserialize(deserializer)?;
-        parse_timestamp_string(&value).map_err(de::Error::custom)
+        let options = options::current();
+        let unit = options.timestamp_unit;
+        if unit == TimestampUnit::Rfc3339 && options.relaxed_timestamp_bounds {
+            return deserializer.deserialize_any(RelaxedTimestampVisitor);
+        }
+        if unit == TimestampUnit::Rfc3339 {
+            return deserialize_str_with(deserializer, parse_timestamp_string);
+        }
+        deserializer.deserialize_any(EpochVisitor(unit))
+    }
+}
+
+/// Whether the canonical RFC 3339 string can represent `value` exactly.
+fn is_canonical_timestamp(value: &prost_types::Timestamp) -> bool {
+    (MIN_TIMESTAMP_SECONDS..=MAX_TIMESTAMP_SECONDS).contains(&value.seconds)
+        && (0..1_000_000_000).contains(&value.nanos)
+}
+
+/// Writes a timestamp as its raw `seconds`/`nanos` fields, for values the
+/// `relaxed_timestamp_bounds` option lets through.
+fn serialize_raw_timestamp<S>(
+    value: &prost_types::Timestamp,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    use serde::ser::SerializeMap;
+
+    let mut map = serializer.serialize_map(Some(2))?;
+    map.serialize_entry("seconds", &Canonical::new(&value.seconds))?;
+    map.serialize_entry("nanos", &value.nanos)?;
+    map.end()
+}
+
+/// Accepts a canonical timestamp string or, under `relaxed_timestamp_bounds`,
+/// the raw-field object written for out-of-range values.
+struct RelaxedTimestampVisitor;
+
+impl<'de> de::Visitor<'de> for RelaxedTimestampVisitor {
+    type Value = prost_types::Timestamp;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("timestamp string or object with seconds and nanos")
+    }
+
+    fn visit_str<Err>(self, value: &str) -> Result<Self::Value, Err>
+    where
+        Err: de::Error,
+    {
+        parse_timestamp_string(value).map_err(Err::custom)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        let mut seconds = None;
+        let mut nanos = None;
+        while let Some(key) = map.next_key::<String>()? {
+            let slot_is_set = match key.as_str() {
+                "seconds" => seconds
+                    .replace(map.next_value::<CanonicalValue<i64>>()?.0)
+                    .is_some(),
+                "nanos" => nanos
+                    .replace(map.next_value::<CanonicalValue<i32>>()?.0)
+                    .is_some(),
+                _ => return Err(de::Error::unknown_field(&key, &["seconds", "nanos"])),
+            };
+            if slot_is_set {
+                return Err(de::Error::custom(format!(
+                    "duplicate timestamp field `{key}`"
+                )));
+            }
+        }
+        let timestamp = prost_types::Timestamp {
+            seconds: seconds.unwrap_or_default(),
+            nanos: nanos.unwrap_or_default(),
+        };
+        check_timestamp_window(&timestamp).map_err(de::Error::custom)?;
+        Ok(timestamp)
+    }
+}
+
+/// A `Timestamp` encoded as a JSON number of seconds since the Unix epoch.
+///
+/// This is the non-canonical encoding selected by the derive's
+/// `timestamp_epoch` field attribute. Whole seconds are written as integers
+/// and anything finer as a floating-point number. On input both the number
+/// and the canonical RFC 3339 string are accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochTimestamp(pub prost_types::Timestamp);
+
+impl Serialize for EpochTimestamp {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let prost_types::Timestamp { seconds, nanos } = self.0;
+        if !(MIN_TIMESTAMP_SECONDS..=MAX_TIMESTAMP_SECONDS).contains(&seconds) {
+            return Err(ser::Error::custom("timestamp seconds out of range"));
+        }
+        if !(0..1_000_000_000).contains(&nanos) {
+            return Err(ser::Error::custom("timestamp nanos out of range"));
+        }
+        if nanos == 0 {
+            return serializer.serialize_i64(seconds);
+        }
+        #[expect(
+            clippy::cast_precision_loss,
+            reason = "Fractional epoch seconds are inherently approximate."
+        )]
+        let value = seconds as f64 + f64::from(nanos) / 1e9;
+        serializer.serialize_f64(value)
+    }
+}
+
+impl<'de> Deserialize<'de> for EpochTimestamp {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer
+            .deserialize_any(EpochVisitor(TimestampUnit::EpochSeconds))
+            .map(EpochTimestamp)
+    }
+}
+
+/// Serializes a timestamp as a JSON number of milliseconds since the epoch.
+///
+/// Whole milliseconds are written as integers and anything finer as a
+/// floating-point number.
+fn serialize_epoch_millis<S>(
+    value: &prost_types::Timestamp,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let prost_types::Timestamp { seconds, nanos } = *value;
+    if !(MIN_TIMESTAMP_SECONDS..=MAX_TIMESTAMP_SECONDS).contains(&seconds) {
+        return Err(ser::Error::custom("timestamp seconds out of range"));
+    }
+    if !(0..1_000_000_000).contains(&nanos) {
+        return Err(ser::Error::custom("timestamp nanos out of range"));
+    }
+    let millis = seconds * 1000 + i64::from(nanos / 1_000_000);
+    let sub_millis = nanos % 1_000_000;
+    if sub_millis == 0 {
+        return serializer.serialize_i64(millis);
+    }
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "Fractional epoch milliseconds are inherently approximate."
+    )]
+    let value = millis as f64 + f64::from(sub_millis) / 1e6;
+    serializer.serialize_f64(value)
+}
+
+/// Reads a timestamp from a JSON number of epoch `TimestampUnit`s or from
+/// the canonical RFC 3339 string.
+struct EpochVisitor(TimestampUnit);
+
+impl<'de> de::Visitor<'de> for EpochVisitor {
+    type Value = prost_types::Timestamp;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == TimestampUnit::EpochMilliseconds {
+            formatter.write_str("epoch milliseconds or timestamp string")
+        } else {
+            formatter.write_str("epoch seconds or timestamp string")
+        }
+    }
+
+    fn visit_i64<Err>(self, value: i64) -> Result<Self::Value, Err>
+    where
+        Err: de::Error,
+    {
+        let (seconds, nanos) = if self.0 == TimestampUnit::EpochMilliseconds {
+            let nanos = i32::try_from(value.rem_euclid(1000) * 1_000_000)
+                .map_err(|_| Err::custom("timestamp nanos out of range"))?;
+            (value.div_euclid(1000), nanos)
+        } else {
+            (value, 0)
+        };
+        if !(MIN_TIMESTAMP_SECONDS..=MAX_TIMESTAMP_SECONDS).contains(&seconds) {
+            return Err(Err::custom("timestamp seconds out of range"));
+        }
+        let timestamp = prost_types::Timestamp { seconds, nanos };
+        check_timestamp_window(&timestamp).map_err(Err::custom)?;
+        Ok(timestamp)
+    }
+
+    fn visit_u64<Err>(self, value: u64) -> Result<Self::Value, Err>
+    where
+        Err: de::Error,
+    {
+        let value =
+            i64::try_from(value).map_err(|_| Err::custom("timestamp seconds out of range"))?;
+        self.visit_i64(value)
+    }
+
+    fn visit_f64<Err>(self, value: f64) -> Result<Self::Value, Err>
+    where
+        Err: de::Error,
+    {
+        let seconds = if self.0 == TimestampUnit::EpochMilliseconds {
+            value / 1000.0
+        } else {
+            value
+        };
+        timestamp_from_epoch_f64(seconds).map_err(Err::custom)
+    }
+
+    fn visit_str<Err>(self, value: &str) -> Result<Self::Value, Err>
+    where
+        Err: de::Error,
+    {
+        parse_timestamp_string(value).map_err(Err::custom)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        visit_arbitrary_precision(self, map)
     }
 }
 
@@ -38,6 +279,10 @@ impl CanonicalSerialize for prost_types::Duration {
     where
         S: Serializer,
     {
+        let unit = options::current().duration_unit;
+        if unit != DurationUnit::Seconds {
+            return serialize_duration_number(self, unit, serializer);
+        }
         let formatted = format_duration(self).map_err(ser::Error::custom)?;
         serializer.serialize_str(&formatted)
     }
@@ -48,8 +293,69 @@ impl CanonicalDeserialize for prost_types::Duration {
     where
         D: Deserializer<'de>,
     {
-        let value = String::deserialize(deserializer)?;
-        parse_duration_string(&value).map_err(de::Error::custom)
+        /// Accepts the canonical string or a number of the configured unit.
+        struct Visitor(DurationUnit);
+
+        impl Visitor {
+            fn duration_from_count<Err>(&self, count: i128) -> Result<prost_types::Duration, Err>
+            where
+                Err: de::Error,
+            {
+                let total = count
+                    .checked_mul(duration_unit_nanos(self.0))
+                    .ok_or_else(|| Err::custom("duration out of range"))?;
+                duration_from_nanos(total).map_err(Err::custom)
+            }
+        }
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = prost_types::Duration;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("duration string or number")
+            }
+
+            fn visit_str<Err>(self, value: &str) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                parse_duration_string(value).map_err(Err::custom)
+            }
+
+            fn visit_i64<Err>(self, value: i64) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                self.duration_from_count(i128::from(value))
+            }
+
+            fn visit_u64<Err>(self, value: u64) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                self.duration_from_count(i128::from(value))
+            }
+
+            fn visit_f64<Err>(self, value: f64) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                duration_from_unit_f64(value, self.0).map_err(Err::custom)
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                visit_arbitrary_precision(self, map)
+            }
+        }
+
+        let unit = options::current().duration_unit;
+        if unit == DurationUnit::Seconds {
+            return deserialize_str_with(deserializer, parse_duration_string);
+        }
+        deserializer.deserialize_any(Visitor(unit))
     }
 }
 
@@ -68,6 +374,11 @@ impl CanonicalSerialize for prost_types::FieldMask {
                 if segment.is_empty() {
                     return Err(ser::Error::custom("field mask segment is empty"));
                 }
+                if segment.bytes().any(|byte| byte.is_ascii_uppercase()) {
+                    return Err(ser::Error::custom(
+                        "field mask path must be lower_snake_case",
+                    ));
+                }
                 let json_segment = snake_to_lower_camel(segment);
                 let round_trip = lower_camel_to_snake(&json_segment);
                 if round_trip != segment {
@@ -87,33 +398,12 @@ impl CanonicalDeserialize for prost_types::FieldMask {
     where
         D: Deserializer<'de>,
     {
-        let value = String::deserialize(deserializer)?;
-        if value.is_empty() {
-            return Ok(prost_types::FieldMask { paths: Vec::new() });
-        }
-
-        let mut paths = Vec::new();
-        for path in value.split(',') {
-            if path.is_empty() {
-                return Err(de::Error::custom("field mask path is empty"));
-            }
-            let mut segments = Vec::new();
-            for segment in path.split('.') {
-                if segment.is_empty() {
-                    return Err(de::Error::custom("field mask segment is empty"));
-                }
-                if segment.contains('_') {
-                    return Err(de::Error::custom("field mask contains underscore"));
-                }
-                segments.push(lower_camel_to_snake(segment));
-            }
-            paths.push(segments.join("."));
-        }
-
-        Ok(prost_types::FieldMask { paths })
+        deserialize_str_with(deserializer, parse_field_mask)
     }
 }
 
+/// Keys are written in sorted order: prost stores `Struct.fields` in a
+/// `BTreeMap`, so the output is stable no matter how the struct was built.
 impl CanonicalSerialize for prost_types::Struct {
     fn serialize_canonical<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -152,6 +442,9 @@ impl CanonicalDeserialize for prost_types::Struct {
                 while let Some((key, value)) =
                     map.next_entry::<String, CanonicalValue<prost_types::Value>>()?
                 {
+                    if fields.contains_key(&key) {
+                        return Err(de::Error::custom("duplicate key in object"));
+                    }
                     fields.insert(key, value.0);
                 }
                 Ok(prost_types::Struct { fields })
@@ -197,9 +490,14 @@ impl CanonicalSerialize for prost_types::Value {
             Some(prost_types::value::Kind::NullValue(_)) => serializer.serialize_unit(),
             Some(prost_types::value::Kind::NumberValue(number)) => {
                 if number.is_finite() {
-                    serializer.serialize_f64(*number)
-                } else {
-                    Err(ser::Error::custom("Value.number_value must be finite"))
+                    return serializer.serialize_f64(*number);
+                }
+                match options::current().value_non_finite {
+                    NonFiniteValue::Error => {
+                        Err(ser::Error::custom("Value.number_value must be finite"))
+                    }
+                    NonFiniteValue::Null => serializer.serialize_unit(),
+                    NonFiniteValue::String => serialize_float64(*number, serializer),
                 }
             }
             Some(prost_types::value::Kind::StringValue(value)) => serializer.serialize_str(value),
@@ -215,6 +513,19 @@ impl CanonicalSerialize for prost_types::Value {
     }
 }
 
+/// Reads whatever the input holds through `deserialize_any`, so it needs a
+/// self-describing format. Besides JSON this covers binary formats such
+/// as CBOR, whose values map on as follows:
+///
+/// | Input                    | `Value` kind                                 |
+/// |--------------------------|----------------------------------------------|
+/// | unit or none             | `null_value`                                 |
+/// | bool                     | `bool_value`                                 |
+/// | integer or float         | `number_value`, if exactly representable     |
+/// | string                   | `string_value`                               |
+/// | bytes                    | `string_value`, if valid UTF-8; else error   |
+/// | sequence                 | `list_value`                                 |
+/// | map with string keys     | `struct_value`                               |
 impl CanonicalDeserialize for prost_types::Value {
     fn deserialize_canonical<'de, D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -258,6 +569,11 @@ impl CanonicalDeserialize for prost_types::Value {
             where
                 Err: de::Error,
             {
+                if options::current().prefer_string_numbers {
+                    return Ok(prost_types::Value {
+                        kind: Some(prost_types::value::Kind::StringValue(value.to_string())),
+                    });
+                }
                 let value = f64_from_i64_exact(value).map_err(Err::custom)?;
                 Ok(prost_types::Value {
                     kind: Some(prost_types::value::Kind::NumberValue(value)),
@@ -268,6 +584,11 @@ impl CanonicalDeserialize for prost_types::Value {
             where
                 Err: de::Error,
             {
+                if options::current().prefer_string_numbers {
+                    return Ok(prost_types::Value {
+                        kind: Some(prost_types::value::Kind::StringValue(value.to_string())),
+                    });
+                }
                 let value = f64_from_u64_exact(value).map_err(Err::custom)?;
                 Ok(prost_types::Value {
                     kind: Some(prost_types::value::Kind::NumberValue(value)),
@@ -278,6 +599,12 @@ impl CanonicalDeserialize for prost_types::Value {
             where
                 Err: de::Error,
             {
+                if options::current().prefer_string_numbers {
+                    let text = format_double(value).map_err(Err::custom)?;
+                    return Ok(prost_types::Value {
+                        kind: Some(prost_types::value::Kind::StringValue(text)),
+                    });
+                }
                 Ok(prost_types::Value {
                     kind: Some(prost_types::value::Kind::NumberValue(value)),
                 })
@@ -292,6 +619,13 @@ impl CanonicalDeserialize for prost_types::Value {
                 })
             }
 
+            fn visit_borrowed_str<Err>(self, value: &'de str) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                self.visit_str(value)
+            }
+
             fn visit_string<Err>(self, value: String) -> Result<Self::Value, Err>
             where
                 Err: de::Error,
@@ -301,6 +635,18 @@ impl CanonicalDeserialize for prost_types::Value {
                 })
             }
 
+            // Binary formats may carry text as raw bytes; `Value` has no
+            // bytes kind, so only UTF-8 is accepted.
+            fn visit_bytes<Err>(self, value: &[u8]) -> Result<Self::Value, Err>
+            where
+                Err: de::Error,
+            {
+                match core::str::from_utf8(value) {
+                    Ok(value) => self.visit_str(value),
+                    Err(_) => Err(Err::invalid_value(de::Unexpected::Bytes(value), &self)),
+                }
+            }
+
             fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
             where
                 A: de::SeqAccess<'de>,
@@ -321,9 +667,19 @@ impl CanonicalDeserialize for prost_types::Value {
                 A: de::MapAccess<'de>,
             {
                 let mut fields = BTreeMap::new();
+                if let Some(key) = map.next_key::<String>()? {
+                    if key == ARBITRARY_PRECISION_KEY {
+                        return visit_number_text(self, map);
+                    }
+                    let value = map.next_value::<CanonicalValue<prost_types::Value>>()?;
+                    fields.insert(key, value.0);
+                }
                 while let Some((key, value)) =
                     map.next_entry::<String, CanonicalValue<prost_types::Value>>()?
                 {
+                    if fields.contains_key(&key) {
+                        return Err(de::Error::custom("duplicate key in object"));
+                    }
                     fields.insert(key, value.0);
                 }
                 Ok(prost_types::Value {
@@ -338,22 +694,46 @@ impl CanonicalDeserialize for prost_types::Value {
     }
 }
 
-impl CanonicalSerialize for prost_types::Any {
-    fn serialize_canonical<S>(&self, _serializer: S) -> Result<S::Ok, S::Error>
+/// `google.protobuf.Empty`, which prost maps to `()`. Like any message
+/// without fields it is written as `{}`; unknown keys are ignored on input.
+impl CanonicalSerialize for () {
+    fn serialize_canonical<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        Err(ser::Error::custom("unsupported Any type"))
+        use serde::ser::SerializeMap;
+
+        serializer.serialize_map(Some(0))?.end()
     }
 }
 
-impl CanonicalDeserialize for prost_types::Any {
-    fn deserialize_canonical<'de, D>(_deserializer: D) -> Result<Self, D::Error>
+impl CanonicalDeserialize for () {
+    fn deserialize_canonical<'de, D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        use serde::de::Error;
-        Err(D::Error::custom("unsupported Any type"))
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = ();
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("map")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                while map
+                    .next_entry::<de::IgnoredAny, de::IgnoredAny>()?
+                    .is_some()
+                {}
+                Ok(())
+            }
+        }
+
+        deserializer.deserialize_map(Visitor)
     }
 }
 
@@ -395,6 +775,34 @@ fn lower_camel_to_snake(value: &str) -> String {
     result
 }
 
+/// Parses the canonical comma-separated form of a `FieldMask`, converting
+/// each lower camel case segment back to its snake case field name.
+fn parse_field_mask(value: &str) -> Result<prost_types::FieldMask, CanonicalError> {
+    if value.is_empty() {
+        return Ok(prost_types::FieldMask { paths: Vec::new() });
+    }
+
+    let mut paths = Vec::new();
+    for path in value.split(',') {
+        if path.is_empty() {
+            return Err(CanonicalError::new("field mask path is empty"));
+        }
+        let mut segments = Vec::new();
+        for segment in path.split('.') {
+            if segment.is_empty() {
+                return Err(CanonicalError::new("field mask segment is empty"));
+            }
+            if segment.contains('_') {
+                return Err(CanonicalError::new("field mask contains underscore"));
+            }
+            segments.push(lower_camel_to_snake(segment));
+        }
+        paths.push(segments.join("."));
+    }
+
+    Ok(prost_types::FieldMask { paths })
+}
+
 /// Minimum allowed timestamp seconds for canonical JSON (0001-01-01T00:00:00Z).
 const MIN_TIMESTAMP_SECONDS: i64 = -62_135_596_800;
 /// Maximum allowed timestamp seconds for canonical JSON (9999-12-31T23:59:59Z).
@@ -402,7 +810,7 @@ const MAX_TIMESTAMP_SECONDS: i64 = 253_402_300_799;
 
 /// Formats a timestamp using canonical protojson rules.
 ///
-/// Chrono's RFC 3339 formatting does not enforce protobuf timestamp bounds or
+/// RFC 3339 formatting in general does not enforce protobuf timestamp bounds or
 /// the canonical fractional-second precision (0/3/6/9 digits with a `Z`
 /// suffix), so we format explicitly here.
 fn format_timestamp(value: &prost_types::Timestamp) -> Result<String, CanonicalError> {
@@ -413,21 +821,18 @@ fn format_timestamp(value: &prost_types::Timestamp) -> Result<String, CanonicalE
     if !(0..1_000_000_000).contains(&nanos) {
         return Err(CanonicalError::new("timestamp nanos out of range"));
     }
-    let nanos_u32 =
-        u32::try_from(nanos).map_err(|_| CanonicalError::new("timestamp nanos out of range"))?;
-    let datetime = Utc
-        .timestamp_opt(value.seconds, nanos_u32)
-        .single()
+    let datetime = civil::from_unix_seconds(value.seconds)
         .ok_or_else(|| CanonicalError::new("timestamp out of range"))?;
 
     let mut formatted = String::with_capacity(32);
-    let year = datetime.year();
-    let month = datetime.month();
-    let day = datetime.day();
-    let hour = datetime.hour();
-    let minute = datetime.minute();
-    let second = datetime.second();
-    let nano = datetime.nanosecond();
+    let civil::CivilDateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+    } = datetime;
 
     write!(
         &mut formatted,
@@ -435,12 +840,13 @@ fn format_timestamp(value: &prost_types::Timestamp) -> Result<String, CanonicalE
     )
     .map_err(|_| CanonicalError::new("format timestamp failed"))?;
 
-    if nano != 0 {
+    if nanos != 0 {
         let mut frac = String::with_capacity(9);
-        write!(&mut frac, "{nano:09}")
+        write!(&mut frac, "{nanos:09}")
             .map_err(|_| CanonicalError::new("format timestamp failed"))?;
-        while frac.ends_with('0') {
-            frac.pop();
+        // Drop whole groups of zeros so the fraction keeps 3, 6, or 9 digits.
+        while frac.ends_with("000") {
+            frac.truncate(frac.len() - 3);
         }
         formatted.push('.');
         formatted.push_str(&frac);
@@ -452,18 +858,83 @@ fn format_timestamp(value: &prost_types::Timestamp) -> Result<String, CanonicalE
 
 fn parse_timestamp_string(value: &str) -> Result<prost_types::Timestamp, CanonicalError> {
     validate_timestamp_format(value)?;
-    let datetime =
-        DateTime::parse_from_rfc3339(value).map_err(|err| CanonicalError::new(err.to_string()))?;
-    let utc = datetime.with_timezone(&Utc);
-    let seconds = utc.timestamp();
+    let (seconds, nanos) = civil::parse_rfc3339(value)?;
     if !(MIN_TIMESTAMP_SECONDS..=MAX_TIMESTAMP_SECONDS).contains(&seconds) {
         return Err(CanonicalError::new("timestamp seconds out of range"));
     }
-    Ok(prost_types::Timestamp {
+    let timestamp = prost_types::Timestamp {
         seconds,
-        nanos: i32::try_from(utc.nanosecond())
+        nanos: i32::try_from(nanos)
             .map_err(|_| CanonicalError::new("timestamp nanos out of range"))?,
-    })
+    };
+    check_timestamp_window(&timestamp)?;
+    Ok(timestamp)
+}
+
+/// Applies the `min_timestamp`/`max_timestamp` options to a parsed value.
+fn check_timestamp_window(value: &prost_types::Timestamp) -> Result<(), CanonicalError> {
+    let key = |timestamp: &prost_types::Timestamp| (timestamp.seconds, timestamp.nanos);
+    let options = options::current();
+    if options
+        .min_timestamp
+        .is_some_and(|min| key(value) < key(&min))
+    {
+        return Err(CanonicalError::new(
+            "timestamp is before the allowed window",
+        ));
+    }
+    if options
+        .max_timestamp
+        .is_some_and(|max| key(value) > key(&max))
+    {
+        return Err(CanonicalError::new("timestamp is after the allowed window"));
+    }
+    Ok(())
+}
+
+/// Converts fractional epoch seconds into a timestamp, rounding to the
+/// nearest nanosecond, and applies the `min_timestamp`/`max_timestamp`
+/// options.
+fn timestamp_from_epoch_f64(value: f64) -> Result<prost_types::Timestamp, CanonicalError> {
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "The bounds are whole seconds well within f64's exact integer range."
+    )]
+    let in_range =
+        (MIN_TIMESTAMP_SECONDS as f64..(MAX_TIMESTAMP_SECONDS + 1) as f64).contains(&value);
+    if !in_range {
+        return Err(CanonicalError::new("timestamp seconds out of range"));
+    }
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "The range check above keeps the value within i64."
+    )]
+    let mut seconds = value as i64;
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "Timestamp seconds are well within f64's exact integer range."
+    )]
+    let mut fraction = value - seconds as f64;
+    if fraction < 0.0 {
+        seconds -= 1;
+        fraction += 1.0;
+    }
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "The fraction is in [0, 1), so the scaled value fits in i32."
+    )]
+    let mut nanos = (fraction * 1e9 + 0.5) as i32;
+    if nanos >= 1_000_000_000 {
+        seconds += 1;
+        nanos -= 1_000_000_000;
+    }
+    // Rounding up can carry just past the last representable second.
+    if seconds > MAX_TIMESTAMP_SECONDS {
+        return Err(CanonicalError::new("timestamp seconds out of range"));
+    }
+    let timestamp = prost_types::Timestamp { seconds, nanos };
+    check_timestamp_window(&timestamp)?;
+    Ok(timestamp)
 }
 
 fn validate_timestamp_format(value: &str) -> Result<(), CanonicalError> {
@@ -480,19 +951,27 @@ fn validate_timestamp_format(value: &str) -> Result<(), CanonicalError> {
     Ok(())
 }
 
-fn format_duration(value: &prost_types::Duration) -> Result<String, CanonicalError> {
-    if value.seconds < -315_576_000_000 || value.seconds > 315_576_000_000 {
+/// Maximum absolute duration seconds for canonical JSON (about 10,000 years).
+const MAX_DURATION_SECONDS: i64 = 315_576_000_000;
+
+fn check_duration(seconds: i64, nanos: i32) -> Result<(), CanonicalError> {
+    if !(-MAX_DURATION_SECONDS..=MAX_DURATION_SECONDS).contains(&seconds) {
         return Err(CanonicalError::new("duration seconds out of range"));
     }
-    let nanos = value.nanos;
     if nanos <= -1_000_000_000 || nanos >= 1_000_000_000 {
         return Err(CanonicalError::new("duration nanos out of range"));
     }
-    if (value.seconds < 0 && nanos > 0) || (value.seconds > 0 && nanos < 0) {
+    if (seconds < 0 && nanos > 0) || (seconds > 0 && nanos < 0) {
         return Err(CanonicalError::new(
             "duration seconds and nanos must have same sign",
         ));
     }
+    Ok(())
+}
+
+fn format_duration(value: &prost_types::Duration) -> Result<String, CanonicalError> {
+    check_duration(value.seconds, value.nanos)?;
+    let nanos = value.nanos;
     if value.seconds == 0 && nanos == 0 {
         return Ok("0s".to_string());
     }
@@ -528,15 +1007,20 @@ fn parse_duration_string(value: &str) -> Result<prost_types::Duration, Canonical
         return Err(CanonicalError::new("duration is empty"));
     }
 
-    let (negative, value) = match value.strip_prefix('-') {
-        Some(rest) => (true, rest),
-        None => (false, value),
+    let (negative, value) = match (value.strip_prefix('-'), value.strip_prefix('+')) {
+        (Some(rest), _) => (true, rest),
+        (None, Some(rest)) => (false, rest),
+        (None, None) => (false, value),
     };
 
     let mut parts = value.splitn(2, '.');
     let seconds_part = parts.next().unwrap_or("0");
     let fraction_part = parts.next();
 
+    // `i64::from_str` would accept a second sign here.
+    if !seconds_part.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(CanonicalError::new("invalid duration seconds"));
+    }
     let seconds = seconds_part
         .parse::<i64>()
         .map_err(|_| CanonicalError::new("invalid duration seconds"))?;
@@ -546,24 +1030,26 @@ fn parse_duration_string(value: &str) -> Result<prost_types::Duration, Canonical
             return Err(CanonicalError::new("invalid duration fractional"));
         }
         if fraction.is_empty() {
-            0
-        } else {
-            let parsed = fraction
-                .parse::<u32>()
-                .map_err(|_| CanonicalError::new("invalid duration nanos"))?;
-            let fraction_len = u32::try_from(fraction.len())
-                .map_err(|_| CanonicalError::new("invalid duration nanos"))?;
-            let scale_exp = 9_u32
-                .checked_sub(fraction_len)
-                .ok_or_else(|| CanonicalError::new("invalid duration nanos"))?;
-            let scale = 10_u32
-                .checked_pow(scale_exp)
-                .ok_or_else(|| CanonicalError::new("invalid duration nanos"))?;
-            let nanos = parsed
-                .checked_mul(scale)
-                .ok_or_else(|| CanonicalError::new("invalid duration nanos"))?;
-            i32::try_from(nanos).map_err(|_| CanonicalError::new("invalid duration nanos"))?
+            return Err(CanonicalError::new("duration fraction is empty"));
         }
+        if !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(CanonicalError::new("invalid duration nanos"));
+        }
+        let parsed = fraction
+            .parse::<u32>()
+            .map_err(|_| CanonicalError::new("invalid duration nanos"))?;
+        let fraction_len = u32::try_from(fraction.len())
+            .map_err(|_| CanonicalError::new("invalid duration nanos"))?;
+        let scale_exp = 9_u32
+            .checked_sub(fraction_len)
+            .ok_or_else(|| CanonicalError::new("invalid duration nanos"))?;
+        let scale = 10_u32
+            .checked_pow(scale_exp)
+            .ok_or_else(|| CanonicalError::new("invalid duration nanos"))?;
+        let nanos = parsed
+            .checked_mul(scale)
+            .ok_or_else(|| CanonicalError::new("invalid duration nanos"))?;
+        i32::try_from(nanos).map_err(|_| CanonicalError::new("invalid duration nanos"))?
     } else {
         0
     };
@@ -574,17 +1060,104 @@ fn parse_duration_string(value: &str) -> Result<prost_types::Duration, Canonical
         (seconds, nanos)
     };
 
-    if !(-315_576_000_000..=315_576_000_000).contains(&seconds) {
-        return Err(CanonicalError::new("duration seconds out of range"));
-    }
-    if nanos <= -1_000_000_000 || nanos >= 1_000_000_000 {
-        return Err(CanonicalError::new("duration nanos out of range"));
-    }
-    if (seconds < 0 && nanos > 0) || (seconds > 0 && nanos < 0) {
+    check_duration(seconds, nanos)?;
+    let duration = prost_types::Duration { seconds, nanos };
+    check_duration_window(&duration)?;
+    Ok(duration)
+}
+
+/// Applies the `max_duration` option to a parsed value.
+fn check_duration_window(value: &prost_types::Duration) -> Result<(), CanonicalError> {
+    let magnitude = |duration: &prost_types::Duration| {
+        (i128::from(duration.seconds) * 1_000_000_000 + i128::from(duration.nanos)).unsigned_abs()
+    };
+    if options::current()
+        .max_duration
+        .is_some_and(|max| magnitude(value) > magnitude(&max))
+    {
         return Err(CanonicalError::new(
-            "duration seconds and nanos must have same sign",
+            "duration exceeds the allowed magnitude",
         ));
     }
+    Ok(())
+}
+
+/// Returns how many nanoseconds make up one `unit`.
+fn duration_unit_nanos(unit: DurationUnit) -> i128 {
+    match unit {
+        DurationUnit::Seconds => 1_000_000_000,
+        DurationUnit::Milliseconds => 1_000_000,
+        DurationUnit::Microseconds => 1_000,
+        DurationUnit::Nanoseconds => 1,
+    }
+}
 
-    Ok(prost_types::Duration { seconds, nanos })
+/// Serializes a duration as a JSON number of `unit`s.
+///
+/// Whole values are written as integers; durations that do not divide evenly
+/// into the unit fall back to a floating-point number.
+fn serialize_duration_number<S>(
+    value: &prost_types::Duration,
+    unit: DurationUnit,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    check_duration(value.seconds, value.nanos).map_err(ser::Error::custom)?;
+    let total = i128::from(value.seconds) * 1_000_000_000 + i128::from(value.nanos);
+    let scale = duration_unit_nanos(unit);
+    if total % scale == 0 {
+        let count = total / scale;
+        match i64::try_from(count) {
+            Ok(count) => serializer.serialize_i64(count),
+            Err(_) => serializer.serialize_i128(count),
+        }
+    } else {
+        #[expect(
+            clippy::cast_precision_loss,
+            reason = "Fractional unit counts are inherently approximate."
+        )]
+        let count = total as f64 / scale as f64;
+        serializer.serialize_f64(count)
+    }
+}
+
+fn duration_from_nanos(total: i128) -> Result<prost_types::Duration, CanonicalError> {
+    let seconds = i64::try_from(total / 1_000_000_000)
+        .map_err(|_| CanonicalError::new("duration seconds out of range"))?;
+    let nanos = i32::try_from(total % 1_000_000_000)
+        .map_err(|_| CanonicalError::new("duration nanos out of range"))?;
+    check_duration(seconds, nanos)?;
+    let duration = prost_types::Duration { seconds, nanos };
+    check_duration_window(&duration)?;
+    Ok(duration)
+}
+
+fn duration_from_unit_f64(
+    value: f64,
+    unit: DurationUnit,
+) -> Result<prost_types::Duration, CanonicalError> {
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "Unit scales are small powers of ten that f64 represents exactly."
+    )]
+    let total = value * duration_unit_nanos(unit) as f64;
+    // Bounds well outside the valid duration range keep the cast below exact
+    // enough for `check_duration` to reject out-of-range values.
+    if !total.is_finite() || total.abs() > 1e30 {
+        return Err(CanonicalError::new("duration out of range"));
+    }
+    // Round half away from zero: scaling by the unit can land a hair below
+    // the intended nanosecond count, which truncation would drop.
+    let rounded = if total < 0.0 {
+        total - 0.5
+    } else {
+        total + 0.5
+    };
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "Sub-nanosecond precision is not representable in a Duration."
+    )]
+    duration_from_nanos(rounded as i128)
 }
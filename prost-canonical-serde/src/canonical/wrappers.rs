@@ -1,20 +1,47 @@
+use alloc::borrow::{Cow, ToOwned};
 use alloc::boxed::Box;
+use alloc::rc::Rc;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::fmt;
 use core::marker::PhantomData;
 
-use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de, ser};
 
-use crate::{CanonicalDeserialize, CanonicalSerialize};
+use super::PathSeed;
+#[cfg(feature = "std")]
+use super::options::{CanonicalOptions, with_options};
+use crate::{CanonicalDeserialize, CanonicalSerialize, ProstMessage};
 
 /// Wraps a value to serialize it using canonical protobuf JSON rules.
 pub struct Canonical<'a, T: CanonicalSerialize + ?Sized> {
     value: &'a T,
+    #[cfg(feature = "std")]
+    options: Option<CanonicalOptions>,
 }
 
 impl<'a, T: CanonicalSerialize + ?Sized> Canonical<'a, T> {
     pub fn new(value: &'a T) -> Self {
-        Self { value }
+        Self {
+            value,
+            #[cfg(feature = "std")]
+            options: None,
+        }
+    }
+
+    /// Wraps `value` to serialize it with `options` applied.
+    ///
+    /// The options travel with the wrapper and are installed while it
+    /// serializes, so they apply whenever and wherever it is serialized,
+    /// such as by a web framework writing a response body after the handler
+    /// returns.
+    #[cfg(feature = "std")]
+    pub fn with_options(value: &'a T, options: CanonicalOptions) -> Self {
+        Self {
+            value,
+            options: Some(options),
+        }
     }
 }
 
@@ -23,10 +50,81 @@ impl<T: CanonicalSerialize + ?Sized> Serialize for Canonical<'_, T> {
     where
         S: Serializer,
     {
+        #[cfg(feature = "std")]
+        if let Some(options) = self.options {
+            return with_options(options, || self.value.serialize_canonical(serializer));
+        }
         self.value.serialize_canonical(serializer)
     }
 }
 
+/// Deserializes a `T` using canonical protobuf JSON rules with `options`
+/// applied, for use with [`serde::de::DeserializeSeed`].
+///
+/// Like [`Canonical::with_options`], the options are installed only while
+/// the seed deserializes, whatever the surrounding thread state.
+#[cfg(feature = "std")]
+pub struct CanonicalSeed<T> {
+    options: CanonicalOptions,
+    marker: PhantomData<T>,
+}
+
+#[cfg(feature = "std")]
+impl<T> CanonicalSeed<T> {
+    pub fn new(options: CanonicalOptions) -> Self {
+        Self {
+            options,
+            marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, T: CanonicalDeserialize> de::DeserializeSeed<'de> for CanonicalSeed<T> {
+    type Value = T;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        with_options(self.options, || T::deserialize_canonical(deserializer))
+    }
+}
+
+/// Free functions for `#[serde(with = "prost_canonical_serde::canonical_with")]`.
+///
+/// Lets a hand-written serde struct mix canonical fields, such as prost
+/// messages and well-known types, with ordinary serde fields.
+pub mod canonical_with {
+    use serde::{Deserializer, Serializer};
+
+    use crate::{CanonicalDeserialize, CanonicalSerialize};
+
+    /// Serializes `value` using canonical protobuf JSON rules.
+    ///
+    /// # Errors
+    /// Returns any error from serializing `value` canonically.
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: CanonicalSerialize + ?Sized,
+        S: Serializer,
+    {
+        value.serialize_canonical(serializer)
+    }
+
+    /// Deserializes a value using canonical protobuf JSON rules.
+    ///
+    /// # Errors
+    /// Returns any error from parsing `T` canonically.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: CanonicalDeserialize,
+        D: Deserializer<'de>,
+    {
+        T::deserialize_canonical(deserializer)
+    }
+}
+
 /// Wraps a value for canonical protobuf JSON deserialization.
 pub struct CanonicalValue<T>(pub T);
 
@@ -46,6 +144,10 @@ impl<T: CanonicalSerialize> CanonicalSerialize for Box<T> {
     {
         self.as_ref().serialize_canonical(serializer)
     }
+
+    fn __merge_fields() -> Option<Vec<crate::MergeField>> {
+        T::__merge_fields()
+    }
 }
 
 impl<T: CanonicalDeserialize> CanonicalDeserialize for Box<T> {
@@ -55,6 +157,98 @@ impl<T: CanonicalDeserialize> CanonicalDeserialize for Box<T> {
     {
         T::deserialize_canonical(deserializer).map(Box::new)
     }
+
+    fn __merge_canonical<'de, D>(&mut self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.as_mut().__merge_canonical(deserializer)
+    }
+}
+
+impl<T: CanonicalSerialize> CanonicalSerialize for Rc<T> {
+    fn serialize_canonical<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.as_ref().serialize_canonical(serializer)
+    }
+
+    fn __merge_fields() -> Option<Vec<crate::MergeField>> {
+        T::__merge_fields()
+    }
+}
+
+impl<T: CanonicalDeserialize> CanonicalDeserialize for Rc<T> {
+    fn deserialize_canonical<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize_canonical(deserializer).map(Rc::new)
+    }
+
+    fn __merge_canonical<'de, D>(&mut self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // A shared value cannot be changed in place, so it is replaced.
+        if let Some(value) = Rc::get_mut(self) {
+            value.__merge_canonical(deserializer)
+        } else {
+            *self = Self::deserialize_canonical(deserializer)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T: CanonicalSerialize> CanonicalSerialize for Arc<T> {
+    fn serialize_canonical<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.as_ref().serialize_canonical(serializer)
+    }
+
+    fn __merge_fields() -> Option<Vec<crate::MergeField>> {
+        T::__merge_fields()
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T: CanonicalDeserialize> CanonicalDeserialize for Arc<T> {
+    fn deserialize_canonical<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize_canonical(deserializer).map(Arc::new)
+    }
+
+    fn __merge_canonical<'de, D>(&mut self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // A shared value cannot be changed in place, so it is replaced.
+        if let Some(value) = Arc::get_mut(self) {
+            value.__merge_canonical(deserializer)
+        } else {
+            *self = Self::deserialize_canonical(deserializer)?;
+            Ok(())
+        }
+    }
+}
+
+impl<T: CanonicalSerialize + ToOwned + ?Sized> CanonicalSerialize for Cow<'_, T> {
+    fn serialize_canonical<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.as_ref().serialize_canonical(serializer)
+    }
+
+    fn __merge_fields() -> Option<Vec<crate::MergeField>> {
+        T::__merge_fields()
+    }
 }
 
 /// Wraps an `Option` for canonical protobuf JSON deserialization.
@@ -118,8 +312,10 @@ impl<'de, T: CanonicalDeserialize> Deserialize<'de> for CanonicalVec<T> {
             where
                 A: de::SeqAccess<'de>,
             {
-                let mut values = Vec::new();
-                while let Some(value) = seq.next_element::<CanonicalValue<T>>()? {
+                let mut values = vec_for_access(&seq);
+                while let Some(value) =
+                    seq.next_element_seed(PathSeed::<CanonicalValue<T>>::index(values.len()))?
+                {
                     values.push(value.0);
                 }
                 Ok(CanonicalVec(values))
@@ -143,3 +339,80 @@ impl<'de, T: CanonicalDeserialize> Deserialize<'de> for CanonicalVec<T> {
         deserializer.deserialize_any(Visitor(PhantomData))
     }
 }
+
+/// Upper bound on what a deserializer's size hint may reserve, so a
+/// misleading hint cannot force a large allocation up front.
+const MAX_RESERVED: usize = 4096;
+
+/// How many elements or entries to reserve for a collection whose
+/// deserializer gave `size_hint`.
+pub(crate) fn reserve_hint(size_hint: Option<usize>) -> usize {
+    size_hint.unwrap_or(0).min(MAX_RESERVED)
+}
+
+/// Creates an empty vector with room for the elements `seq` says it holds.
+pub(crate) fn vec_for_access<'de, T, A>(seq: &A) -> Vec<T>
+where
+    A: de::SeqAccess<'de>,
+{
+    Vec::with_capacity(reserve_hint(seq.size_hint()))
+}
+
+/// Repeated values, so a top-level JSON array can be read with
+/// `CanonicalValue<Vec<T>>`. `null` yields an empty vector, as it does for a
+/// repeated field.
+impl<T: CanonicalDeserialize> CanonicalDeserialize for Vec<T> {
+    fn deserialize_canonical<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        CanonicalVec::deserialize(deserializer).map(|values| values.0)
+    }
+}
+
+/// Internal helper for `omit_empty_message` fields: whether `message` would
+/// serialize as `{}`.
+///
+/// Runs the message's per-field presence checks, stopping at the first field
+/// that would be written, without serializing any values.
+#[doc(hidden)]
+pub fn is_empty_message<T: ProstMessage + ?Sized>(message: &T) -> bool {
+    message.serialize_fields(&mut EmptyMessageProbe).is_ok()
+}
+
+/// A map serializer that fails on its first entry.
+struct EmptyMessageProbe;
+
+#[derive(Debug)]
+struct FieldPresent;
+
+impl fmt::Display for FieldPresent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("message has a field set")
+    }
+}
+
+impl core::error::Error for FieldPresent {}
+
+impl ser::Error for FieldPresent {
+    fn custom<T: fmt::Display>(_msg: T) -> Self {
+        Self
+    }
+}
+
+impl ser::SerializeMap for EmptyMessageProbe {
+    type Ok = ();
+    type Error = FieldPresent;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, _key: &T) -> Result<(), FieldPresent> {
+        Err(FieldPresent)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, _value: &T) -> Result<(), FieldPresent> {
+        Err(FieldPresent)
+    }
+
+    fn end(self) -> Result<(), FieldPresent> {
+        Ok(())
+    }
+}
@@ -29,6 +29,45 @@
 //!
 //! The derive macros generate canonical protobuf JSON serde implementations, so
 //! you should not need to use the adapters in this crate directly.
+//!
+//! # Field order
+//! Generated `Serialize` impls emit fields in struct declaration order (which
+//! prost derives from `.proto` field order), so `serde_json::to_string` output
+//! is stable. Serializing into `serde_json::Value` instead goes through
+//! `serde_json::Map`, which sorts keys unless `serde_json`'s `preserve_order`
+//! feature is enabled; enable it if you need declaration order through
+//! `Value`.
+//!
+//! # Integer input
+//! 64-bit integer fields are written as strings, and a string is always
+//! accepted on input across the type's full range. A bare JSON number is
+//! also accepted: integer literals cover the full `i64`/`u64` range, while
+//! a number with a fraction or exponent, such as `1e3`, is read as an `f64`
+//! and must be integral and no larger than 2^53 (2^54 for `u64`) in
+//! magnitude, so that the `f64` is known to be exact. Enabling `serde_json`'s
+//! `arbitrary_precision` feature does not change which inputs are accepted.
+//!
+//! # Non-canonical options
+//! A few opt-in departures from the canonical mapping, such as encoding
+//! `Duration` as a number of milliseconds, are available through
+//! [`CanonicalOptions`], either carried by a value with
+//! `Canonical::with_options` and `CanonicalSeed` or installed for a closure
+//! with `with_options` (all require the `std` feature).
+//!
+//! # Features
+//! - `std` (default): `std` support, the non-canonical options, and the
+//!   `serde_json` helpers.
+//! - `bytes-base64` (default): base64 encoding for `bytes` fields. Schemas
+//!   without `bytes` fields can disable it to drop the `base64` dependency;
+//!   deriving on a message with a `bytes` field then fails to compile.
+//! - `chrono` (default): uses `chrono` for `Timestamp` date arithmetic and
+//!   RFC 3339 parsing. Without it a built-in implementation is used.
+//! - `indexmap`: accepts `indexmap::IndexMap` as a map field type. Entries are
+//!   written in insertion order.
+//! - `any` (default): implements the canonical traits for
+//!   `google.protobuf.Any` holding a well-known type, written as
+//!   `{"@type": ..., "value": ...}`. Other payloads are reported as errors.
+//!   Without it, deriving on a message with an `Any` field fails to compile.
 #![cfg_attr(not(feature = "std"), no_std)]
 
 extern crate alloc;
@@ -36,10 +75,38 @@ extern crate alloc;
 mod canonical;
 
 pub use canonical::{
-    Canonical, CanonicalEnum, CanonicalEnumMap, CanonicalEnumMapRef, CanonicalEnumOption,
-    CanonicalEnumSeq, CanonicalEnumValue, CanonicalEnumVec, CanonicalError, CanonicalMap,
-    CanonicalMapKey, CanonicalMapRef, CanonicalMapType, CanonicalOption, CanonicalSeq,
-    CanonicalValue, CanonicalVec,
+    Canonical, CanonicalEnum, CanonicalEnumMap, CanonicalEnumMapFieldRef, CanonicalEnumMapRef,
+    CanonicalEnumOption, CanonicalEnumSeq, CanonicalEnumValue, CanonicalEnumVec, CanonicalError,
+    CanonicalMap, CanonicalMapFieldRef, CanonicalMapKey, CanonicalMapRef, CanonicalMapType,
+    CanonicalOption, CanonicalOptions, CanonicalSeq, CanonicalValue, CanonicalVec, DurationUnit,
+    EpochTimestamp, NonFiniteValue, TimestampUnit, canonical_with,
+};
+
+#[cfg(feature = "bytes-base64")]
+pub use canonical::CanonicalBytesOrArray;
+
+#[cfg(feature = "std")]
+pub use canonical::{
+    CanonicalArrayStream, CanonicalFormatter, CanonicalSeed, CanonicalStreamError,
+    from_canonical_slice, from_canonical_str, from_canonical_value, merge_from_canonical_value,
+    to_canonical_map, to_canonical_value, to_canonical_value_masked, to_canonical_writer,
+    to_canonical_writer_pretty, to_string_with_canonical_formatter, to_string_with_newline,
+    with_options,
+};
+
+// Derived code names `alloc` through this path, so the deriving crate does
+// not need its own `extern crate alloc`.
+#[doc(hidden)]
+pub extern crate alloc as __alloc;
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use serde_json as __serde_json;
+
+#[doc(hidden)]
+pub use canonical::{
+    MergeSeed, MessageOptions, PathSeed, empty_repeated_as_null, is_empty_message, message_options,
+    reject_null_oneof_members, resolve_field_key, unwrap_message_name,
 };
 
 pub use prost_canonical_serde_derive::{CanonicalDeserialize, CanonicalSerialize};
@@ -55,8 +122,33 @@ pub trait CanonicalSerialize {
     fn serialize_canonical<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer;
+
+    /// Describes a derived message's fields for merging and masking, or
+    /// `None` for types that are handled as a single value.
+    #[doc(hidden)]
+    fn __merge_fields() -> Option<alloc::vec::Vec<MergeField>> {
+        None
+    }
+}
+
+/// Internal description of one message field, used to merge a partial JSON
+/// object onto a message and to apply field masks.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub struct MergeField {
+    pub proto_name: &'static str,
+    pub json_name: &'static str,
+    /// JSON name of the oneof the field belongs to, if any.
+    pub oneof: Option<&'static str>,
+    /// The field type's own merge fields, when it is a message.
+    pub children: MergeChildren,
 }
 
+/// Returns a field type's own merge fields, or `None` when a merge replaces
+/// the field as a whole.
+#[doc(hidden)]
+pub type MergeChildren = fn() -> Option<alloc::vec::Vec<MergeField>>;
+
 /// Deserializes a value using protobuf canonical JSON rules.
 pub trait CanonicalDeserialize: Sized {
     /// Deserializes this value from canonical protobuf JSON form.
@@ -66,20 +158,128 @@ pub trait CanonicalDeserialize: Sized {
     fn deserialize_canonical<'de, D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>;
+
+    /// Reads a partial value onto `self`. Derived messages set only the
+    /// fields the input names and merge singular message fields recursively;
+    /// every other type is replaced as a whole.
+    #[doc(hidden)]
+    fn __merge_canonical<'de, D>(&mut self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        *self = Self::deserialize_canonical(deserializer)?;
+        Ok(())
+    }
+}
+
+/// The table of fields of a derived message, for building schemas or
+/// validating input without a descriptor.
+///
+/// Derived only for messages marked `#[prost_canonical_serde(canonical_fields)]`.
+pub trait CanonicalFields {
+    /// Every field in declaration order, with oneof members in place of their
+    /// oneof and flattened fields in place of their message.
+    const FIELDS: &'static [CanonicalFieldInfo];
+}
+
+/// One entry of [`CanonicalFields::FIELDS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanonicalFieldInfo {
+    /// The field name from the `.proto` file.
+    pub proto_name: &'static str,
+    /// The name used for the field in canonical JSON output.
+    pub json_name: &'static str,
+    /// What the field holds.
+    pub kind: CanonicalFieldKind,
+    /// JSON name of the oneof the field belongs to, if any.
+    pub oneof: Option<&'static str>,
+}
+
+/// The protobuf type of a [`CanonicalFieldInfo`]. Presence (`optional`) is
+/// not part of the kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CanonicalFieldKind {
+    Bool,
+    Int32,
+    Uint32,
+    Int64,
+    Uint64,
+    Float,
+    Double,
+    String,
+    Bytes,
+    Enum,
+    Timestamp,
+    Duration,
+    /// Any other message, including the remaining well-known types.
+    Message,
+    /// A `repeated` field of the given element kind.
+    Repeated(&'static CanonicalFieldKind),
+    /// A `map` field with the given key and value kinds.
+    Map(&'static CanonicalFieldKind, &'static CanonicalFieldKind),
+    /// A type with no protobuf counterpart, such as a field encoded `with` a
+    /// custom module or a 128-bit map key.
+    Other,
 }
 
 /// Internal helper trait implemented by prost-generated enums.
 #[doc(hidden)]
 pub trait ProstEnum: Sized {
+    /// Whether quoted numbers such as `"2"` are accepted on input.
+    const ALLOW_QUOTED_NUMBERS: bool = false;
+    /// Whether values are written as numbers instead of names.
+    const SERIALIZE_AS_NUMBER: bool = false;
+
     fn from_i32(value: i32) -> Option<Self>;
     fn from_str_name(value: &str) -> Option<Self>;
     fn as_str_name(&self) -> &'static str;
     fn as_i32(&self) -> i32;
 }
 
+/// Internal helper trait implemented by derived messages.
+#[doc(hidden)]
+pub trait ProstMessage {
+    /// Every field in declaration order, with oneof members in place of their
+    /// oneof and flattened fields in place of their message. This is the
+    /// table behind [`CanonicalFields::FIELDS`] and the merge fields.
+    const FIELDS: &'static [CanonicalFieldInfo];
+
+    /// Writes the message's fields into an already open map, so a `flatten`
+    /// field can inline them into its parent.
+    fn serialize_fields<S>(&self, map: &mut S) -> Result<(), S::Error>
+    where
+        S: serde::ser::SerializeMap;
+
+    /// The [`MergeField::children`] of each entry of
+    /// [`ProstMessage::FIELDS`], in the same order.
+    fn merge_children() -> alloc::vec::Vec<MergeChildren>;
+
+    /// Every field for merging, built from [`ProstMessage::FIELDS`].
+    fn merge_fields() -> alloc::vec::Vec<MergeField> {
+        let children = Self::merge_children();
+        debug_assert_eq!(children.len(), Self::FIELDS.len());
+        Self::FIELDS
+            .iter()
+            .zip(children)
+            .map(|(field, children)| MergeField {
+                proto_name: field.proto_name,
+                json_name: field.json_name,
+                oneof: field.oneof,
+                children,
+            })
+            .collect()
+    }
+}
+
 /// Internal helper trait implemented by prost-generated oneof enums.
 #[doc(hidden)]
 pub trait ProstOneof: Sized {
+    /// JSON and proto names of every variant.
+    const FIELD_NAMES: &'static [&'static str];
+    /// Every variant as a field; the containing message fills in `oneof`.
+    const FIELDS: &'static [CanonicalFieldInfo];
+
     fn serialize_field<S>(&self, map: &mut S) -> Result<(), S::Error>
     where
         S: serde::ser::SerializeMap;
@@ -87,6 +287,29 @@ pub trait ProstOneof: Sized {
     fn try_deserialize<'de, A>(key: &str, map: &mut A) -> Result<OneofMatch<Self>, A::Error>
     where
         A: serde::de::MapAccess<'de>;
+
+    /// Reads the member under `key` onto `target`, merging a message member
+    /// that is already set and replacing anything else. Returns whether `key`
+    /// named a member.
+    fn try_merge<'de, A>(
+        key: &str,
+        map: &mut A,
+        target: &mut Option<Self>,
+    ) -> Result<bool, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        match Self::try_deserialize(key, map)? {
+            OneofMatch::Matched(value) => {
+                *target = value;
+                Ok(true)
+            }
+            OneofMatch::NoMatch => Ok(false),
+        }
+    }
+
+    /// The [`MergeField::children`] of each entry of [`ProstOneof::FIELDS`].
+    fn merge_children() -> alloc::vec::Vec<MergeChildren>;
 }
 
 /// Internal helper used to indicate oneof match outcomes.
@@ -98,6 +321,123 @@ pub enum OneofMatch<T> {
     Matched(Option<T>),
 }
 
+/// Internal guard emitted by the derives for every `bytes` field.
+#[cfg(feature = "bytes-base64")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __require_bytes_base64 {
+    () => {};
+}
+
+/// Internal guard emitted by the derives for every `bytes` field.
+#[cfg(not(feature = "bytes-base64"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __require_bytes_base64 {
+    () => {
+        ::core::compile_error!(
+            "`bytes` fields require the `bytes-base64` feature of prost-canonical-serde"
+        );
+    };
+}
+
+/// Internal guard emitted by the derives for every `google.protobuf.Any` field.
+#[cfg(feature = "any")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __require_any {
+    () => {};
+}
+
+/// Internal guard emitted by the derives for every `google.protobuf.Any` field.
+#[cfg(not(feature = "any"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __require_any {
+    () => {
+        ::core::compile_error!(
+            "`google.protobuf.Any` fields require the `any` feature of prost-canonical-serde"
+        );
+    };
+}
+
+/// Internal guard emitted by the derives for attributes that need `std`.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __require_std {
+    ($attribute:literal) => {};
+}
+
+/// Internal guard emitted by the derives for attributes that need `std`.
+#[cfg(not(feature = "std"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __require_std {
+    ($attribute:literal) => {
+        ::core::compile_error!(::core::concat!(
+            "`",
+            $attribute,
+            "` requires the `std` feature of prost-canonical-serde"
+        ));
+    };
+}
+
+/// Internal check emitted by the derive: whether any key in `a` is also in
+/// `b`. Oneof member names live on the oneof enum, so a message can only
+/// compare them with its own keys at compile time.
+#[doc(hidden)]
+#[must_use]
+pub const fn keys_overlap(a: &[&str], b: &[&str]) -> bool {
+    let mut i = 0;
+    while i < a.len() {
+        let mut j = 0;
+        while j < b.len() {
+            if const_str_eq(a[i], b[j]) {
+                return true;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    false
+}
+
+const fn const_str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Internal helper that prefixes a deserialization error with the field name.
+///
+/// Errors from nested messages, repeated elements and map entries are merged
+/// into one path, such as `error in field "nested.items[1].labels["a"]": ...`.
+#[doc(hidden)]
+pub fn field_error<E: serde::de::Error>(field: &str, err: &E) -> E {
+    canonical::with_path_segment(canonical::PathSegment::Field(field), err)
+}
+
+/// Internal helper that raises the error of a flattened message field as an
+/// error of its parent.
+///
+/// Errors naming one of the flattened message's keys keep that path, since
+/// the keys sit directly in the parent; others are prefixed with the field
+/// name.
+#[doc(hidden)]
+pub fn flattened_field_error<E: serde::de::Error>(field: &str, err: &impl core::fmt::Display) -> E {
+    canonical::with_flattened_segment(canonical::PathSegment::Field(field), err)
+}
+
 #[cfg(all(test, feature = "std"))]
 mod tests {
     use prost_canonical_serde_example::{KitchenSink, Nested, Status, kitchen_sink};
@@ -145,6 +485,7 @@ mod tests {
                 nanos: 123_000_000,
             }),
             optional_int32: None,
+            statuses: vec![Status::Active as i32, Status::Unspecified as i32],
         }
     }
 
@@ -155,4 +496,112 @@ mod tests {
         let decoded: KitchenSink = serde_json::from_str(&json).expect("deserialize canonical");
         assert_eq!(message, decoded);
     }
+
+    #[test]
+    fn unknown_enum_number_roundtrips() {
+        let message = KitchenSink {
+            status: 99,
+            ..KitchenSink::default()
+        };
+        let json = serde_json::to_string(&message).expect("serialize canonical");
+        assert_eq!(json, r#"{"status":99}"#);
+        let decoded: KitchenSink = serde_json::from_str(&json).expect("deserialize canonical");
+        assert_eq!(decoded.status, 99);
+        assert_eq!(message, decoded);
+    }
+
+    #[test]
+    fn bytes_tolerate_surrounding_whitespace() {
+        let decoded: KitchenSink =
+            serde_json::from_str(r#"{"bytesField":" AAEC/w== "}"#).expect("deserialize");
+        assert_eq!(decoded.bytes_field, vec![0, 1, 2, 255]);
+        assert!(serde_json::from_str::<KitchenSink>(r#"{"bytesField":"AAEC /w=="}"#).is_err());
+    }
+
+    #[test]
+    fn field_mask_rejects_non_snake_case_paths() {
+        let mask = prost_types::FieldMask {
+            paths: vec![String::from("foo_bar.baz")],
+        };
+        let json = serde_json::to_string(&crate::Canonical::new(&mask)).expect("serialize");
+        assert_eq!(json, r#""fooBar.baz""#);
+
+        let mask = prost_types::FieldMask {
+            paths: vec![String::from("foo.fooBar")],
+        };
+        let err = serde_json::to_string(&crate::Canonical::new(&mask)).expect_err("camel path");
+        assert_eq!(err.to_string(), "field mask path must be lower_snake_case");
+    }
+
+    #[test]
+    fn timestamp_fraction_uses_three_six_or_nine_digits() {
+        for (nanos, expected) in [
+            (100_000_000, r#""1970-01-01T00:00:00.100Z""#),
+            (120_000_000, r#""1970-01-01T00:00:00.120Z""#),
+            (123_456_000, r#""1970-01-01T00:00:00.123456Z""#),
+            (123_456_789, r#""1970-01-01T00:00:00.123456789Z""#),
+            (10, r#""1970-01-01T00:00:00.000000010Z""#),
+        ] {
+            let timestamp = prost_types::Timestamp { seconds: 0, nanos };
+            let json =
+                serde_json::to_string(&crate::Canonical::new(&timestamp)).expect("serialize");
+            assert_eq!(json, expected);
+        }
+    }
+
+    #[test]
+    fn timestamp_offsets_are_normalized_to_utc() {
+        for (input, expected) in [
+            (
+                r#""2006-01-02T15:04:05+01:00""#,
+                r#""2006-01-02T14:04:05Z""#,
+            ),
+            (
+                r#""2006-01-02T15:04:05.5-07:30""#,
+                r#""2006-01-02T22:34:05.500Z""#,
+            ),
+            (
+                r#""2006-01-02T15:04:05-00:00""#,
+                r#""2006-01-02T15:04:05Z""#,
+            ),
+            (
+                r#""2006-01-02T15:04:05+00:00""#,
+                r#""2006-01-02T15:04:05Z""#,
+            ),
+        ] {
+            let timestamp: crate::CanonicalValue<prost_types::Timestamp> =
+                serde_json::from_str(input).expect(input);
+            let json =
+                serde_json::to_string(&crate::Canonical::new(&timestamp.0)).expect("serialize");
+            assert_eq!(json, expected);
+        }
+    }
+
+    #[test]
+    fn duration_sign_and_fraction_grammar() {
+        let parse = |json: &str| {
+            serde_json::from_str::<crate::CanonicalValue<prost_types::Duration>>(json)
+                .map(|value| (value.0.seconds, value.0.nanos))
+        };
+        assert_eq!(parse(r#""+1.5s""#).expect("plus sign"), (1, 500_000_000));
+        assert_eq!(parse(r#""-0.000000001s""#).expect("negative nano"), (0, -1));
+        assert_eq!(
+            parse(r#""315576000000s""#).expect("max"),
+            (315_576_000_000, 0)
+        );
+        assert_eq!(
+            parse(r#""-315576000000s""#).expect("min"),
+            (-315_576_000_000, 0)
+        );
+        for invalid in [
+            r#""5.s""#,
+            r#""315576000001s""#,
+            r#""+-5s""#,
+            r#""--5s""#,
+            r#""++5s""#,
+            r#""5.+5s""#,
+        ] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+    }
 }
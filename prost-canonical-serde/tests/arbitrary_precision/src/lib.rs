@@ -0,0 +1,74 @@
+//! Runs with `serde_json`'s `arbitrary_precision` feature, which hands
+//! non-integer numbers to visitors as a map holding the number's text.
+
+#[cfg(test)]
+mod tests {
+    use prost_canonical_serde::{CanonicalDeserialize, CanonicalValue};
+    use prost_types::value::Kind;
+
+    fn from_str<T: CanonicalDeserialize>(json: &str) -> Result<T, serde_json::Error> {
+        serde_json::from_str::<CanonicalValue<T>>(json).map(|value| value.0)
+    }
+
+    fn error<T: CanonicalDeserialize + std::fmt::Debug>(json: &str) -> String {
+        let err = from_str::<T>(json).expect_err(json).to_string();
+        err.split(" at line").next().unwrap_or_default().to_string()
+    }
+
+    #[test]
+    fn integers_accept_their_full_range_as_bare_numbers() {
+        assert_eq!(from_str::<i64>("9223372036854775807").unwrap(), i64::MAX);
+        assert_eq!(from_str::<i64>("-9223372036854775808").unwrap(), i64::MIN);
+        assert_eq!(from_str::<u64>("18446744073709551615").unwrap(), u64::MAX);
+        assert_eq!(error::<i64>("9223372036854775808"), "i64 out of range");
+        assert_eq!(error::<u64>("18446744073709551616"), "u64 out of range");
+    }
+
+    #[test]
+    fn non_integer_numbers_behave_as_without_the_feature() {
+        assert_eq!(from_str::<i64>("1e3").unwrap(), 1000);
+        assert_eq!(from_str::<i32>("-2.0").unwrap(), -2);
+        assert_eq!(error::<i64>("1.5"), "invalid i64");
+        assert_eq!(from_str::<f64>("0.1").unwrap(), 0.1);
+        assert_eq!(from_str::<f32>("0.1").unwrap(), 0.1);
+        assert_eq!(error::<f64>("1e400"), "number out of range");
+        assert_eq!(error::<i64>(r#"{"a":1}"#), "invalid type: map, expected i64 or string");
+    }
+
+    #[test]
+    fn enum_numbers_behave_as_without_the_feature() {
+        use prost_canonical_serde::{CanonicalEnumValue, CanonicalEnumVec};
+        use prost_types::NullValue;
+
+        fn decode_error<T: for<'de> serde::Deserialize<'de>>(json: &str) -> String {
+            let Err(err) = serde_json::from_str::<T>(json) else {
+                panic!("{json} decoded");
+            };
+            let err = err.to_string();
+            err.split(" at line").next().unwrap_or_default().to_string()
+        }
+
+        let value = serde_json::from_str::<CanonicalEnumValue<NullValue>>("0").unwrap();
+        assert_eq!(value.0, 0);
+        assert_eq!(
+            decode_error::<CanonicalEnumValue<NullValue>>("1.5"),
+            "invalid type: floating point `1.5`, expected enum string or number"
+        );
+        assert_eq!(
+            decode_error::<CanonicalEnumVec<NullValue>>("1.5"),
+            "invalid type: floating point `1.5`, expected sequence"
+        );
+    }
+
+    #[test]
+    fn value_numbers_are_not_mistaken_for_structs() {
+        let value = from_str::<prost_types::Value>("0.25").unwrap();
+        assert_eq!(value.kind, Some(Kind::NumberValue(0.25)));
+
+        let value = from_str::<prost_types::Value>(r#"{"a":1.5}"#).unwrap();
+        let Some(Kind::StructValue(fields)) = value.kind else {
+            panic!("expected a struct");
+        };
+        assert_eq!(fields.fields["a"].kind, Some(Kind::NumberValue(1.5)));
+    }
+}
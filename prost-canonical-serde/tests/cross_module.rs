@@ -0,0 +1,101 @@
+//! Messages that reference enums from other modules, laid out the way prost
+//! generates code for several `.proto` packages included into sibling
+//! modules.
+
+extern crate alloc;
+
+pub mod alerts {
+    use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+    #[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+    pub struct Alert {
+        #[prost(enumeration = "super::levels::Level", tag = "1")]
+        #[prost_canonical_serde(proto_name = "level", json_name = "level")]
+        pub level: i32,
+        #[prost(enumeration = "super::levels::Level", repeated, tag = "2")]
+        #[prost_canonical_serde(proto_name = "history", json_name = "history")]
+        pub history: ::prost::alloc::vec::Vec<i32>,
+        #[prost(map = "string, enumeration(super::levels::Level)", tag = "3")]
+        #[prost_canonical_serde(proto_name = "by_host", json_name = "byHost")]
+        pub by_host: ::std::collections::HashMap<::prost::alloc::string::String, i32>,
+        #[prost(oneof = "alert::Target", tags = "4, 5")]
+        pub target: ::core::option::Option<alert::Target>,
+    }
+
+    pub mod alert {
+        use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+        #[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+        pub enum Target {
+            #[prost(string, tag = "4")]
+            #[prost_canonical_serde(proto_name = "host", json_name = "host")]
+            Host(::prost::alloc::string::String),
+            #[prost(enumeration = "super::super::levels::Level", tag = "5")]
+            #[prost_canonical_serde(proto_name = "min_level", json_name = "minLevel")]
+            MinLevel(i32),
+        }
+    }
+}
+
+pub mod levels {
+    use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        ::prost::Enumeration,
+        CanonicalSerialize,
+        CanonicalDeserialize,
+    )]
+    #[repr(i32)]
+    pub enum Level {
+        Unspecified = 0,
+        Warning = 1,
+        Critical = 2,
+    }
+
+    impl Level {
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Self::Unspecified => "LEVEL_UNSPECIFIED",
+                Self::Warning => "LEVEL_WARNING",
+                Self::Critical => "LEVEL_CRITICAL",
+            }
+        }
+
+        pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+            match value {
+                "LEVEL_UNSPECIFIED" => Some(Self::Unspecified),
+                "LEVEL_WARNING" => Some(Self::Warning),
+                "LEVEL_CRITICAL" => Some(Self::Critical),
+                _ => None,
+            }
+        }
+    }
+}
+
+use alerts::{Alert, alert::Target};
+use levels::Level;
+
+#[test]
+fn enums_from_sibling_modules_roundtrip() {
+    let alert = Alert {
+        level: Level::Critical as i32,
+        history: vec![Level::Warning as i32, Level::Critical as i32],
+        by_host: [("db".to_string(), Level::Warning as i32)].into(),
+        target: Some(Target::MinLevel(Level::Warning as i32)),
+    };
+    let json = serde_json::to_string(&alert).expect("serialize");
+    assert_eq!(
+        json,
+        r#"{"level":"LEVEL_CRITICAL","history":["LEVEL_WARNING","LEVEL_CRITICAL"],"byHost":{"db":"LEVEL_WARNING"},"minLevel":"LEVEL_WARNING"}"#
+    );
+    let decoded: Alert = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(decoded, alert);
+}
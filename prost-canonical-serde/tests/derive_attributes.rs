@@ -0,0 +1,1710 @@
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use core::marker::PhantomData;
+
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+use prost_canonical_serde_example::{Nested, Status};
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct WithSkipped {
+    #[prost_canonical_serde(proto_name = "name", json_name = "name")]
+    name: String,
+    #[prost_canonical_serde(skip)]
+    bookkeeping: Vec<u8>,
+}
+
+#[test]
+fn skipped_field_is_not_serialized_or_deserialized() {
+    let message = WithSkipped {
+        name: "demo".to_string(),
+        bookkeeping: vec![1, 2, 3],
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(json, r#"{"name":"demo"}"#);
+
+    let decoded: WithSkipped =
+        serde_json::from_str(r#"{"name":"demo","bookkeeping":"AQID"}"#).expect("deserialize");
+    assert_eq!(
+        decoded,
+        WithSkipped {
+            name: "demo".to_string(),
+            bookkeeping: Vec::new(),
+        }
+    );
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Tagged<T> {
+    #[prost_canonical_serde(proto_name = "id", json_name = "id")]
+    id: u64,
+    marker: PhantomData<T>,
+}
+
+#[test]
+fn phantom_data_field_is_ignored() {
+    let message = Tagged::<String> {
+        id: 7,
+        marker: PhantomData,
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(json, r#"{"id":"7"}"#);
+
+    let decoded: Tagged<String> =
+        serde_json::from_str(r#"{"id":"7","marker":null}"#).expect("deserialize");
+    assert_eq!(decoded, message);
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct OptionalContainers {
+    #[prost_canonical_serde(proto_name = "ids", json_name = "ids")]
+    ids: Option<Vec<i64>>,
+    #[prost_canonical_serde(proto_name = "payload", json_name = "payload")]
+    payload: Option<Vec<u8>>,
+}
+
+#[test]
+fn optional_repeated_field_serializes_when_present() {
+    let message = OptionalContainers {
+        ids: Some(vec![1, 2]),
+        payload: Some(vec![0, 255]),
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(json, r#"{"ids":["1","2"],"payload":"AP8="}"#);
+    let decoded: OptionalContainers = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(decoded, message);
+
+    let empty = OptionalContainers {
+        ids: Some(Vec::new()),
+        payload: None,
+    };
+    let json = serde_json::to_string(&empty).expect("serialize");
+    assert_eq!(json, r#"{"ids":[]}"#);
+    let decoded: OptionalContainers = serde_json::from_str("{}").expect("deserialize");
+    assert_eq!(decoded, OptionalContainers::default());
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct ExoticNames {
+    #[prost_canonical_serde(proto_name = "quoted", json_name = "quo\"ted")]
+    quoted: i32,
+    #[prost_canonical_serde(proto_name = "accented", json_name = "caf\u{e9}")]
+    accented: i32,
+    #[prost_canonical_serde(proto_name = "spaced", json_name = "a\": b.c")]
+    spaced: Vec<i32>,
+}
+
+#[test]
+fn json_names_needing_escapes_roundtrip() {
+    let message = ExoticNames {
+        quoted: 1,
+        accented: 2,
+        spaced: Vec::new(),
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(json, "{\"quo\\\"ted\":1,\"caf\u{e9}\":2}");
+
+    let decoded: ExoticNames =
+        serde_json::from_str(r#"{"quo\"ted":1,"caf\u00e9":2}"#).expect("deserialize");
+    assert_eq!(decoded, message);
+
+    // Names that are not plain words are quoted in error paths, so they read
+    // as a single segment.
+    for (json, path) in [
+        (r#"{"quo\"ted":"x"}"#, r#"["quo\"ted"]"#),
+        (r#"{"caf\u00e9":"x"}"#, "caf\u{e9}"),
+        (r#"{"a\": b.c":[1,"x"]}"#, r#"["a\": b.c"][1]"#),
+    ] {
+        let err = serde_json::from_str::<ExoticNames>(json).expect_err(json);
+        assert!(
+            err.to_string()
+                .starts_with(&format!(r#"error in field "{path}": invalid i32 string"#)),
+            "{err}"
+        );
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration, CanonicalSerialize, CanonicalDeserialize,
+)]
+#[prost_canonical_serde(allow_quoted_enum_numbers)]
+#[repr(i32)]
+enum Priority {
+    Low = 0,
+    High = 1,
+}
+
+impl Priority {
+    #[expect(
+        clippy::trivially_copy_pass_by_ref,
+        reason = "Mirrors the signature prost generates."
+    )]
+    fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Low => "PRIORITY_LOW",
+            Self::High => "PRIORITY_HIGH",
+        }
+    }
+
+    fn from_str_name(value: &str) -> Option<Self> {
+        match value {
+            "PRIORITY_LOW" => Some(Self::Low),
+            "PRIORITY_HIGH" => Some(Self::High),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct WithPriority {
+    #[prost(enumeration = "Priority")]
+    #[prost_canonical_serde(proto_name = "priority", json_name = "priority")]
+    priority: i32,
+    #[prost(enumeration = "Status")]
+    #[prost_canonical_serde(proto_name = "status", json_name = "status")]
+    status: i32,
+}
+
+#[test]
+fn quoted_enum_numbers_are_opt_in() {
+    let decoded: WithPriority = serde_json::from_str(r#"{"priority":"1"}"#).expect("deserialize");
+    assert_eq!(decoded.priority, Priority::High as i32);
+    let decoded: WithPriority = serde_json::from_str(r#"{"priority":"7"}"#).expect("deserialize");
+    assert_eq!(decoded.priority, 7);
+    let decoded: WithPriority = serde_json::from_str(r#"{"priority":"-3"}"#).expect("deserialize");
+    assert_eq!(decoded.priority, -3);
+    // Quoted numbers follow the same grammar as integer map keys.
+    for input in ["x", "+2", "002", " 2", "-0", ""] {
+        let json = format!(r#"{{"priority":"{input}"}}"#);
+        assert!(
+            serde_json::from_str::<WithPriority>(&json).is_err(),
+            "{input:?} was accepted"
+        );
+    }
+
+    // Enums without the attribute stay strict.
+    assert!(serde_json::from_str::<WithPriority>(r#"{"status":"1"}"#).is_err());
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration, CanonicalSerialize, CanonicalDeserialize,
+)]
+#[prost_canonical_serde(enum_numbers)]
+#[repr(i32)]
+enum Level {
+    Unset = 0,
+    Warn = 2,
+}
+
+impl Level {
+    #[expect(
+        clippy::trivially_copy_pass_by_ref,
+        reason = "Mirrors the signature prost generates."
+    )]
+    fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unset => "LEVEL_UNSET",
+            Self::Warn => "LEVEL_WARN",
+        }
+    }
+
+    fn from_str_name(value: &str) -> Option<Self> {
+        match value {
+            "LEVEL_UNSET" => Some(Self::Unset),
+            "LEVEL_WARN" => Some(Self::Warn),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct WithLevels {
+    #[prost(enumeration = "Level")]
+    #[prost_canonical_serde(proto_name = "level", json_name = "level")]
+    level: i32,
+    #[prost(enumeration = "Level", repeated)]
+    #[prost_canonical_serde(proto_name = "history", json_name = "history")]
+    history: Vec<i32>,
+    #[prost(map = "string, enumeration(Level)")]
+    #[prost_canonical_serde(proto_name = "by_name", json_name = "byName")]
+    by_name: BTreeMap<String, i32>,
+}
+
+#[test]
+fn enum_numbers_serializes_every_field_shape_as_numbers() {
+    assert_eq!(serde_json::to_string(&Level::Warn).expect("serialize"), "2");
+    assert_eq!(
+        serde_json::to_string(&Priority::High).expect("serialize"),
+        r#""PRIORITY_HIGH""#
+    );
+
+    let message = WithLevels {
+        level: Level::Warn as i32,
+        history: vec![Level::Unset as i32, Level::Warn as i32],
+        by_name: BTreeMap::from([("a".to_string(), Level::Warn as i32)]),
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(json, r#"{"level":2,"history":[0,2],"byName":{"a":2}}"#);
+    let decoded: WithLevels = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(decoded, message);
+
+    // Names are still accepted on input.
+    let decoded: WithLevels =
+        serde_json::from_str(r#"{"level":"LEVEL_WARN","history":["LEVEL_UNSET"]}"#)
+            .expect("deserialize names");
+    assert_eq!(decoded.level, Level::Warn as i32);
+    assert_eq!(decoded.history, vec![Level::Unset as i32]);
+}
+
+/// Shaped like prost output for `enum JobState { option allow_alias = true;
+/// ... JOB_STATE_STARTED = 1; }`: the alias has no variant of its own.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration, CanonicalSerialize, CanonicalDeserialize,
+)]
+#[prost_canonical_serde(alias(name = "JOB_STATE_STARTED", number = 1))]
+#[prost_canonical_serde(alias(name = "JOB_STATE_ERROR", number = -1))]
+#[repr(i32)]
+enum JobState {
+    Unspecified = 0,
+    Running = 1,
+    Failed = -1,
+}
+
+impl JobState {
+    #[expect(
+        clippy::trivially_copy_pass_by_ref,
+        reason = "Mirrors the signature prost generates."
+    )]
+    fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "JOB_STATE_UNSPECIFIED",
+            Self::Running => "JOB_STATE_RUNNING",
+            Self::Failed => "JOB_STATE_FAILED",
+        }
+    }
+
+    fn from_str_name(value: &str) -> Option<Self> {
+        match value {
+            "JOB_STATE_UNSPECIFIED" => Some(Self::Unspecified),
+            "JOB_STATE_RUNNING" => Some(Self::Running),
+            "JOB_STATE_FAILED" => Some(Self::Failed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Job {
+    #[prost(enumeration = "JobState")]
+    #[prost_canonical_serde(proto_name = "state", json_name = "state")]
+    state: i32,
+    #[prost(enumeration = "JobState", repeated)]
+    #[prost_canonical_serde(proto_name = "history", json_name = "history")]
+    history: Vec<i32>,
+}
+
+#[test]
+fn enum_aliases_are_accepted_and_normalized_to_the_primary_name() {
+    let job: Job = serde_json::from_str(
+        r#"{"state":"JOB_STATE_STARTED","history":["JOB_STATE_ERROR","JOB_STATE_RUNNING"]}"#,
+    )
+    .expect("deserialize aliases");
+    assert_eq!(
+        job,
+        Job {
+            state: JobState::Running as i32,
+            history: vec![JobState::Failed as i32, JobState::Running as i32],
+        }
+    );
+    assert_eq!(
+        serde_json::to_string(&job).expect("serialize"),
+        r#"{"state":"JOB_STATE_RUNNING","history":["JOB_STATE_FAILED","JOB_STATE_RUNNING"]}"#
+    );
+
+    let state: JobState = serde_json::from_str(r#""JOB_STATE_STARTED""#).expect("deserialize");
+    assert_eq!(state, JobState::Running);
+    assert!(serde_json::from_str::<JobState>(r#""JOB_STATE_BOGUS""#).is_err());
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct StatusFields {
+    #[prost(enumeration = "Status")]
+    #[prost_canonical_serde(proto_name = "single", json_name = "single")]
+    single: i32,
+    #[prost(enumeration = "Status", repeated)]
+    #[prost_canonical_serde(proto_name = "list", json_name = "list")]
+    list: Vec<i32>,
+    #[prost(map = "string, enumeration(Status)")]
+    #[prost_canonical_serde(proto_name = "by_name", json_name = "byName")]
+    by_name: BTreeMap<String, i32>,
+}
+
+#[test]
+fn unknown_enum_numbers_roundtrip_in_every_field_shape() {
+    let message = StatusFields {
+        single: 42,
+        list: vec![Status::Active as i32, 42, Status::Suspended as i32],
+        by_name: BTreeMap::from([("a".to_string(), 42), ("b".to_string(), 1)]),
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(
+        json,
+        r#"{"single":42,"list":["STATUS_ACTIVE",42,"STATUS_SUSPENDED"],"byName":{"a":42,"b":"STATUS_ACTIVE"}}"#
+    );
+    let decoded: StatusFields = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(decoded, message);
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct ListNode {
+    #[prost_canonical_serde(proto_name = "value", json_name = "value")]
+    value: i32,
+    #[prost_canonical_serde(proto_name = "next", json_name = "next")]
+    next: Option<Box<ListNode>>,
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct BoxedOptionNode {
+    #[prost_canonical_serde(proto_name = "value", json_name = "value")]
+    value: i32,
+    #[prost_canonical_serde(proto_name = "next", json_name = "next")]
+    next: Box<Option<BoxedOptionNode>>,
+}
+
+#[test]
+fn boxed_recursive_messages_roundtrip() {
+    let list = ListNode {
+        value: 1,
+        next: Some(Box::new(ListNode {
+            value: 2,
+            next: None,
+        })),
+    };
+    let json = serde_json::to_string(&list).expect("serialize");
+    assert_eq!(json, r#"{"value":1,"next":{"value":2}}"#);
+    assert_eq!(
+        serde_json::from_str::<ListNode>(&json).expect("deserialize"),
+        list
+    );
+    assert_eq!(
+        serde_json::from_str::<ListNode>(r#"{"value":1,"next":null}"#).expect("deserialize"),
+        ListNode {
+            value: 1,
+            next: None,
+        }
+    );
+
+    // An empty boxed child is still present, and errors name the full path.
+    let empty_child = ListNode {
+        value: 1,
+        next: Some(Box::default()),
+    };
+    let json = serde_json::to_string(&empty_child).expect("serialize");
+    assert_eq!(json, r#"{"value":1,"next":{}}"#);
+    assert_eq!(
+        serde_json::from_str::<ListNode>(&json).expect("deserialize"),
+        empty_child
+    );
+    let err = serde_json::from_str::<ListNode>(r#"{"next":{"next":{"value":"x"}}}"#)
+        .expect_err("invalid nested value");
+    assert!(
+        err.to_string()
+            .starts_with(r#"error in field "next.next.value": invalid i32 string"#),
+        "{err}"
+    );
+
+    let boxed = BoxedOptionNode {
+        value: 1,
+        next: Box::new(Some(BoxedOptionNode {
+            value: 2,
+            next: Box::new(None),
+        })),
+    };
+    let json = serde_json::to_string(&boxed).expect("serialize");
+    assert_eq!(json, r#"{"value":1,"next":{"value":2}}"#);
+    assert_eq!(
+        serde_json::from_str::<BoxedOptionNode>(&json).expect("deserialize"),
+        boxed
+    );
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Expr {
+    #[prost(oneof = "ExprNode", tags = "1, 2")]
+    node: Option<ExprNode>,
+}
+
+#[derive(Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+enum ExprNode {
+    #[prost(int64, tag = "1")]
+    Literal(i64),
+    #[prost(message, tag = "2")]
+    Negate(Box<Expr>),
+}
+
+mod shapes {
+    pub(crate) use super::ShapeKind as Kind;
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+#[prost_canonical_serde(canonical_fields)]
+struct Shape {
+    #[prost(oneof = "shapes::Kind", tags = "1, 2")]
+    #[prost_canonical_serde(oneof_type = "ShapeKind")]
+    kind: Option<shapes::Kind>,
+}
+
+#[derive(Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+enum ShapeKind {
+    #[prost(int32, tag = "1")]
+    #[prost_canonical_serde(proto_name = "radius", json_name = "radius")]
+    Radius(i32),
+    #[prost(int32, tag = "2")]
+    #[prost_canonical_serde(proto_name = "side", json_name = "side")]
+    Side(i32),
+}
+
+#[test]
+fn oneof_type_overrides_the_field_type_path() {
+    let shape = Shape {
+        kind: Some(ShapeKind::Side(3)),
+    };
+    let json = serde_json::to_string(&shape).expect("serialize");
+    assert_eq!(json, r#"{"side":3}"#);
+    assert_eq!(
+        serde_json::from_str::<Shape>(&json).expect("deserialize"),
+        shape
+    );
+    assert_eq!(
+        Shape::canonical_field_names(),
+        [("radius", "radius"), ("side", "side")]
+    );
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Account {
+    #[prost(oneof = "AccountState", tags = "1, 2")]
+    state: Option<AccountState>,
+}
+
+#[derive(Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+enum AccountState {
+    #[prost(enumeration = "Status", tag = "1")]
+    #[prost_canonical_serde(proto_name = "status", json_name = "status")]
+    Status(i32),
+    #[prost(string, tag = "2")]
+    #[prost_canonical_serde(proto_name = "closed_reason", json_name = "closedReason")]
+    ClosedReason(String),
+}
+
+#[test]
+fn duplicate_enum_oneof_keys_are_rejected() {
+    let account: Account =
+        serde_json::from_str(r#"{"status":"STATUS_ACTIVE"}"#).expect("deserialize");
+    assert_eq!(
+        account.state,
+        Some(AccountState::Status(Status::Active as i32))
+    );
+
+    for json in [
+        r#"{"status":"STATUS_ACTIVE","status":2}"#,
+        r#"{"status":1,"status":"STATUS_ACTIVE"}"#,
+        r#"{"status":"STATUS_ACTIVE","closedReason":"gone"}"#,
+    ] {
+        let err = serde_json::from_str::<Account>(json).expect_err(json);
+        assert!(
+            err.to_string().starts_with("multiple oneof fields set"),
+            "{json}: {err}"
+        );
+    }
+}
+
+fn negated(depth: usize) -> Expr {
+    (0..depth).fold(
+        Expr {
+            node: Some(ExprNode::Literal(1)),
+        },
+        |inner, _| Expr {
+            node: Some(ExprNode::Negate(Box::new(inner))),
+        },
+    )
+}
+
+#[test]
+fn recursive_oneof_roundtrips_and_rejects_pathological_nesting() {
+    let expr = negated(2);
+    let json = serde_json::to_string(&expr).expect("serialize");
+    assert_eq!(json, r#"{"negate":{"negate":{"literal":"1"}}}"#);
+    assert_eq!(
+        serde_json::from_str::<Expr>(&json).expect("deserialize"),
+        expr
+    );
+
+    let deep = negated(100);
+    let json = serde_json::to_string(&deep).expect("serialize deep");
+    assert_eq!(
+        serde_json::from_str::<Expr>(&json).expect("deserialize deep"),
+        deep
+    );
+
+    // serde_json's recursion limit turns runaway nesting into an error
+    // instead of a stack overflow.
+    let depth = 1_000;
+    let json = format!(
+        "{}{{\"literal\":\"1\"}}{}",
+        "{\"negate\":".repeat(depth),
+        "}".repeat(depth)
+    );
+    let err = serde_json::from_str::<Expr>(&json).expect_err("too deep");
+    assert!(
+        err.to_string().contains("recursion limit exceeded"),
+        "{err}"
+    );
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Int64Keyed {
+    #[prost(map = "int64, int32")]
+    #[prost_canonical_serde(proto_name = "counts", json_name = "counts")]
+    counts: BTreeMap<i64, i32>,
+}
+
+#[test]
+fn integer_map_keys_reject_exponent_and_float_forms() {
+    // Values accept exponent notation, keys are plain decimal only.
+    let decoded: Int64Keyed =
+        serde_json::from_str(r#"{"counts":{"1000":"1e3"}}"#).expect("deserialize");
+    assert_eq!(decoded.counts, BTreeMap::from([(1000, 1000)]));
+
+    for key in ["1e3", "1.0", "1E3", "1000.0"] {
+        let json = format!(r#"{{"counts":{{"{key}":1}}}}"#);
+        let err = serde_json::from_str::<Int64Keyed>(&json).expect_err(key);
+        assert!(
+            err.to_string().contains("invalid i64 map key"),
+            "{key}: {err}"
+        );
+    }
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct WrappedNested(prost_canonical_serde_example::Nested);
+
+#[test]
+fn newtype_struct_delegates_to_the_wrapped_message() {
+    let message = WrappedNested(prost_canonical_serde_example::Nested {
+        id: 7,
+        note: "inner".to_string(),
+    });
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(
+        json,
+        serde_json::to_string(&message.0).expect("serialize inner")
+    );
+    let decoded: WrappedNested = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(decoded, message);
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct MixedTimestamps {
+    #[prost_canonical_serde(proto_name = "legacy", json_name = "legacy", timestamp_epoch)]
+    legacy: Option<prost_types::Timestamp>,
+    #[prost_canonical_serde(proto_name = "canonical", json_name = "canonical")]
+    canonical: Option<prost_types::Timestamp>,
+}
+
+#[test]
+fn timestamp_epoch_applies_only_to_the_annotated_field() {
+    let timestamp = prost_types::Timestamp {
+        seconds: 1_136_214_245,
+        nanos: 0,
+    };
+    let message = MixedTimestamps {
+        legacy: Some(timestamp),
+        canonical: Some(timestamp),
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(
+        json,
+        r#"{"legacy":1136214245,"canonical":"2006-01-02T15:04:05Z"}"#
+    );
+    let decoded: MixedTimestamps = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(decoded, message);
+
+    let decoded: MixedTimestamps =
+        serde_json::from_str(r#"{"legacy":1136214245.5,"canonical":null}"#).expect("fraction");
+    assert_eq!(
+        decoded.legacy,
+        Some(prost_types::Timestamp {
+            seconds: 1_136_214_245,
+            nanos: 500_000_000,
+        })
+    );
+    assert_eq!(decoded.canonical, None);
+    let decoded: MixedTimestamps =
+        serde_json::from_str(r#"{"legacy":"2006-01-02T15:04:05Z"}"#).expect("string input");
+    assert_eq!(decoded.legacy, Some(timestamp));
+
+    assert!(serde_json::from_str::<MixedTimestamps>(r#"{"canonical":1136214245}"#).is_err());
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct LenientBytes {
+    #[prost_canonical_serde(proto_name = "data", json_name = "data", accept_byte_array)]
+    data: Vec<u8>,
+    #[prost_canonical_serde(proto_name = "maybe", json_name = "maybe", accept_byte_array)]
+    maybe: Option<Vec<u8>>,
+    #[prost_canonical_serde(proto_name = "strict", json_name = "strict")]
+    strict: Vec<u8>,
+}
+
+#[test]
+fn accept_byte_array_reads_arrays_but_writes_base64() {
+    let decoded: LenientBytes =
+        serde_json::from_str(r#"{"data":[0,1,2,255],"maybe":[7]}"#).expect("deserialize arrays");
+    assert_eq!(
+        decoded,
+        LenientBytes {
+            data: vec![0, 1, 2, 255],
+            maybe: Some(vec![7]),
+            strict: Vec::new(),
+        }
+    );
+    let json = serde_json::to_string(&decoded).expect("serialize");
+    assert_eq!(json, r#"{"data":"AAEC/w==","maybe":"Bw=="}"#);
+    assert_eq!(
+        serde_json::from_str::<LenientBytes>(&json).expect("deserialize base64"),
+        decoded
+    );
+
+    assert!(serde_json::from_str::<LenientBytes>(r#"{"data":[256]}"#).is_err());
+    assert!(serde_json::from_str::<LenientBytes>(r#"{"strict":[1]}"#).is_err());
+}
+
+const DIGEST_LEN: usize = 40;
+
+#[derive(Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct FixedBytes {
+    #[prost_canonical_serde(proto_name = "tag", json_name = "tag")]
+    tag: [u8; 4],
+    #[prost_canonical_serde(proto_name = "digest", json_name = "digest")]
+    digest: [u8; DIGEST_LEN],
+    #[prost_canonical_serde(proto_name = "maybe", json_name = "maybe")]
+    maybe: Option<[u8; 2]>,
+    #[prost_canonical_serde(proto_name = "pairs", json_name = "pairs")]
+    pairs: Vec<[u8; 2]>,
+}
+
+#[test]
+fn fixed_width_byte_arrays_are_base64_with_checked_length() {
+    let message = FixedBytes {
+        tag: [0, 1, 2, 255],
+        digest: [7; DIGEST_LEN],
+        maybe: Some([1, 2]),
+        pairs: vec![[3, 4]],
+    };
+    let json = serde_json::to_value(&message).expect("serialize");
+    assert_eq!(json["tag"], "AAEC/w==");
+    assert_eq!(json["maybe"], "AQI=");
+    assert_eq!(json["pairs"], serde_json::json!(["AwQ="]));
+    assert_eq!(
+        serde_json::from_value::<FixedBytes>(json).expect("deserialize"),
+        message
+    );
+
+    // Arrays are never empty, so even all-zero values are written, and
+    // missing ones read back as zeros.
+    let zeros: FixedBytes = serde_json::from_str("{}").expect("deserialize");
+    assert_eq!(zeros.digest, [0; DIGEST_LEN]);
+    let json = serde_json::to_string(&zeros).expect("serialize");
+    assert!(
+        json.starts_with(r#"{"tag":"AAAAAA==","digest":""#),
+        "{json}"
+    );
+
+    let err = serde_json::from_str::<FixedBytes>(r#"{"tag":"AAEC"}"#).expect_err("short tag");
+    assert!(
+        err.to_string()
+            .starts_with(r#"error in field "tag": expected 4 bytes, found 3"#),
+        "{err}"
+    );
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Ping;
+
+#[test]
+fn unit_types_are_empty_messages() {
+    assert_eq!(serde_json::to_string(&Ping).expect("serialize"), "{}");
+    let decoded: Ping = serde_json::from_str(r#"{"ignored":1}"#).expect("deserialize");
+    assert_eq!(decoded, Ping);
+
+    let empty = prost_canonical_serde::Canonical::new(&());
+    assert_eq!(serde_json::to_string(&empty).expect("serialize"), "{}");
+    let decoded: prost_canonical_serde::CanonicalValue<()> =
+        serde_json::from_str(r#"{"ignored":[1]}"#).expect("deserialize");
+    assert_eq!(decoded.0, ());
+    assert!(serde_json::from_str::<prost_canonical_serde::CanonicalValue<()>>("null").is_err());
+}
+
+#[test]
+fn integer_map_keys_must_be_canonical_decimal() {
+    for key in ["+1", "01", " 1", "1 ", "-0", "-", ""] {
+        let json = format!(r#"{{"counts":{{"{key}":1}}}}"#);
+        let err = serde_json::from_str::<Int64Keyed>(&json).expect_err(key);
+        assert!(
+            err.to_string()
+                .contains(&format!("invalid i64 map key {key:?}")),
+            "{key}: {err}"
+        );
+    }
+    let decoded: Int64Keyed =
+        serde_json::from_str(r#"{"counts":{"0":1,"-7":2,"10":3}}"#).expect("deserialize");
+    assert_eq!(decoded.counts, BTreeMap::from([(0, 1), (-7, 2), (10, 3)]));
+}
+
+/// Not a valid protobuf map, but usable in hand-written messages.
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct WideKeyed {
+    #[prost_canonical_serde(proto_name = "signed", json_name = "signed")]
+    signed: BTreeMap<i128, String>,
+    #[prost_canonical_serde(proto_name = "unsigned", json_name = "unsigned")]
+    unsigned: BTreeMap<u128, i32>,
+}
+
+#[test]
+fn wide_integer_map_keys_use_decimal_strings() {
+    let message = WideKeyed {
+        signed: BTreeMap::from([(i128::MIN, "min".to_string()), (1, "one".to_string())]),
+        unsigned: BTreeMap::from([(u128::MAX, 7)]),
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(
+        json,
+        r#"{"signed":{"-170141183460469231731687303715884105728":"min","1":"one"},"unsigned":{"340282366920938463463374607431768211455":7}}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<WideKeyed>(&json).expect("deserialize"),
+        message
+    );
+
+    let err = serde_json::from_str::<WideKeyed>(r#"{"unsigned":{"-1":1}}"#).expect_err("negative");
+    assert!(
+        err.to_string().contains(r#"invalid u128 map key "-1""#),
+        "{err}"
+    );
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct BoolKeyed {
+    #[prost(map = "bool, string")]
+    #[prost_canonical_serde(proto_name = "flags", json_name = "flags")]
+    flags: BTreeMap<bool, String>,
+    #[prost(map = "bool, enumeration(Status)")]
+    #[prost_canonical_serde(proto_name = "statuses", json_name = "statuses")]
+    statuses: BTreeMap<bool, i32>,
+}
+
+#[test]
+fn bool_map_keys_are_exactly_true_or_false() {
+    let message = BoolKeyed {
+        flags: BTreeMap::from([(false, "no".to_string()), (true, "yes".to_string())]),
+        statuses: BTreeMap::from([(true, Status::Active as i32)]),
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(
+        json,
+        r#"{"flags":{"false":"no","true":"yes"},"statuses":{"true":"STATUS_ACTIVE"}}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<BoolKeyed>(&json).expect("deserialize"),
+        message
+    );
+
+    for key in ["True", "TRUE", "1", "0", " true", ""] {
+        let json = format!(r#"{{"flags":{{"{key}":"x"}}}}"#);
+        let err = serde_json::from_str::<BoolKeyed>(&json).expect_err(key);
+        assert!(
+            err.to_string().contains("invalid bool map key"),
+            "{key}: {err}"
+        );
+        let json = format!(r#"{{"statuses":{{"{key}":0}}}}"#);
+        assert!(serde_json::from_str::<BoolKeyed>(&json).is_err(), "{key}");
+    }
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+#[prost_canonical_serde(try_from_value)]
+struct Request {
+    #[prost_canonical_serde(proto_name = "user_id", json_name = "userId")]
+    user_id: u64,
+}
+
+#[test]
+fn try_from_value_uses_canonical_rules() {
+    let request =
+        Request::try_from(serde_json::json!({"user_id": "18446744073709551615"})).expect("convert");
+    assert_eq!(request.user_id, u64::MAX);
+
+    let request: Request = serde_json::json!({"userId": 7})
+        .try_into()
+        .expect("convert");
+    assert_eq!(request, Request { user_id: 7 });
+
+    let err: serde_json::Error =
+        Request::try_from(serde_json::json!({"userId": -1})).expect_err("negative id");
+    assert!(err.to_string().contains("userId"), "{err}");
+}
+
+/// Shaped like prost's output for proto2 `optional` scalars, which track
+/// explicit presence.
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Proto2Optionals {
+    #[prost(int32, optional, tag = "1")]
+    #[prost_canonical_serde(proto_name = "count", json_name = "count")]
+    count: Option<i32>,
+    #[prost(uint64, optional, tag = "2")]
+    #[prost_canonical_serde(proto_name = "total", json_name = "total")]
+    total: Option<u64>,
+    #[prost(bool, optional, tag = "3")]
+    #[prost_canonical_serde(proto_name = "enabled", json_name = "enabled")]
+    enabled: Option<bool>,
+    #[prost(double, optional, tag = "4")]
+    #[prost_canonical_serde(proto_name = "ratio", json_name = "ratio")]
+    ratio: Option<f64>,
+    #[prost(string, optional, tag = "5")]
+    #[prost_canonical_serde(proto_name = "label", json_name = "label")]
+    label: Option<String>,
+    #[prost(bytes = "vec", optional, tag = "6")]
+    #[prost_canonical_serde(proto_name = "payload", json_name = "payload")]
+    payload: Option<Vec<u8>>,
+    #[prost(enumeration = "Status", optional, tag = "7")]
+    #[prost_canonical_serde(proto_name = "status", json_name = "status")]
+    status: Option<i32>,
+}
+
+#[test]
+fn proto2_optional_defaults_are_emitted_when_set() {
+    let unset = Proto2Optionals::default();
+    assert_eq!(serde_json::to_string(&unset).expect("serialize"), "{}");
+
+    let zeros = Proto2Optionals {
+        count: Some(0),
+        total: Some(0),
+        enabled: Some(false),
+        ratio: Some(0.0),
+        label: Some(String::new()),
+        payload: Some(Vec::new()),
+        status: Some(0),
+    };
+    let json = serde_json::to_string(&zeros).expect("serialize");
+    assert_eq!(
+        json,
+        r#"{"count":0,"total":"0","enabled":false,"ratio":"0","label":"","payload":"","status":"STATUS_UNSPECIFIED"}"#
+    );
+    let decoded: Proto2Optionals = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(decoded, zeros);
+
+    // `null` leaves a field unset rather than setting its default.
+    let decoded: Proto2Optionals =
+        serde_json::from_str(r#"{"count":null,"status":null}"#).expect("deserialize");
+    assert_eq!(decoded, unset);
+}
+
+/// Shaped like prost's output for proto2 `required` fields declared with
+/// `[default = ...]`.
+#[derive(Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Proto2Defaults {
+    #[prost(int32, required, tag = "1", default = "7")]
+    #[prost_canonical_serde(proto_name = "retries", json_name = "retries", default = "7")]
+    retries: i32,
+    #[prost(string, required, tag = "2", default = "primary")]
+    #[prost_canonical_serde(
+        proto_name = "region",
+        json_name = "region",
+        default = "String::from(\"primary\")"
+    )]
+    region: String,
+    #[prost(int32, required, tag = "3")]
+    #[prost_canonical_serde(proto_name = "port", json_name = "port")]
+    port: i32,
+}
+
+#[test]
+fn default_attribute_sets_the_initial_value() {
+    let decoded: Proto2Defaults = serde_json::from_str("{}").expect("deserialize");
+    assert_eq!(
+        decoded,
+        Proto2Defaults {
+            retries: 7,
+            region: "primary".to_string(),
+            port: 0,
+        }
+    );
+
+    let decoded: Proto2Defaults =
+        serde_json::from_str(r#"{"retries":0,"region":null,"port":80}"#).expect("deserialize");
+    assert_eq!(
+        decoded,
+        Proto2Defaults {
+            retries: 0,
+            region: "primary".to_string(),
+            port: 80,
+        }
+    );
+
+    let json = serde_json::to_string(&decoded).expect("serialize");
+    assert_eq!(json, r#"{"retries":0,"port":80}"#);
+    assert_eq!(
+        serde_json::from_str::<Proto2Defaults>(&json).expect("roundtrip"),
+        decoded
+    );
+}
+
+mod hex_bytes {
+    use core::fmt::Write;
+
+    pub fn serialize<S: serde::Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut hex = String::new();
+        for byte in value {
+            write!(hex, "{byte:02x}").expect("write to string");
+        }
+        serializer.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let hex: String = serde::Deserialize::deserialize(deserializer)?;
+        (0..hex.len())
+            .step_by(2)
+            .map(|index| {
+                hex.get(index..index + 2)
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                    .ok_or_else(|| serde::de::Error::custom("invalid hex"))
+            })
+            .collect()
+    }
+}
+
+/// Plain serde encoding, e.g. an `int64` as a JSON number.
+mod plain {
+    pub fn serialize<T: serde::Serialize, S: serde::Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: serde::Deserialize<'de>, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        T::deserialize(deserializer)
+    }
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct CustomEncoded<T> {
+    #[prost(bytes = "vec", tag = "1")]
+    #[prost_canonical_serde(proto_name = "digest", json_name = "digest", with = "hex_bytes")]
+    digest: Vec<u8>,
+    #[prost(int64, tag = "2")]
+    #[prost_canonical_serde(proto_name = "size", json_name = "size", with = "plain")]
+    size: i64,
+    #[prost(int64, tag = "3")]
+    #[prost_canonical_serde(
+        proto_name = "limit",
+        json_name = "limit",
+        with = "plain",
+        default = "-1"
+    )]
+    limit: i64,
+    #[prost(int64, tag = "4")]
+    #[prost_canonical_serde(proto_name = "canonical", json_name = "canonical")]
+    canonical: i64,
+    marker: PhantomData<T>,
+}
+
+#[test]
+fn with_attribute_replaces_the_canonical_encoding() {
+    let message = CustomEncoded::<u8> {
+        digest: vec![0xde, 0xad, 0x01],
+        size: 42,
+        limit: 5,
+        canonical: 42,
+        marker: PhantomData,
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(
+        json,
+        r#"{"digest":"dead01","size":42,"limit":5,"canonical":"42"}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<CustomEncoded<u8>>(&json).expect("deserialize"),
+        message
+    );
+
+    // Default values are still omitted, and absent or null keys keep them;
+    // the `default` attribute, not zero, decides what is omitted.
+    let empty = CustomEncoded::<u8> {
+        limit: -1,
+        ..CustomEncoded::default()
+    };
+    assert_eq!(serde_json::to_string(&empty).expect("serialize"), "{}");
+    let zero_limit = CustomEncoded::<u8>::default();
+    assert_eq!(
+        serde_json::to_string(&zero_limit).expect("serialize"),
+        r#"{"limit":0}"#
+    );
+    let decoded: CustomEncoded<u8> =
+        serde_json::from_str(r#"{"digest":null}"#).expect("deserialize");
+    assert_eq!(decoded.digest, Vec::<u8>::new());
+    assert_eq!(decoded.limit, -1);
+
+    let err =
+        serde_json::from_str::<CustomEncoded<u8>>(r#"{"digest":"zz"}"#).expect_err("invalid hex");
+    assert!(
+        err.to_string()
+            .starts_with(r#"error in field "digest": invalid hex"#),
+        "{err}"
+    );
+    assert!(serde_json::from_str::<CustomEncoded<u8>>(r#"{"size":"42"}"#).is_err());
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct RepeatedWellKnown {
+    #[prost(message, repeated, tag = "1")]
+    #[prost_canonical_serde(proto_name = "values", json_name = "values")]
+    values: Vec<prost_types::Value>,
+    #[prost(message, repeated, tag = "2")]
+    #[prost_canonical_serde(proto_name = "times", json_name = "times")]
+    times: Vec<prost_types::Timestamp>,
+    #[prost(message, repeated, tag = "3")]
+    #[prost_canonical_serde(proto_name = "spans", json_name = "spans")]
+    spans: Vec<prost_types::Duration>,
+}
+
+#[test]
+fn repeated_well_known_types_roundtrip() {
+    use prost_types::value::Kind;
+
+    let value = |kind| prost_types::Value { kind: Some(kind) };
+    let message = RepeatedWellKnown {
+        values: vec![
+            value(Kind::NullValue(0)),
+            value(Kind::StringValue("s".to_string())),
+            value(Kind::BoolValue(true)),
+        ],
+        times: vec![
+            prost_types::Timestamp {
+                seconds: 0,
+                nanos: 0,
+            },
+            prost_types::Timestamp {
+                seconds: 1,
+                nanos: 500_000_000,
+            },
+        ],
+        spans: vec![prost_types::Duration {
+            seconds: -1,
+            nanos: -500_000_000,
+        }],
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(
+        json,
+        r#"{"values":[null,"s",true],"times":["1970-01-01T00:00:00Z","1970-01-01T00:00:01.500Z"],"spans":["-1.5s"]}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<RepeatedWellKnown>(&json).expect("deserialize"),
+        message
+    );
+
+    // Elements are read like `CanonicalValue<T>`, so only `Value` gives
+    // `null` a meaning inside a list.
+    for json in [r#"{"times":[null]}"#, r#"{"spans":[null]}"#] {
+        assert!(
+            serde_json::from_str::<RepeatedWellKnown>(json).is_err(),
+            "{json}"
+        );
+    }
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct ValueMap {
+    #[prost(map = "string, message", tag = "1")]
+    #[prost_canonical_serde(proto_name = "fields", json_name = "fields")]
+    fields: BTreeMap<String, prost_types::Value>,
+}
+
+#[test]
+fn value_map_values_keep_nested_json_and_nulls() {
+    let json =
+        r#"{"fields":{"list":[1.5,"two",{"three":[]}],"missing":null,"object":{"a":{"b":false}}}}"#;
+    let message: ValueMap = serde_json::from_str(json).expect("deserialize");
+    assert_eq!(message.fields.len(), 3);
+    assert_eq!(
+        message.fields["missing"],
+        prost_types::Value {
+            kind: Some(prost_types::value::Kind::NullValue(0)),
+        }
+    );
+    assert_eq!(serde_json::to_string(&message).expect("serialize"), json);
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct PageInfo {
+    #[prost(int32, tag = "1")]
+    #[prost_canonical_serde(proto_name = "page_size", json_name = "pageSize")]
+    page_size: i32,
+    #[prost(string, tag = "2")]
+    #[prost_canonical_serde(proto_name = "page_token", json_name = "pageToken")]
+    page_token: String,
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Audit {
+    #[prost(string, tag = "1")]
+    #[prost_canonical_serde(proto_name = "editor", json_name = "editor")]
+    editor: String,
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+#[prost_canonical_serde(canonical_fields)]
+struct ListRequest {
+    #[prost(string, tag = "1")]
+    #[prost_canonical_serde(proto_name = "parent", json_name = "parent")]
+    parent: String,
+    #[prost(message, required, tag = "2")]
+    #[prost_canonical_serde(proto_name = "page", json_name = "page", flatten)]
+    page: PageInfo,
+    #[prost(message, optional, tag = "3")]
+    #[prost_canonical_serde(proto_name = "audit", json_name = "audit", flatten)]
+    audit: Option<Audit>,
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct FlattenNames {
+    #[prost(string, tag = "1")]
+    #[prost_canonical_serde(proto_name = "flattened", json_name = "flattened")]
+    flattened: String,
+    #[prost(bool, tag = "2")]
+    #[prost_canonical_serde(proto_name = "present", json_name = "present")]
+    present: bool,
+    #[prost(message, optional, tag = "3")]
+    #[prost_canonical_serde(proto_name = "audit", json_name = "audit", flatten)]
+    audit: Option<Audit>,
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct FlattenedAccount {
+    #[prost(message, required, tag = "1")]
+    #[prost_canonical_serde(proto_name = "account", json_name = "account", flatten)]
+    account: Account,
+}
+
+#[test]
+fn flatten_inlines_message_fields_into_the_parent() {
+    let message = ListRequest {
+        parent: "shelves/1".to_string(),
+        page: PageInfo {
+            page_size: 10,
+            page_token: "next".to_string(),
+        },
+        audit: Some(Audit {
+            editor: "ana".to_string(),
+        }),
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(
+        json,
+        r#"{"parent":"shelves/1","pageSize":10,"pageToken":"next","editor":"ana"}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<ListRequest>(&json).expect("deserialize"),
+        message
+    );
+    assert_eq!(
+        ListRequest::canonical_field_names(),
+        [
+            ("parent", "parent"),
+            ("page_size", "pageSize"),
+            ("page_token", "pageToken"),
+            ("editor", "editor"),
+        ]
+    );
+
+    // Proto names work too, and an absent optional message stays unset.
+    let decoded: ListRequest =
+        serde_json::from_str(r#"{"page_size":5,"unknown":true}"#).expect("deserialize");
+    assert_eq!(
+        decoded,
+        ListRequest {
+            page: PageInfo {
+                page_size: 5,
+                ..PageInfo::default()
+            },
+            ..ListRequest::default()
+        }
+    );
+
+    let err = serde_json::from_str::<ListRequest>(r#"{"pageSize":"x"}"#).expect_err("bad size");
+    assert!(
+        err.to_string()
+            .starts_with(r#"error in field "pageSize": invalid i32 string"#),
+        "{err}"
+    );
+
+    // Errors that name no key of the flattened message are placed under it.
+    let err = serde_json::from_str::<FlattenedAccount>(
+        r#"{"status":"STATUS_ACTIVE","closedReason":"moved"}"#,
+    )
+    .expect_err("two oneof members");
+    assert!(err.is_data(), "{err}");
+    assert!(
+        err.to_string()
+            .starts_with(r#"error in field "account": multiple oneof fields set"#),
+        "{err}"
+    );
+}
+
+#[test]
+fn flatten_fields_may_share_names_with_generated_locals() {
+    let message = FlattenNames {
+        flattened: "yes".to_string(),
+        present: true,
+        audit: Some(Audit {
+            editor: "ana".to_string(),
+        }),
+    };
+    let json = r#"{"flattened":"yes","present":true,"editor":"ana"}"#;
+    assert_eq!(serde_json::to_string(&message).expect("serialize"), json);
+    assert_eq!(
+        serde_json::from_str::<FlattenNames>(json).expect("deserialize"),
+        message
+    );
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct WithAny {
+    #[prost(message, optional, tag = "1")]
+    #[prost_canonical_serde(proto_name = "detail", json_name = "detail")]
+    detail: Option<::prost_types::Any>,
+    #[prost(message, repeated, tag = "2")]
+    #[prost_canonical_serde(proto_name = "details", json_name = "details")]
+    details: Vec<::prost_types::Any>,
+}
+
+#[test]
+fn unsupported_any_errors_name_the_field() {
+    let unsupported = r#"google.protobuf.Any is only supported for well-known types, not "type.googleapis.com/demo.Nested""#;
+    for (json, field) in [
+        (
+            r#"{"detail":{"@type":"type.googleapis.com/demo.Nested","id":1}}"#,
+            "detail",
+        ),
+        (
+            r#"{"details":[{"@type":"type.googleapis.com/demo.Nested"}]}"#,
+            "details[0]",
+        ),
+    ] {
+        let err = serde_json::from_str::<WithAny>(json).expect_err(json);
+        assert!(
+            err.to_string()
+                .starts_with(&format!(r#"error in field "{field}": {unsupported}"#)),
+            "{err}"
+        );
+    }
+
+    let message = WithAny {
+        details: vec![::prost_types::Any {
+            type_url: "type.googleapis.com/demo.Nested".to_string(),
+            value: Vec::new(),
+        }],
+        ..WithAny::default()
+    };
+    let err = serde_json::to_string(&message).expect_err("serialize");
+    assert_eq!(err.to_string(), unsupported);
+    assert_eq!(
+        serde_json::to_string(&WithAny::default()).expect("serialize"),
+        "{}"
+    );
+}
+
+#[test]
+fn any_wraps_well_known_payloads_under_value() {
+    use prost::Message;
+
+    let any = |name: &str, value: Vec<u8>| ::prost_types::Any {
+        type_url: format!("type.googleapis.com/google.protobuf.{name}"),
+        value,
+    };
+    let struct_payload = ::prost_types::Struct {
+        fields: BTreeMap::from([(
+            "a".to_string(),
+            ::prost_types::Value {
+                kind: Some(::prost_types::value::Kind::BoolValue(true)),
+            },
+        )]),
+    };
+    let message = WithAny {
+        detail: Some(any(
+            "Duration",
+            ::prost_types::Duration {
+                seconds: 1,
+                nanos: 500_000_000,
+            }
+            .encode_to_vec(),
+        )),
+        details: vec![
+            any(
+                "Timestamp",
+                ::prost_types::Timestamp {
+                    seconds: 0,
+                    nanos: 0,
+                }
+                .encode_to_vec(),
+            ),
+            any(
+                "FieldMask",
+                ::prost_types::FieldMask {
+                    paths: vec!["foo_bar".to_string()],
+                }
+                .encode_to_vec(),
+            ),
+            any("Struct", struct_payload.encode_to_vec()),
+            any("Int64Value", 7_i64.encode_to_vec()),
+            any("StringValue", "s".to_string().encode_to_vec()),
+            any("BytesValue", vec![1_u8, 2].encode_to_vec()),
+            any("Empty", ().encode_to_vec()),
+        ],
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(
+        json,
+        concat!(
+            r#"{"detail":{"@type":"type.googleapis.com/google.protobuf.Duration","value":"1.5s"},"#,
+            r#""details":["#,
+            r#"{"@type":"type.googleapis.com/google.protobuf.Timestamp","value":"1970-01-01T00:00:00Z"},"#,
+            r#"{"@type":"type.googleapis.com/google.protobuf.FieldMask","value":"fooBar"},"#,
+            r#"{"@type":"type.googleapis.com/google.protobuf.Struct","value":{"a":true}},"#,
+            r#"{"@type":"type.googleapis.com/google.protobuf.Int64Value","value":"7"},"#,
+            r#"{"@type":"type.googleapis.com/google.protobuf.StringValue","value":"s"},"#,
+            r#"{"@type":"type.googleapis.com/google.protobuf.BytesValue","value":"AQI="},"#,
+            r#"{"@type":"type.googleapis.com/google.protobuf.Empty","value":{}}]}"#,
+        )
+    );
+    assert_eq!(
+        serde_json::from_str::<WithAny>(&json).expect("deserialize"),
+        message
+    );
+
+    // `value` may come first, and an empty object is a default `Any`.
+    let decoded: WithAny = serde_json::from_str(
+        r#"{"detail":{"value":"1.5s","@type":"type.googleapis.com/google.protobuf.Duration"},"details":[{}]}"#,
+    )
+    .expect("deserialize");
+    assert_eq!(decoded.detail, message.detail);
+    assert_eq!(decoded.details, [::prost_types::Any::default()]);
+    assert_eq!(
+        serde_json::to_string(&decoded).expect("serialize"),
+        concat!(
+            r#"{"detail":{"@type":"type.googleapis.com/google.protobuf.Duration","value":"1.5s"},"#,
+            r#""details":[{}]}"#,
+        )
+    );
+
+    for (json, path) in [
+        (r#"{"detail":{"value":"1.5s"}}"#, "detail"),
+        (
+            r#"{"detail":{"@type":"type.googleapis.com/google.protobuf.Duration","value":"soon"}}"#,
+            "detail.value",
+        ),
+        (
+            r#"{"detail":{"@type":"type.googleapis.com/google.protobuf.Duration","value":"1s","bogus":1}}"#,
+            "detail",
+        ),
+    ] {
+        let err = serde_json::from_str::<WithAny>(json).expect_err(json);
+        assert!(
+            err.to_string()
+                .starts_with(&format!(r#"error in field "{path}": "#)),
+            "{err}"
+        );
+    }
+}
+
+// A flattened message needs no `canonical_fields` of its own.
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Paging {
+    #[prost(int32, tag = "1")]
+    #[prost_canonical_serde(proto_name = "page_size", json_name = "pageSize")]
+    page_size: i32,
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+#[prost_canonical_serde(canonical_fields)]
+struct Described {
+    #[prost(uint64, optional, tag = "1")]
+    #[prost_canonical_serde(proto_name = "count", json_name = "count")]
+    count: Option<u64>,
+    #[prost(enumeration = "Status", repeated, tag = "2")]
+    #[prost_canonical_serde(proto_name = "statuses", json_name = "statuses")]
+    statuses: Vec<i32>,
+    #[prost(map = "string, message", tag = "3")]
+    #[prost_canonical_serde(proto_name = "by_name", json_name = "byName")]
+    by_name: BTreeMap<String, Paging>,
+    #[prost(message, optional, tag = "4")]
+    #[prost_canonical_serde(proto_name = "created", json_name = "created")]
+    created: Option<prost_types::Timestamp>,
+    #[prost(message, required, tag = "5")]
+    #[prost_canonical_serde(proto_name = "paging", json_name = "paging", flatten)]
+    paging: Paging,
+    #[prost(oneof = "AccountState", tags = "6, 7")]
+    state: Option<AccountState>,
+}
+
+#[test]
+fn canonical_fields_lists_names_and_kinds() {
+    use prost_canonical_serde::{CanonicalFieldInfo, CanonicalFieldKind, CanonicalFields};
+
+    let field = |proto_name, json_name, kind, oneof| CanonicalFieldInfo {
+        proto_name,
+        json_name,
+        kind,
+        oneof,
+    };
+    assert_eq!(
+        Described::FIELDS,
+        [
+            field("count", "count", CanonicalFieldKind::Uint64, None),
+            field(
+                "statuses",
+                "statuses",
+                CanonicalFieldKind::Repeated(&CanonicalFieldKind::Enum),
+                None,
+            ),
+            field(
+                "by_name",
+                "byName",
+                CanonicalFieldKind::Map(&CanonicalFieldKind::String, &CanonicalFieldKind::Message),
+                None,
+            ),
+            field("created", "created", CanonicalFieldKind::Timestamp, None),
+            field("page_size", "pageSize", CanonicalFieldKind::Int32, None),
+            field("status", "status", CanonicalFieldKind::Enum, Some("state")),
+            field(
+                "closed_reason",
+                "closedReason",
+                CanonicalFieldKind::String,
+                Some("state"),
+            ),
+        ]
+    );
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Sparse {
+    #[prost(message, optional, tag = "1")]
+    #[prost_canonical_serde(proto_name = "kept", json_name = "kept")]
+    kept: Option<Nested>,
+    #[prost(message, optional, tag = "2")]
+    #[prost_canonical_serde(proto_name = "omitted", json_name = "omitted", omit_empty_message)]
+    omitted: Option<Nested>,
+    #[prost(message, optional, boxed, tag = "3")]
+    #[prost_canonical_serde(proto_name = "boxed", json_name = "boxed", omit_empty_message)]
+    boxed: Option<Box<Nested>>,
+    #[prost(int32, tag = "4")]
+    #[prost_canonical_serde(proto_name = "after", json_name = "after")]
+    after: i32,
+}
+
+#[test]
+fn omit_empty_message_skips_fields_that_would_be_empty_objects() {
+    let message = Sparse {
+        kept: Some(Nested::default()),
+        omitted: Some(Nested::default()),
+        boxed: Some(Box::default()),
+        after: 1,
+    };
+    assert_eq!(
+        serde_json::to_string(&message).expect("serialize"),
+        r#"{"kept":{},"after":1}"#
+    );
+
+    let message = Sparse {
+        omitted: Some(Nested {
+            id: 1,
+            note: String::new(),
+        }),
+        boxed: Some(Box::new(Nested {
+            id: 0,
+            note: "n".to_string(),
+        })),
+        ..Sparse::default()
+    };
+    assert_eq!(
+        serde_json::to_string(&message).expect("serialize"),
+        r#"{"omitted":{"id":1},"boxed":{"note":"n"}}"#
+    );
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Patched {
+    #[prost(string, tag = "1")]
+    #[prost_canonical_serde(proto_name = "name", json_name = "name")]
+    name: String,
+    #[prost_canonical_serde(skip)]
+    cache: Vec<u8>,
+    #[prost(message, optional, tag = "2")]
+    #[prost_canonical_serde(proto_name = "empty", json_name = "empty", omit_empty_message)]
+    empty: Option<Nested>,
+    #[prost(message, optional, tag = "3")]
+    #[prost_canonical_serde(proto_name = "seen", json_name = "seen", timestamp_epoch)]
+    seen: Option<prost_types::Timestamp>,
+    #[prost(message, optional, boxed, tag = "4")]
+    #[prost_canonical_serde(proto_name = "inner", json_name = "inner")]
+    inner: Option<Box<WithSkipped>>,
+}
+
+#[test]
+fn merge_leaves_fields_the_patch_does_not_name_untouched() {
+    use prost_canonical_serde::merge_from_canonical_value;
+
+    let original = || Patched {
+        name: "a".to_string(),
+        cache: vec![1, 2, 3],
+        empty: Some(Nested::default()),
+        seen: Some(prost_types::Timestamp {
+            seconds: 1_136_214_245,
+            nanos: 123_456_789,
+        }),
+        inner: Some(Box::new(WithSkipped {
+            name: "inner".to_string(),
+            bookkeeping: vec![4, 5],
+        })),
+    };
+    let mut message = original();
+    merge_from_canonical_value(&mut message, serde_json::json!({"name": "b"})).expect("merge");
+    assert_eq!(
+        message,
+        Patched {
+            name: "b".to_string(),
+            ..original()
+        }
+    );
+
+    // Nested messages are merged in place, so their own untouched fields,
+    // including skipped ones, are kept too.
+    merge_from_canonical_value(&mut message, serde_json::json!({"inner": {"name": "c"}}))
+        .expect("merge");
+    assert_eq!(
+        message.inner,
+        Some(Box::new(WithSkipped {
+            name: "c".to_string(),
+            bookkeeping: vec![4, 5],
+        }))
+    );
+
+    merge_from_canonical_value(
+        &mut message,
+        serde_json::json!({"inner": null, "seen": null}),
+    )
+    .expect("merge");
+    assert_eq!(
+        message,
+        Patched {
+            name: "b".to_string(),
+            seen: None,
+            inner: None,
+            ..original()
+        }
+    );
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Shared {
+    #[prost(message, optional, tag = "1")]
+    #[prost_canonical_serde(proto_name = "nested", json_name = "nested")]
+    nested: Option<std::sync::Arc<Nested>>,
+}
+
+#[test]
+fn merge_and_mask_descend_through_arc_fields() {
+    use prost_canonical_serde::{merge_from_canonical_value, to_canonical_value_masked};
+
+    let mut message = Shared {
+        nested: Some(std::sync::Arc::new(Nested {
+            id: 1,
+            note: "old".to_string(),
+        })),
+    };
+    merge_from_canonical_value(&mut message, serde_json::json!({"nested": {"note": "new"}}))
+        .expect("merge");
+    assert_eq!(
+        message.nested.as_deref(),
+        Some(&Nested {
+            id: 1,
+            note: "new".to_string(),
+        })
+    );
+
+    let mask = prost_types::FieldMask {
+        paths: vec!["nested.note".to_string()],
+    };
+    assert_eq!(
+        to_canonical_value_masked(&message, &mask).expect("mask"),
+        serde_json::json!({"nested": {"note": "new"}})
+    );
+}
+
+type FixedState = core::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct CustomHasherMaps {
+    #[prost(map = "string, int32", tag = "1")]
+    #[prost_canonical_serde(proto_name = "counts", json_name = "counts")]
+    counts: std::collections::HashMap<String, i32, FixedState>,
+    #[prost(map = "int64, string", optional, tag = "2")]
+    #[prost_canonical_serde(proto_name = "labels", json_name = "labels")]
+    labels: Option<std::collections::HashMap<i64, String, FixedState>>,
+}
+
+#[test]
+fn maps_with_custom_hashers_roundtrip() {
+    let mut message = CustomHasherMaps::default();
+    message.counts.insert("a".to_string(), 1);
+    let mut labels = std::collections::HashMap::default();
+    labels.insert(-2, "minus two".to_string());
+    message.labels = Some(labels);
+
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(json, r#"{"counts":{"a":1},"labels":{"-2":"minus two"}}"#);
+    let decoded: CustomHasherMaps = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(decoded, message);
+    let empty: CustomHasherMaps = serde_json::from_str("{}").expect("deserialize empty");
+    assert_eq!(empty, CustomHasherMaps::default());
+}
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct FirstKeyField {
+    #[prost(bool, tag = "1")]
+    #[prost_canonical_serde(proto_name = "first_key", json_name = "firstKey")]
+    first_key: bool,
+    #[prost(int32, tag = "2")]
+    #[prost_canonical_serde(proto_name = "size", json_name = "size")]
+    size: i32,
+}
+
+#[test]
+fn field_named_first_key_does_not_shadow_the_visitor_state() {
+    let message = FirstKeyField {
+        first_key: true,
+        size: 3,
+    };
+    let json = r#"{"firstKey":true,"size":3}"#;
+    assert_eq!(serde_json::to_string(&message).expect("serialize"), json);
+    assert_eq!(
+        serde_json::from_str::<FirstKeyField>(json).expect("deserialize"),
+        message
+    );
+}
@@ -1,5 +1,6 @@
 use chrono::{TimeZone, Timelike, Utc};
 use prost_canonical_serde_example::demo::Example;
+use prost_canonical_serde_example::{Choice, KitchenSink, Nested, ScalarFlavors, Status};
 use prost_types::Timestamp;
 use std::time::SystemTime;
 
@@ -42,3 +43,360 @@ fn example_canonical_json_roundtrip() {
     let roundtrip: Example = serde_json::from_value(json).expect("deserialize example");
     assert_eq!(roundtrip, message);
 }
+
+#[test]
+fn deserialize_error_names_the_field() {
+    let err = serde_json::from_str::<Example>(r#"{"name":"demo","count":"12x"}"#)
+        .expect_err("invalid int64 should fail");
+    let message = err.to_string();
+    assert_eq!(
+        message,
+        r#"error in field "count": invalid i64 string at line 1 column 28"#
+    );
+}
+
+#[test]
+fn scalar_flavors_use_their_json_shape() {
+    let message = ScalarFlavors {
+        fixed32_field: 4_000_000_000,
+        fixed64_field: 18_000_000_000_000_000_000,
+        sfixed32_field: -7,
+        sfixed64_field: -9_000_000_000,
+        sint32_field: -3,
+        sint64_field: -5,
+    };
+
+    let json = serde_json::to_value(message).expect("serialize scalar flavors");
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "fixed32Field": 4_000_000_000_u32,
+            "fixed64Field": "18000000000000000000",
+            "sfixed32Field": -7,
+            "sfixed64Field": "-9000000000",
+            "sint32Field": -3,
+            "sint64Field": "-5",
+        })
+    );
+
+    let roundtrip: ScalarFlavors = serde_json::from_value(json).expect("deserialize");
+    assert_eq!(roundtrip, message);
+}
+
+#[test]
+fn to_string_emits_fields_in_declaration_order() {
+    let message = KitchenSink {
+        int32_field: 1,
+        bool_field: true,
+        string_field: "s".to_string(),
+        status: Status::Active as i32,
+        optional_int32: Some(3),
+        ..KitchenSink::default()
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(
+        json,
+        r#"{"int32Field":1,"boolField":true,"stringField":"s","status":"STATUS_ACTIVE","optionalInt32":3}"#
+    );
+}
+
+#[test]
+fn empty_oneof_is_an_error_only_when_standalone() {
+    // A oneof inside a message is optional, so `{}` simply leaves it unset.
+    let message: KitchenSink = serde_json::from_str("{}").expect("deserialize message");
+    assert_eq!(message.choice, None);
+    let message: KitchenSink =
+        serde_json::from_str(r#"{"name":null}"#).expect("deserialize message");
+    assert_eq!(message.choice, None);
+
+    // A standalone oneof has no "unset" value to fall back to.
+    for json in ["{}", r#"{"unknown":1}"#, r#"{"name":null}"#] {
+        let err = serde_json::from_str::<Choice>(json).expect_err("standalone oneof");
+        assert!(err.to_string().starts_with("expected oneof field"), "{err}");
+    }
+    assert_eq!(
+        serde_json::from_str::<Choice>(r#"{"name":"n"}"#).expect("deserialize oneof"),
+        Choice::Name("n".to_string())
+    );
+}
+
+#[test]
+fn numeric_keys_never_match_oneof_variants() {
+    // `name` is field 14; its number is not an accepted key.
+    let message: KitchenSink =
+        serde_json::from_str(r#"{"14":"value","15":{"id":1}}"#).expect("deserialize message");
+    assert_eq!(message.choice, None);
+
+    for json in [r#"{"14":"value"}"#, r#"{"0":"value"}"#] {
+        let err = serde_json::from_str::<Choice>(json).expect_err("standalone oneof");
+        assert!(err.to_string().starts_with("expected oneof field"), "{err}");
+    }
+}
+
+#[test]
+fn kitchen_sink_field_name_table() {
+    let names = KitchenSink::canonical_field_names();
+    assert_eq!(
+        names,
+        [
+            ("int32_field", "int32Field"),
+            ("int64_field", "int64Field"),
+            ("uint64_field", "uint64Field"),
+            ("bool_field", "boolField"),
+            ("string_field", "stringField"),
+            ("bytes_field", "bytesField"),
+            ("float_field", "floatField"),
+            ("double_field", "doubleField"),
+            ("status", "status"),
+            ("nested", "nested"),
+            ("repeated_nested", "repeatedNested"),
+            ("string_to_int", "stringToInt"),
+            ("int_to_string", "intToString"),
+            ("timestamp", "timestamp"),
+            ("optional_int32", "optionalInt32"),
+            ("statuses", "statuses"),
+            // prost declares oneof fields after all other fields.
+            ("name", "name"),
+            ("nested_choice", "nestedChoice"),
+        ]
+    );
+}
+
+#[test]
+fn repeated_enum_accepts_mixed_names_and_numbers() {
+    let message: KitchenSink =
+        serde_json::from_str(r#"{"statuses":["STATUS_ACTIVE",2,"STATUS_UNSPECIFIED",7]}"#)
+            .expect("deserialize");
+    assert_eq!(
+        message.statuses,
+        [
+            Status::Active as i32,
+            Status::Suspended as i32,
+            Status::Unspecified as i32,
+            7,
+        ]
+    );
+
+    // Known numbers are written back as names; unknown ones stay numeric.
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(
+        json,
+        r#"{"statuses":["STATUS_ACTIVE","STATUS_SUSPENDED","STATUS_UNSPECIFIED",7]}"#
+    );
+}
+
+#[test]
+fn special_float_strings_are_rejected_for_integers() {
+    for token in ["NaN", "Infinity", "-Infinity"] {
+        for (field, expected) in [
+            ("int32Field", "invalid i32 string"),
+            ("int64Field", "invalid i64 string"),
+            ("uint64Field", "invalid u64 string"),
+        ] {
+            let json = format!(r#"{{"{field}":"{token}"}}"#);
+            let err = serde_json::from_str::<KitchenSink>(&json).expect_err(&json);
+            assert!(
+                err.to_string()
+                    .starts_with(&format!(r#"error in field "{field}": {expected}"#)),
+                "{err}"
+            );
+        }
+        for (field, expected) in [
+            ("fixed32Field", "invalid u32 string"),
+            ("sfixed32Field", "invalid i32 string"),
+        ] {
+            let json = format!(r#"{{"{field}":"{token}"}}"#);
+            let err = serde_json::from_str::<ScalarFlavors>(&json).expect_err(&json);
+            assert!(
+                err.to_string()
+                    .starts_with(&format!(r#"error in field "{field}": {expected}"#)),
+                "{err}"
+            );
+        }
+    }
+}
+
+#[test]
+fn shared_and_borrowed_messages_serialize_canonically() {
+    use prost_canonical_serde::{Canonical, CanonicalValue};
+    use std::borrow::Cow;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    let nested = Nested {
+        id: 5,
+        note: "shared".to_string(),
+    };
+    let expected = r#"{"id":5,"note":"shared"}"#;
+    let arc = Arc::new(nested.clone());
+    let rc = Rc::new(nested.clone());
+    let cow: Cow<'_, Nested> = Cow::Borrowed(&nested);
+    for json in [
+        serde_json::to_string(&Canonical::new(&arc)),
+        serde_json::to_string(&Canonical::new(&rc)),
+        serde_json::to_string(&Canonical::new(&cow)),
+    ] {
+        assert_eq!(json.expect("serialize"), expected);
+    }
+
+    let arc: CanonicalValue<Arc<Nested>> = serde_json::from_str(expected).expect("deserialize");
+    assert_eq!(*arc.0, nested);
+    let rc: CanonicalValue<Rc<Nested>> = serde_json::from_str(expected).expect("deserialize");
+    assert_eq!(*rc.0, nested);
+}
+#[test]
+fn nested_errors_report_the_full_field_path() {
+    for (json, expected) in [
+        (
+            r#"{"nested":{"id":"x"}}"#,
+            r#"error in field "nested.id": invalid i32 string"#,
+        ),
+        (
+            r#"{"repeatedNested":[{"id":1},{"id":"x"}]}"#,
+            r#"error in field "repeatedNested[1].id": invalid i32 string"#,
+        ),
+        (
+            r#"{"nestedChoice":{"id":"x"}}"#,
+            r#"error in field "nestedChoice.id": invalid i32 string"#,
+        ),
+        (
+            r#"{"statuses":["STATUS_ACTIVE","BOGUS"]}"#,
+            r#"error in field "statuses[1]": invalid enum string"#,
+        ),
+        (
+            r#"{"stringToInt":{"a":"x"}}"#,
+            r#"error in field "stringToInt["a"]": invalid i32 string"#,
+        ),
+        (
+            r#"{"stringToInt":{"a\": b.c":"x"}}"#,
+            r#"error in field "stringToInt["a\": b.c"]": invalid i32 string"#,
+        ),
+        (
+            r#"{"intToString":{"1":5}}"#,
+            r#"error in field "intToString["1"]": invalid type: integer `5`"#,
+        ),
+    ] {
+        let err = serde_json::from_str::<KitchenSink>(json).expect_err(json);
+        assert!(err.to_string().starts_with(expected), "{err}");
+    }
+}
+
+#[test]
+fn nested_syntax_errors_keep_their_category() {
+    let err = serde_json::from_str::<KitchenSink>(r#"{"nested": {"id": 1"#).expect_err("truncated");
+    assert!(err.is_eof(), "{err}");
+    assert_eq!(
+        err.to_string(),
+        "EOF while parsing an object at line 1 column 19"
+    );
+
+    let err = serde_json::from_str::<KitchenSink>(r#"{"nested": {"id": 1,}}"#)
+        .expect_err("trailing comma");
+    assert!(err.is_syntax(), "{err}");
+    assert_eq!(err.to_string(), "trailing comma at line 1 column 21");
+
+    let err = serde_json::from_str::<KitchenSink>(r#"{"repeatedNested": [{"id": 1}, {"id": "#)
+        .expect_err("truncated element");
+    assert!(err.is_eof(), "{err}");
+}
+
+#[test]
+fn booleans_are_rejected_for_numeric_fields() {
+    for field in [
+        "int32Field",
+        "int64Field",
+        "uint64Field",
+        "floatField",
+        "doubleField",
+    ] {
+        let json = format!(r#"{{"{field}":true}}"#);
+        let err = serde_json::from_str::<KitchenSink>(&json).expect_err(&json);
+        assert!(
+            err.to_string().starts_with(&format!(
+                r#"error in field "{field}": expected number or numeric string, found boolean"#
+            )),
+            "{err}"
+        );
+    }
+    let err = serde_json::from_str::<ScalarFlavors>(r#"{"fixed32Field":false}"#)
+        .expect_err("boolean fixed32");
+    assert!(
+        err.to_string()
+            .contains("expected number or numeric string, found boolean"),
+        "{err}"
+    );
+}
+
+#[test]
+fn proto3_optional_keeps_presence_while_implicit_fields_omit_zero() {
+    let message = KitchenSink {
+        int32_field: 0,
+        optional_int32: Some(0),
+        ..KitchenSink::default()
+    };
+    let json = serde_json::to_string(&message).expect("serialize");
+    assert_eq!(json, r#"{"optionalInt32":0}"#);
+    let roundtrip: KitchenSink = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(roundtrip.optional_int32, Some(0));
+
+    // Unset explicit presence is omitted, and an explicit zero for an
+    // implicit-presence field reads back as the default.
+    let json = serde_json::to_string(&KitchenSink::default()).expect("serialize");
+    assert_eq!(json, "{}");
+    let message: KitchenSink =
+        serde_json::from_str(r#"{"int32Field":0,"optionalInt32":null}"#).expect("deserialize");
+    assert_eq!(message.int32_field, 0);
+    assert_eq!(message.optional_int32, None);
+}
+
+#[test]
+fn oneof_members_with_the_wrong_json_type_name_the_member() {
+    for (json, expected) in [
+        (
+            r#"{"nestedChoice":"x"}"#,
+            r#"error in field "nestedChoice": invalid type: string "x", expected map"#,
+        ),
+        (
+            r#"{"nestedChoice":[1]}"#,
+            r#"error in field "nestedChoice": invalid type: sequence, expected map"#,
+        ),
+        (
+            r#"{"name":5}"#,
+            r#"error in field "name": invalid type: integer `5`, expected a string"#,
+        ),
+    ] {
+        let err = serde_json::from_str::<KitchenSink>(json).expect_err(json);
+        assert!(err.to_string().starts_with(expected), "{err}");
+        let err = serde_json::from_str::<Choice>(json).expect_err(json);
+        assert!(err.to_string().starts_with(expected), "{err}");
+    }
+}
+
+#[test]
+fn lone_surrogate_escapes_are_rejected() {
+    for json in [
+        r#"{"stringField":"\ud800"}"#,
+        r#"{"stringField":"\udc00"}"#,
+        r#"{"stringField":"\ud800x"}"#,
+        r#"{"stringField":"\ud800A"}"#,
+        r#"{"stringField":"\udc00\ud800"}"#,
+    ] {
+        for result in [
+            serde_json::from_str::<KitchenSink>(json),
+            serde_json::from_slice::<KitchenSink>(json.as_bytes()),
+        ] {
+            // The parser rejects these itself, so the error stays a syntax
+            // error rather than naming the field.
+            let err = result.expect_err(json);
+            assert!(err.is_syntax(), "{err}");
+        }
+    }
+    let err = serde_json::from_str::<KitchenSink>(r#"{"stringToInt":{"\udfff":1}}"#)
+        .expect_err("lone surrogate key");
+    assert!(err.is_syntax(), "{err}");
+
+    // A proper surrogate pair decodes to the supplementary character.
+    let message: KitchenSink =
+        serde_json::from_str(r#"{"stringField":"\ud83d\ude00"}"#).expect("deserialize pair");
+    assert_eq!(message.string_field, "\u{1F600}");
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "indexmap")]
+
+extern crate alloc;
+
+use indexmap::IndexMap;
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalOptions, CanonicalSerialize};
+use prost_canonical_serde_example::Status;
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Ordered {
+    #[prost(map = "string, int32")]
+    #[prost_canonical_serde(proto_name = "counts", json_name = "counts")]
+    counts: IndexMap<String, i32>,
+    #[prost(map = "int64, enumeration(Status)")]
+    #[prost_canonical_serde(proto_name = "statuses", json_name = "statuses")]
+    statuses: IndexMap<i64, i32>,
+}
+
+#[test]
+fn index_map_fields_keep_insertion_order() {
+    let message = Ordered {
+        counts: IndexMap::from([("zulu".to_string(), 1), ("alpha".to_string(), 2)]),
+        statuses: IndexMap::from([(9, Status::Active as i32), (-1, 42)]),
+    };
+    let expected = r#"{"counts":{"zulu":1,"alpha":2},"statuses":{"9":"STATUS_ACTIVE","-1":42}}"#;
+    assert_eq!(
+        serde_json::to_string(&message).expect("serialize"),
+        expected
+    );
+
+    // `sort_map_keys` only reorders maps without a stable iteration order.
+    let options = CanonicalOptions::new().sort_map_keys(true);
+    let sorted = prost_canonical_serde::with_options(options, || serde_json::to_string(&message))
+        .expect("serialize");
+    assert_eq!(sorted, expected);
+
+    let decoded: Ordered = serde_json::from_str(expected).expect("deserialize");
+    assert_eq!(decoded, message);
+    assert_eq!(decoded.counts.keys().collect::<Vec<_>>(), ["zulu", "alpha"]);
+}
@@ -0,0 +1,587 @@
+use prost_canonical_serde::{
+    Canonical, CanonicalArrayStream, from_canonical_slice, from_canonical_str,
+    from_canonical_value, merge_from_canonical_value, to_canonical_map, to_canonical_value,
+    to_canonical_value_masked, to_canonical_writer, to_canonical_writer_pretty,
+    to_string_with_newline,
+};
+use prost_canonical_serde_example::Nested;
+use serde_json::error::Category;
+
+#[test]
+fn array_stream_yields_each_element() {
+    let input = br#"[
+        {"id": 1, "note": "first, with [brackets]"},
+        {"id": "2"},
+        {"id": 3, "note": "escaped \"quote\""}
+    ]"#;
+    let stream = CanonicalArrayStream::<_, Nested>::new(&input[..]);
+    let values = stream.collect::<Result<Vec<_>, _>>().expect("stream array");
+    assert_eq!(
+        values,
+        vec![
+            Nested {
+                id: 1,
+                note: "first, with [brackets]".to_string(),
+            },
+            Nested {
+                id: 2,
+                note: String::new(),
+            },
+            Nested {
+                id: 3,
+                note: "escaped \"quote\"".to_string(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn array_stream_handles_edge_cases() {
+    let stream = |input: &'static str| CanonicalArrayStream::<_, Nested>::new(input.as_bytes());
+    let errors = |input: &'static str| {
+        stream(input)
+            .filter_map(Result::err)
+            .map(|err| (err.to_string(), err.classify()))
+            .collect::<Vec<_>>()
+    };
+    let error = |message: &str, category| vec![(message.to_string(), category)];
+
+    assert_eq!(stream(" [ ] ").count(), 0);
+    assert_eq!(
+        errors(""),
+        error(
+            "EOF while parsing a value at line 1 column 0",
+            Category::Eof
+        )
+    );
+    assert_eq!(
+        errors(r#"{"id": 1}"#),
+        error("expected JSON array at line 1 column 1", Category::Data)
+    );
+    assert_eq!(
+        errors(r#"[{"id": 1},]"#),
+        error("expected array element at line 1 column 12", Category::Data)
+    );
+    assert_eq!(
+        errors("[,1]"),
+        error("expected value at line 1 column 2", Category::Syntax)
+    );
+    assert_eq!(
+        errors(r#"[{"id": 1]}]"#),
+        error("expected `,` or `}` at line 1 column 10", Category::Syntax)
+    );
+    // A truncated stream is reported as `serde_json` reports any truncated
+    // input, so callers can tell it from malformed input.
+    assert_eq!(
+        errors(r#"[{"id": 1}"#),
+        error(
+            "EOF while parsing a list at line 1 column 10",
+            Category::Eof
+        )
+    );
+    assert_eq!(
+        errors("[\n{\"id\":"),
+        error(
+            "EOF while parsing a value at line 2 column 6",
+            Category::Eof
+        )
+    );
+
+    // A failing element ends the stream, with its position counted from the
+    // start of the input rather than of the element.
+    let mut values = stream("[\n  {\"id\": 1},\n  {\"id\": \"x\"},\n  {\"id\": 3}\n]");
+    assert_eq!(values.next().expect("first").expect("decodes").id, 1);
+    let err = values.next().expect("second").expect_err("bad id");
+    assert_eq!(
+        err.to_string(),
+        "error in field \"id\": invalid i32 string at line 3 column 12"
+    );
+    assert!(values.next().is_none());
+    assert_eq!(
+        errors(r#"[{"id": 1}, {"id": "x"}]"#),
+        error(
+            "error in field \"id\": invalid i32 string at line 1 column 22",
+            Category::Data
+        )
+    );
+
+    // Deep into a minified array, the element's position is offset by where
+    // it starts rather than found by reading the stream again.
+    let input = format!(r#"[{}{{"id":"x"}}]"#, r#"{"id":1},"#.repeat(1000));
+    let err = CanonicalArrayStream::<_, Nested>::new(input.as_bytes())
+        .find_map(Result::err)
+        .expect("bad element");
+    assert_eq!(
+        (err.line(), err.column(), err.classify()),
+        (1, input.len() - 2, Category::Data)
+    );
+}
+
+#[test]
+fn writer_output_matches_to_string() {
+    let value = Nested {
+        id: 7,
+        note: "seven".to_string(),
+    };
+
+    let mut compact = Vec::new();
+    to_canonical_writer(&mut compact, &value).expect("write compact");
+    assert_eq!(
+        compact,
+        serde_json::to_vec(&Canonical::new(&value)).expect("serialize")
+    );
+
+    let mut pretty = Vec::new();
+    to_canonical_writer_pretty(&mut pretty, &value).expect("write pretty");
+    assert_eq!(
+        String::from_utf8(pretty).expect("utf-8"),
+        "{\n  \"id\": 7,\n  \"note\": \"seven\"\n}"
+    );
+}
+
+#[test]
+fn canonical_formatter_matches_protobuf_float_output() {
+    use prost_canonical_serde::to_string_with_canonical_formatter;
+    use prost_canonical_serde_example::KitchenSink;
+
+    let cases: [(f64, f32, &str); 6] = [
+        (1.0, 1.0, r#"{"floatField":1,"doubleField":1}"#),
+        (0.1, 0.1, r#"{"floatField":0.1,"doubleField":0.1}"#),
+        (
+            1e300,
+            f32::MAX,
+            r#"{"floatField":3.40282347e+38,"doubleField":1e+300}"#,
+        ),
+        (1e-7, 1e-7, r#"{"floatField":1e-07,"doubleField":1e-07}"#),
+        (
+            0.1 + 0.2,
+            1.0 / 3.0,
+            r#"{"floatField":0.333333343,"doubleField":0.30000000000000004}"#,
+        ),
+        (
+            -123_456_789_012_345_680.0,
+            -0.000_1,
+            r#"{"floatField":-0.0001,"doubleField":-1.2345678901234568e+17}"#,
+        ),
+    ];
+    for (double_field, float_field, expected) in cases {
+        let message = KitchenSink {
+            float_field,
+            double_field,
+            ..KitchenSink::default()
+        };
+        assert_eq!(
+            to_string_with_canonical_formatter(&message).expect("serialize"),
+            expected
+        );
+    }
+}
+
+#[test]
+fn default_float_strings_match_protobuf_exponent_rules() {
+    use prost_canonical_serde_example::KitchenSink;
+
+    // Expected strings are what Go's protojson emits for the same values.
+    let cases: [(f64, f32, &str, &str); 10] = [
+        (1.5, 1.5, "1.5", "1.5"),
+        (1e20, 1e20, "100000000000000000000", "100000000000000000000"),
+        (1e21, 1e21, "1e+21", "1e+21"),
+        (-1.5e300, -1.5e30, "-1.5e+300", "-1.5e+30"),
+        (0.000_001, 0.000_001, "0.000001", "0.000001"),
+        (1e-7, 1e-7, "1e-7", "1e-7"),
+        (-2.5e-10, -2.5e-10, "-2.5e-10", "-2.5e-10"),
+        (0.1 + 0.2, 1.0 / 3.0, "0.30000000000000004", "0.33333334"),
+        (
+            f64::MAX,
+            f32::MAX,
+            "1.7976931348623157e+308",
+            "3.4028235e+38",
+        ),
+        (-f64::from_bits(1), f32::from_bits(1), "-5e-324", "1e-45"),
+    ];
+    for (double_field, float_field, double_expected, float_expected) in cases {
+        let message = KitchenSink {
+            float_field,
+            double_field,
+            ..KitchenSink::default()
+        };
+        let json = serde_json::to_value(&message).expect("serialize");
+        assert_eq!(json["doubleField"], double_expected);
+        assert_eq!(json["floatField"], float_expected);
+
+        let roundtrip: KitchenSink = serde_json::from_value(json).expect("deserialize");
+        assert_eq!(roundtrip.double_field.to_bits(), double_field.to_bits());
+        assert_eq!(roundtrip.float_field.to_bits(), float_field.to_bits());
+    }
+
+    let err = serde_json::from_str::<KitchenSink>(r#"{"floatField":"3.5e+38"}"#)
+        .expect_err("f32 overflow");
+    assert!(err.to_string().contains("float out of range"), "{err}");
+}
+
+#[test]
+fn float_strings_and_canonical_formatter_follow_different_references() {
+    use prost_canonical_serde::to_string_with_canonical_formatter;
+    use prost_canonical_serde_example::KitchenSink;
+
+    // Float strings follow Go's protojson and `CanonicalFormatter` follows
+    // the C++ implementation. The two disagree on where exponential notation
+    // starts, on how the exponent is written, and on how many digits the
+    // smallest values get.
+    let cases: [(f64, &str, &str); 4] = [
+        (0.1, "0.1", "0.1"),
+        (1e-7, "1e-7", "1e-07"),
+        (
+            1.234_567_890_123_456_8e20,
+            "123456789012345680000",
+            "1.2345678901234568e+20",
+        ),
+        (f64::from_bits(1), "5e-324", "4.94065645841247e-324"),
+    ];
+    for (double_field, go, cpp) in cases {
+        let message = KitchenSink {
+            double_field,
+            ..KitchenSink::default()
+        };
+        let json = serde_json::to_value(&message).expect("serialize");
+        assert_eq!(json["doubleField"], go);
+        assert_eq!(
+            to_string_with_canonical_formatter(&message).expect("serialize"),
+            format!(r#"{{"doubleField":{cpp}}}"#)
+        );
+    }
+}
+
+#[test]
+fn canonical_value_roundtrip() {
+    let message = Nested {
+        id: 42,
+        note: "value".to_string(),
+    };
+    let value = to_canonical_value(&message).expect("to value");
+    assert_eq!(value, serde_json::json!({"id": 42, "note": "value"}));
+    let decoded: Nested = from_canonical_value(value).expect("from value");
+    assert_eq!(decoded, message);
+
+    let err = from_canonical_value::<Nested>(serde_json::json!({"id": "x"})).expect_err("bad id");
+    assert!(err.to_string().contains("id"), "{err}");
+}
+
+#[test]
+fn to_string_with_newline_appends_exactly_one_newline() {
+    let message = Nested {
+        id: 1,
+        note: "line".to_string(),
+    };
+    let plain = serde_json::to_string(&message).expect("serialize");
+    assert!(!plain.ends_with('\n'));
+    let line = to_string_with_newline(&message).expect("serialize");
+    assert_eq!(line, format!("{plain}\n"));
+
+    let jsonl: String = [&message, &Nested::default()]
+        .into_iter()
+        .map(|message| to_string_with_newline(message).expect("serialize"))
+        .collect();
+    assert_eq!(jsonl.lines().count(), 2);
+    assert_eq!(jsonl, format!("{plain}\n{{}}\n"));
+}
+
+#[test]
+fn top_level_array_of_messages() {
+    use prost_canonical_serde::CanonicalValue;
+
+    let values: CanonicalValue<Vec<Nested>> =
+        serde_json::from_str(r#"[{"id":1},{"id":"2","note":"two"}]"#).expect("deserialize");
+    assert_eq!(
+        values.0,
+        vec![
+            Nested {
+                id: 1,
+                note: String::new(),
+            },
+            Nested {
+                id: 2,
+                note: "two".to_string(),
+            },
+        ]
+    );
+
+    // `Vec<u8>` keeps its base64 `bytes` mapping.
+    let bytes: CanonicalValue<Vec<u8>> = serde_json::from_str(r#""AAE=""#).expect("bytes");
+    assert_eq!(bytes.0, vec![0, 1]);
+}
+
+#[test]
+fn from_canonical_str_reports_empty_input() {
+    for input in ["", "   \n"] {
+        let err = from_canonical_str::<Nested>(input).expect_err("empty input");
+        assert_eq!(err.to_string(), "empty input");
+        let err = from_canonical_slice::<Nested>(input.as_bytes()).expect_err("empty input");
+        assert_eq!(err.to_string(), "empty input");
+    }
+
+    let decoded: Nested = from_canonical_str(" {\"id\":3}\n").expect("deserialize");
+    assert_eq!(decoded.id, 3);
+    assert!(from_canonical_str::<Nested>("{} {}").is_err());
+}
+
+#[test]
+fn map_fields_reserve_from_the_value_size_hint() {
+    use prost_canonical_serde_example::KitchenSink;
+
+    let entries = (0..100)
+        .map(|index| (format!("key{index}"), serde_json::Value::from(index)))
+        .collect::<serde_json::Map<_, _>>();
+    let value = serde_json::json!({ "stringToInt": entries });
+    let message: KitchenSink = from_canonical_value(value).expect("deserialize");
+    assert_eq!(message.string_to_int.len(), 100);
+    assert!(message.string_to_int.capacity() >= 100);
+}
+
+#[test]
+fn canonical_with_mixes_canonical_and_plain_serde_fields() {
+    use prost_types::Duration;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Envelope {
+        request_id: u64,
+        #[serde(with = "prost_canonical_serde::canonical_with")]
+        nested: Nested,
+        #[serde(with = "prost_canonical_serde::canonical_with")]
+        timeout: Duration,
+    }
+
+    let envelope = Envelope {
+        request_id: 7,
+        nested: Nested {
+            id: 1,
+            note: "n".to_string(),
+        },
+        timeout: Duration {
+            seconds: 1,
+            nanos: 500_000_000,
+        },
+    };
+    let json = serde_json::to_string(&envelope).expect("serialize");
+    assert_eq!(
+        json,
+        r#"{"request_id":7,"nested":{"id":1,"note":"n"},"timeout":"1.5s"}"#
+    );
+    let roundtrip: Envelope = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(roundtrip, envelope);
+}
+
+#[test]
+fn canonical_map_holds_only_set_fields() {
+    let map = to_canonical_map(&Nested {
+        id: 7,
+        note: String::new(),
+    })
+    .expect("map");
+    assert_eq!(map.len(), 1);
+    assert_eq!(map["id"], 7);
+
+    let err = to_canonical_map(&5_i32).expect_err("scalar is not an object");
+    assert!(err.to_string().contains("expected a JSON object"));
+}
+
+#[test]
+fn merge_recurses_into_messages_and_replaces_lists_and_maps() {
+    use prost_canonical_serde_example::{Choice, KitchenSink};
+
+    let mut message = KitchenSink {
+        int32_field: 1,
+        string_field: "keep".to_string(),
+        nested: Some(Nested {
+            id: 2,
+            note: "old".to_string(),
+        }),
+        repeated_nested: vec![Nested {
+            id: 3,
+            note: String::new(),
+        }],
+        string_to_int: [("a".to_string(), 1), ("b".to_string(), 2)].into(),
+        choice: Some(Choice::Name("first".to_string())),
+        ..KitchenSink::default()
+    };
+    merge_from_canonical_value(
+        &mut message,
+        serde_json::json!({
+            "int32_field": 10,
+            "nested": {"note": "new"},
+            "repeatedNested": [{"id": 4}],
+            "stringToInt": {"c": 3},
+            "nestedChoice": {"id": 5},
+            "stringField": null,
+        }),
+    )
+    .expect("merge");
+
+    assert_eq!(message.int32_field, 10);
+    assert_eq!(message.string_field, "");
+    assert_eq!(
+        message.nested,
+        Some(Nested {
+            id: 2,
+            note: "new".to_string(),
+        })
+    );
+    assert_eq!(
+        message.repeated_nested,
+        vec![Nested {
+            id: 4,
+            note: String::new(),
+        }]
+    );
+    assert_eq!(message.string_to_int, [("c".to_string(), 3)].into());
+    assert_eq!(
+        message.choice,
+        Some(Choice::NestedChoice(Nested {
+            id: 5,
+            note: String::new(),
+        }))
+    );
+
+    // A message member already set is merged like any other message field.
+    merge_from_canonical_value(
+        &mut message,
+        serde_json::json!({"nestedChoice": {"note": "set"}}),
+    )
+    .expect("merge");
+    assert_eq!(
+        message.choice,
+        Some(Choice::NestedChoice(Nested {
+            id: 5,
+            note: "set".to_string(),
+        }))
+    );
+}
+
+#[test]
+fn failed_merge_leaves_target_unchanged() {
+    let mut message = Nested {
+        id: 1,
+        note: "keep".to_string(),
+    };
+    let err = merge_from_canonical_value(&mut message, serde_json::json!({"id": "x"}))
+        .expect_err("bad id");
+    assert!(err.to_string().contains("id"));
+    let err = merge_from_canonical_value(&mut message, serde_json::json!([1])).expect_err("array");
+    assert!(err.to_string().contains("must be a JSON object"));
+    assert_eq!(message.note, "keep");
+    assert_eq!(message.id, 1);
+}
+
+#[test]
+fn field_masks_select_paths() {
+    use prost_canonical_serde_example::KitchenSink;
+    use prost_types::FieldMask;
+
+    let message = KitchenSink {
+        int32_field: 1,
+        string_field: "hidden".to_string(),
+        nested: Some(Nested {
+            id: 2,
+            note: "note".to_string(),
+        }),
+        string_to_int: [("a".to_string(), 1)].into(),
+        ..KitchenSink::default()
+    };
+    let mask = |paths: &[&str]| FieldMask {
+        paths: paths.iter().map(ToString::to_string).collect(),
+    };
+
+    let value = to_canonical_value_masked(
+        &message,
+        &mask(&["int32_field", "nested.note", "string_to_int", "bool_field"]),
+    )
+    .expect("masked");
+    assert_eq!(
+        value,
+        serde_json::json!({
+            "int32Field": 1,
+            "nested": {"note": "note"},
+            "stringToInt": {"a": 1},
+        })
+    );
+
+    assert_eq!(
+        to_canonical_value_masked(&message, &mask(&[])).expect("empty mask"),
+        to_canonical_value(&message).expect("full")
+    );
+
+    let err = to_canonical_value_masked(&message, &mask(&["missing"])).expect_err("unknown");
+    assert!(
+        err.to_string()
+            .contains("unknown field mask path `missing`")
+    );
+    let err = to_canonical_value_masked(&message, &mask(&["string_to_int.a"]))
+        .expect_err("not a message");
+    assert!(err.to_string().contains("not a message"));
+
+    // Paths are checked against the schema, not the data, so unset fields
+    // are rejected the same way.
+    let unset = KitchenSink::default();
+    let err =
+        to_canonical_value_masked(&unset, &mask(&["nested.bogus"])).expect_err("unset nested");
+    assert!(err.to_string().contains("unknown field mask path `bogus`"));
+    let err =
+        to_canonical_value_masked(&unset, &mask(&["stringField.x"])).expect_err("unset scalar");
+    assert!(err.to_string().contains("not a message"));
+    let err = to_canonical_value_masked(&message, &mask(&["nested", "nested.bogus"]))
+        .expect_err("whole field selected");
+    assert!(err.to_string().contains("unknown field mask path `bogus`"));
+}
+
+#[test]
+fn value_accepts_strings_given_as_borrowed_text_or_bytes() {
+    use prost_canonical_serde::CanonicalDeserialize;
+    use serde::de::value::{BorrowedStrDeserializer, BytesDeserializer, Error};
+
+    let string = |text: &str| prost_types::Value {
+        kind: Some(prost_types::value::Kind::StringValue(text.to_string())),
+    };
+    let value = prost_types::Value::deserialize_canonical(BorrowedStrDeserializer::<Error>::new(
+        "borrowed",
+    ))
+    .expect("borrowed str");
+    assert_eq!(value, string("borrowed"));
+    let value =
+        prost_types::Value::deserialize_canonical(BytesDeserializer::<Error>::new(b"bytes"))
+            .expect("bytes");
+    assert_eq!(value, string("bytes"));
+
+    let err = prost_types::Value::deserialize_canonical(BytesDeserializer::<Error>::new(&[0xff]))
+        .expect_err("invalid UTF-8");
+    assert!(
+        err.to_string().starts_with("invalid value: byte array"),
+        "{err}"
+    );
+}
+
+#[test]
+fn struct_keys_are_sorted_and_unique() {
+    let number = |value| prost_types::Value {
+        kind: Some(prost_types::value::Kind::NumberValue(value)),
+    };
+    let mut value = prost_types::Struct::default();
+    for (key, n) in [("zeta", 1.0), ("alpha", 2.0), ("mid", 3.0)] {
+        value.fields.insert(key.to_string(), number(n));
+    }
+    assert_eq!(
+        serde_json::to_string(&Canonical::new(&value)).expect("serialize"),
+        r#"{"alpha":2.0,"mid":3.0,"zeta":1.0}"#
+    );
+
+    for json in [
+        r#"{"a":1,"b":2,"a":3}"#,
+        r#"{"outer":{"a":1,"a":2}}"#,
+        r#"{"list":[{"a":1,"a":2}]}"#,
+    ] {
+        let err = from_canonical_str::<prost_types::Struct>(json).expect_err(json);
+        assert!(err.to_string().contains("duplicate key in object"), "{err}");
+        let err = from_canonical_str::<prost_types::Value>(json).expect_err(json);
+        assert!(err.to_string().contains("duplicate key in object"), "{err}");
+    }
+}
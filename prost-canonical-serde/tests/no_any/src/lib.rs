@@ -0,0 +1,18 @@
+//! Fixture for `tests/features.rs`: must fail to compile because the `any`
+//! feature is disabled.
+
+extern crate alloc;
+
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct WithAny {
+    #[prost_canonical_serde(proto_name = "details", json_name = "details")]
+    pub details: Vec<::prost_types::Any>,
+}
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct WithoutAny {
+    #[prost_canonical_serde(proto_name = "name", json_name = "name")]
+    pub name: String,
+}
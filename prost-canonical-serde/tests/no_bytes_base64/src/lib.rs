@@ -0,0 +1,18 @@
+//! Fixture for `tests/features.rs`: must fail to compile because the
+//! `bytes-base64` feature is disabled.
+
+extern crate alloc;
+
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct WithBytes {
+    #[prost_canonical_serde(proto_name = "payload", json_name = "payload")]
+    pub payload: Vec<u8>,
+}
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct WithoutBytes {
+    #[prost_canonical_serde(proto_name = "name", json_name = "name")]
+    pub name: String,
+}
@@ -0,0 +1,642 @@
+use prost_canonical_serde::{
+    Canonical, CanonicalMapFieldRef, CanonicalMapRef, CanonicalOptions, CanonicalSeed,
+    CanonicalValue, DurationUnit, EpochTimestamp, NonFiniteValue, TimestampUnit, with_options,
+};
+use prost_canonical_serde_example::{Choice, KitchenSink};
+use prost_types::{Duration, Timestamp};
+use std::collections::BTreeMap;
+
+fn to_json_with<T: prost_canonical_serde::CanonicalSerialize>(
+    options: CanonicalOptions,
+    value: &T,
+) -> String {
+    with_options(options, || serde_json::to_string(&Canonical::new(value))).expect("serialize")
+}
+
+#[test]
+fn duration_as_milliseconds_roundtrip() {
+    let options = CanonicalOptions::new().duration_as(DurationUnit::Milliseconds);
+    let duration = Duration {
+        seconds: 1,
+        nanos: 500_000_000,
+    };
+    assert_eq!(to_json_with(options, &duration), "1500");
+
+    let decoded = with_options(options, || {
+        serde_json::from_str::<CanonicalValue<Duration>>("1500")
+    })
+    .expect("deserialize")
+    .0;
+    assert_eq!(decoded, duration);
+
+    // The canonical string form is still accepted, and defaults stay canonical.
+    let decoded = with_options(options, || {
+        serde_json::from_str::<CanonicalValue<Duration>>(r#""1.5s""#)
+    })
+    .expect("deserialize")
+    .0;
+    assert_eq!(decoded, duration);
+    assert_eq!(
+        serde_json::to_string(&Canonical::new(&duration)).expect("serialize"),
+        r#""1.5s""#
+    );
+}
+
+#[test]
+fn duration_as_unit_roundtrips_sub_unit_values() {
+    let options = CanonicalOptions::new().duration_as(DurationUnit::Milliseconds);
+    for (seconds, nanos) in [(0, 498), (0, 1009), (0, -498), (-1, -999_999_999), (3, 1)] {
+        let duration = Duration { seconds, nanos };
+        let json = to_json_with(options, &duration);
+        let decoded = with_options(options, || {
+            serde_json::from_str::<CanonicalValue<Duration>>(&json)
+        })
+        .expect("deserialize")
+        .0;
+        assert_eq!(decoded, duration, "{json}");
+    }
+}
+
+#[test]
+fn wrappers_carry_their_own_options() {
+    use serde::de::DeserializeSeed;
+
+    let options = CanonicalOptions::new().duration_as(DurationUnit::Milliseconds);
+    let duration = Duration {
+        seconds: 1,
+        nanos: 500_000_000,
+    };
+    // Built inside `with_options` but serialized after it returns, as a
+    // response body would be.
+    let carried = with_options(CanonicalOptions::new(), || {
+        Canonical::with_options(&duration, options)
+    });
+    let plain = with_options(options, || Canonical::new(&duration));
+    assert_eq!(serde_json::to_string(&carried).expect("serialize"), "1500");
+    assert_eq!(
+        serde_json::to_string(&plain).expect("serialize"),
+        r#""1.5s""#
+    );
+
+    let mut deserializer = serde_json::Deserializer::from_str("1500");
+    let decoded = CanonicalSeed::<Duration>::new(options)
+        .deserialize(&mut deserializer)
+        .expect("deserialize");
+    assert_eq!(decoded, duration);
+    assert!(serde_json::from_str::<CanonicalValue<Duration>>("1500").is_err());
+}
+
+#[test]
+fn case_insensitive_keys_are_opt_in() {
+    let json = r#"{"INT32FIELD":1,"String_Field":"a","NAME":"n"}"#;
+    assert_eq!(
+        serde_json::from_str::<KitchenSink>(json).expect("deserialize"),
+        KitchenSink::default()
+    );
+
+    let options = CanonicalOptions::new().case_insensitive_keys(true);
+    let decoded =
+        with_options(options, || serde_json::from_str::<KitchenSink>(json)).expect("deserialize");
+    assert_eq!(decoded.int32_field, 1);
+    assert_eq!(decoded.string_field, "a");
+    assert_eq!(decoded.choice, Some(Choice::Name("n".to_string())));
+}
+
+#[test]
+fn sort_map_keys_orders_hash_map_entries() {
+    let message = KitchenSink {
+        string_to_int: ["delta", "alpha", "charlie", "bravo", "echo"]
+            .into_iter()
+            .zip(1..)
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+        ..KitchenSink::default()
+    };
+    let options = CanonicalOptions::new().sort_map_keys(true);
+    assert_eq!(
+        to_json_with(options, &message),
+        r#"{"stringToInt":{"alpha":2,"bravo":4,"charlie":3,"delta":1,"echo":5}}"#
+    );
+}
+
+#[test]
+fn sort_map_keys_orders_integer_keys_by_their_text() {
+    let entries = [10, -1, 2].map(|key| (key, key.to_string()));
+    let message = KitchenSink {
+        int_to_string: entries.iter().cloned().collect(),
+        ..KitchenSink::default()
+    };
+    let expected = r#"{"intToString":{"-1":"-1","10":"10","2":"2"}}"#;
+    let options = CanonicalOptions::new().sort_map_keys(true);
+    assert_eq!(to_json_with(options, &message), expected);
+
+    // A `BTreeMap` keeps its own numeric order.
+    let ordered = entries.into_iter().collect::<BTreeMap<_, _>>();
+    let json = with_options(options, || {
+        serde_json::to_string(&CanonicalMapFieldRef::new(&ordered))
+    })
+    .expect("serialize");
+    assert_eq!(json, r#"{"-1":"-1","2":"2","10":"10"}"#);
+}
+
+/// A map-like type that is not a `CanonicalMapType`.
+struct Pairs(Vec<(String, i32)>);
+
+impl<'a> IntoIterator for &'a Pairs {
+    type Item = (&'a String, &'a i32);
+    type IntoIter = std::iter::Map<
+        std::slice::Iter<'a, (String, i32)>,
+        fn(&'a (String, i32)) -> (&'a String, &'a i32),
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter().map(|(key, value)| (key, value))
+    }
+}
+
+#[test]
+fn map_ref_accepts_custom_map_types_in_their_own_order() {
+    let pairs = Pairs(vec![("b".to_string(), 2), ("a".to_string(), 1)]);
+    let options = CanonicalOptions::new().sort_map_keys(true);
+    let json = with_options(options, || {
+        serde_json::to_string(&CanonicalMapRef::new(&pairs))
+    })
+    .expect("serialize");
+    assert_eq!(json, r#"{"b":2,"a":1}"#);
+}
+
+#[test]
+fn message_name_wrapper_is_opt_in() {
+    let json = r#"{"KitchenSink":{"int32Field":1}}"#;
+    let plain: KitchenSink = serde_json::from_str(json).expect("unknown key is ignored");
+    assert_eq!(plain, KitchenSink::default());
+
+    let options = CanonicalOptions::new().unwrap_message_name(true);
+    let decoded: KitchenSink =
+        with_options(options, || serde_json::from_str(json)).expect("deserialize");
+    assert_eq!(decoded.int32_field, 1);
+
+    let bare: KitchenSink =
+        with_options(options, || serde_json::from_str(r#"{"int32Field":2}"#)).expect("bare");
+    assert_eq!(bare.int32_field, 2);
+
+    let nested: KitchenSink = with_options(options, || {
+        serde_json::from_str(r#"{"nested":{"Nested":{"id":3}}}"#)
+    })
+    .expect("nested wrapper");
+    assert_eq!(nested.nested.map(|nested| nested.id), Some(3));
+
+    let err = with_options(options, || {
+        serde_json::from_str::<KitchenSink>(r#"{"KitchenSink":{},"int32Field":1}"#)
+    })
+    .expect_err("extra key");
+    assert!(err.to_string().contains("only key"), "{err}");
+}
+
+#[test]
+fn timestamp_and_duration_windows_reject_spec_valid_values() {
+    use prost_types::Timestamp;
+
+    let options = CanonicalOptions::new()
+        .min_timestamp(Timestamp {
+            seconds: 946_684_800, // 2000-01-01T00:00:00Z
+            nanos: 0,
+        })
+        .max_timestamp(Timestamp {
+            seconds: 4_102_444_800, // 2100-01-01T00:00:00Z
+            nanos: 0,
+        })
+        .max_duration(Duration {
+            seconds: 86_400,
+            nanos: 0,
+        });
+    let parse = |json: &str| {
+        with_options(options, || {
+            serde_json::from_str::<CanonicalValue<Timestamp>>(json).map(|value| value.0)
+        })
+    };
+
+    let err = parse(r#""1999-12-31T23:59:59Z""#).expect_err("before window");
+    assert!(
+        err.to_string().contains("before the allowed window"),
+        "{err}"
+    );
+    let err = parse(r#""2100-01-01T00:00:00.000000001Z""#).expect_err("after window");
+    assert!(
+        err.to_string().contains("after the allowed window"),
+        "{err}"
+    );
+    parse(r#""2000-01-01T00:00:00Z""#).expect("window start is inclusive");
+    // Without the options the same value is valid canonical JSON.
+    serde_json::from_str::<CanonicalValue<Timestamp>>(r#""1999-12-31T23:59:59Z""#)
+        .expect("spec-valid timestamp");
+
+    // Epoch numbers go through the same window, whether integral or not.
+    for unit in [
+        TimestampUnit::EpochSeconds,
+        TimestampUnit::EpochMilliseconds,
+    ] {
+        let scale = if unit == TimestampUnit::EpochMilliseconds {
+            "000"
+        } else {
+            ""
+        };
+        let epoch_options = options.timestamp_as(unit);
+        let parse_epoch = |json: String| {
+            with_options(epoch_options, || {
+                serde_json::from_str::<CanonicalValue<Timestamp>>(&json).map(|value| value.0)
+            })
+        };
+        for (json, message) in [
+            (format!("-1{scale}"), "before the allowed window"),
+            (format!("946684799{scale}"), "before the allowed window"),
+            (format!("946684799{scale}.5"), "before the allowed window"),
+            (format!("4102444801{scale}"), "after the allowed window"),
+            (format!("4102444800{scale}.5"), "after the allowed window"),
+        ] {
+            let err = parse_epoch(json.clone()).expect_err(&json);
+            assert!(err.to_string().contains(message), "{json}: {err}");
+        }
+        parse_epoch(format!("946684800{scale}")).expect("window start is inclusive");
+    }
+
+    // The epoch helper goes through the same window.
+    let err = with_options(options, || {
+        serde_json::from_str::<EpochTimestamp>("946684799.5")
+    })
+    .expect_err("epoch helper before window");
+    assert!(
+        err.to_string().contains("before the allowed window"),
+        "{err}"
+    );
+
+    let parse_duration = |json: &str| {
+        with_options(options, || {
+            serde_json::from_str::<CanonicalValue<Duration>>(json).map(|value| value.0)
+        })
+    };
+    parse_duration(r#""-86400s""#).expect("magnitude at the limit");
+    let err = parse_duration(r#""-86400.5s""#).expect_err("too long");
+    assert!(err.to_string().contains("allowed magnitude"), "{err}");
+}
+
+#[test]
+fn enum_as_quoted_number_roundtrip() {
+    use prost_canonical_serde_example::Status;
+
+    let message = KitchenSink {
+        status: Status::Active as i32,
+        ..KitchenSink::default()
+    };
+    let options = CanonicalOptions::new().enum_as_quoted_number(true);
+    let json = to_json_with(options, &message);
+    assert_eq!(json, format!(r#"{{"status":"{}"}}"#, Status::Active as i32));
+    let decoded: KitchenSink =
+        with_options(options, || serde_json::from_str(&json)).expect("deserialize");
+    assert_eq!(decoded, message);
+
+    // Canonical input is still accepted, and the default output is unchanged.
+    let decoded: KitchenSink = with_options(options, || {
+        serde_json::from_str(r#"{"status":"STATUS_ACTIVE"}"#)
+    })
+    .expect("deserialize name");
+    assert_eq!(decoded, message);
+    assert_eq!(
+        serde_json::to_string(&message).expect("serialize"),
+        r#"{"status":"STATUS_ACTIVE"}"#
+    );
+    assert!(serde_json::from_str::<KitchenSink>(&json).is_err());
+}
+
+#[test]
+fn prefer_string_numbers_keeps_value_numbers_as_text() {
+    use prost_types::value::Kind;
+
+    let options = CanonicalOptions::new().prefer_string_numbers(true);
+    let parse = |json: &str| {
+        with_options(options, || {
+            serde_json::from_str::<CanonicalValue<prost_types::Value>>(json).map(|value| value.0)
+        })
+        .expect(json)
+        .kind
+    };
+    for (json, expected) in [
+        ("12345678901234567890", "12345678901234567890"),
+        ("-9007199254740993", "-9007199254740993"),
+        ("0.1", "0.1"),
+        // Other numbers are written like a `double` field.
+        ("100.0", "100"),
+        ("1e300", "1e+300"),
+        ("1e-7", "1e-7"),
+        // Beyond `u64` an integer is read as an `f64` like any other number.
+        ("123456789012345678901234", "1.2345678901234567e+23"),
+    ] {
+        assert_eq!(parse(json), Some(Kind::StringValue(expected.to_string())));
+    }
+    assert_eq!(parse("true"), Some(Kind::BoolValue(true)));
+
+    // By default numbers are `number_value`s and inexact integers fail.
+    let value: CanonicalValue<prost_types::Value> =
+        serde_json::from_str("0.1").expect("deserialize");
+    assert_eq!(value.0.kind, Some(Kind::NumberValue(0.1)));
+    assert!(
+        serde_json::from_str::<CanonicalValue<prost_types::Value>>("-9007199254740993").is_err()
+    );
+}
+
+#[test]
+fn empty_repeated_as_null_emits_present_keys() {
+    let options = CanonicalOptions::new().empty_repeated_as_null(true);
+    let message = KitchenSink {
+        int32_field: 1,
+        ..KitchenSink::default()
+    };
+    let json = to_json_with(options, &message);
+    assert_eq!(
+        json,
+        r#"{"int32Field":1,"repeatedNested":null,"stringToInt":null,"intToString":null,"statuses":null}"#
+    );
+    let decoded: KitchenSink = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(decoded, message);
+
+    // Non-empty fields are unaffected, and the default still omits them.
+    let message = KitchenSink {
+        statuses: vec![1],
+        ..KitchenSink::default()
+    };
+    assert!(to_json_with(options, &message).contains(r#""statuses":["STATUS_ACTIVE"]"#));
+    assert_eq!(
+        serde_json::to_string(&KitchenSink::default()).expect("serialize"),
+        "{}"
+    );
+}
+
+#[test]
+fn lenient_float_tokens_are_opt_in() {
+    let parse = |json: &str| serde_json::from_str::<KitchenSink>(json).map(|m| m.double_field);
+    let lenient = CanonicalOptions::new().lenient_float_tokens(true);
+    for token in ["nan", "NAN", "inf", "+Infinity", "-INF", "infinity", "+inf"] {
+        let json = format!(r#"{{"doubleField":"{token}"}}"#);
+        assert!(parse(&json).is_err(), "{token}");
+        let value = with_options(lenient, || parse(&json)).expect(token);
+        assert!(!value.is_finite(), "{token}");
+        if token.starts_with('-') {
+            assert!(value.is_infinite() && value.is_sign_negative(), "{token}");
+        }
+    }
+    for token in ["+nan", "infin", "+", ""] {
+        let json = format!(r#"{{"doubleField":"{token}"}}"#);
+        assert!(with_options(lenient, || parse(&json)).is_err(), "{token}");
+    }
+
+    // Output stays canonical.
+    let message = KitchenSink {
+        double_field: f64::INFINITY,
+        float_field: f32::NAN,
+        ..KitchenSink::default()
+    };
+    assert_eq!(
+        to_json_with(lenient, &message),
+        r#"{"floatField":"NaN","doubleField":"Infinity"}"#
+    );
+}
+
+#[test]
+fn timestamp_as_epoch_units_roundtrip() {
+    let timestamp = Timestamp {
+        seconds: 1,
+        nanos: 500_000_000,
+    };
+    for (unit, expected) in [
+        (TimestampUnit::EpochSeconds, "1.5"),
+        (TimestampUnit::EpochMilliseconds, "1500"),
+    ] {
+        let options = CanonicalOptions::new().timestamp_as(unit);
+        assert_eq!(to_json_with(options, &timestamp), expected);
+        for input in [expected, r#""1970-01-01T00:00:01.500Z""#] {
+            let decoded = with_options(options, || {
+                serde_json::from_str::<CanonicalValue<Timestamp>>(input)
+            })
+            .expect(input)
+            .0;
+            assert_eq!(decoded, timestamp);
+        }
+    }
+
+    let options = CanonicalOptions::new().timestamp_as(TimestampUnit::EpochMilliseconds);
+    let before_epoch = Timestamp {
+        seconds: -2,
+        nanos: 999_000_000,
+    };
+    assert_eq!(to_json_with(options, &before_epoch), "-1001");
+    let decoded = with_options(options, || {
+        serde_json::from_str::<CanonicalValue<Timestamp>>("-1001")
+    })
+    .expect("deserialize")
+    .0;
+    assert_eq!(decoded, before_epoch);
+    let sub_milli = Timestamp {
+        seconds: 0,
+        nanos: 1_500_000,
+    };
+    assert_eq!(to_json_with(options, &sub_milli), "1.5");
+
+    // Messages pick the option up for every timestamp field.
+    let message = KitchenSink {
+        timestamp: Some(timestamp),
+        ..KitchenSink::default()
+    };
+    let json = to_json_with(options, &message);
+    assert_eq!(json, r#"{"timestamp":1500}"#);
+    let decoded: KitchenSink =
+        with_options(options, || serde_json::from_str(&json)).expect("deserialize");
+    assert_eq!(decoded, message);
+    assert!(serde_json::from_str::<KitchenSink>(&json).is_err());
+}
+
+#[test]
+fn value_non_finite_fallbacks() {
+    use prost_types::value::Kind;
+
+    let number = |value: f64| prost_types::Value {
+        kind: Some(Kind::NumberValue(value)),
+    };
+    let list = prost_types::ListValue {
+        values: vec![number(f64::NAN), number(f64::NEG_INFINITY), number(1.5)],
+    };
+    let err = serde_json::to_string(&Canonical::new(&list)).expect_err("canonical");
+    assert_eq!(err.to_string(), "Value.number_value must be finite");
+
+    let null = CanonicalOptions::new().value_non_finite_as(NonFiniteValue::Null);
+    assert_eq!(to_json_with(null, &list), "[null,null,1.5]");
+
+    let string = CanonicalOptions::new().value_non_finite_as(NonFiniteValue::String);
+    assert_eq!(to_json_with(string, &list), r#"["NaN","-Infinity",1.5]"#);
+    assert_eq!(
+        to_json_with(string, &number(f64::INFINITY)),
+        r#""Infinity""#
+    );
+}
+
+#[test]
+fn null_oneof_members_are_rejected_only_when_opted_in() {
+    // By default a `null` member reads like an absent key.
+    let message: KitchenSink =
+        serde_json::from_str(r#"{"name":null,"int32Field":1}"#).expect("deserialize");
+    assert_eq!(message.choice, None);
+    assert_eq!(message.int32_field, 1);
+
+    let strict = CanonicalOptions::new().reject_null_oneof_members(true);
+    for (json, field) in [
+        (r#"{"name":null}"#, "name"),
+        (r#"{"nested_choice":null}"#, "nestedChoice"),
+    ] {
+        let err =
+            with_options(strict, || serde_json::from_str::<KitchenSink>(json)).expect_err(json);
+        assert!(
+            err.to_string().starts_with(&format!(
+                r#"error in field "{field}": oneof member must not be null"#
+            )),
+            "{err}"
+        );
+        assert!(with_options(strict, || serde_json::from_str::<Choice>(json)).is_err());
+    }
+
+    // A oneof field left out entirely, or set to `null` as a whole, is fine.
+    let message: KitchenSink = with_options(strict, || {
+        serde_json::from_str(r#"{"choice":null,"int32Field":1}"#)
+    })
+    .expect("deserialize");
+    assert_eq!(message.choice, None);
+    let choice: Choice =
+        with_options(strict, || serde_json::from_str(r#"{"name":"n"}"#)).expect("deserialize");
+    assert_eq!(choice, Choice::Name("n".to_string()));
+}
+
+#[test]
+fn enum_as_number_covers_singular_repeated_and_map_values() {
+    use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+    use prost_canonical_serde_example::Status;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+    struct StatusShapes {
+        #[prost(enumeration = "Status")]
+        #[prost_canonical_serde(proto_name = "status", json_name = "status")]
+        status: i32,
+        #[prost(enumeration = "Status", repeated)]
+        #[prost_canonical_serde(proto_name = "history", json_name = "history")]
+        history: Vec<i32>,
+        #[prost(map = "string, enumeration(Status)")]
+        #[prost_canonical_serde(proto_name = "by_name", json_name = "byName")]
+        by_name: BTreeMap<String, i32>,
+    }
+
+    let message = StatusShapes {
+        status: Status::Active as i32,
+        history: vec![Status::Unspecified as i32, Status::Suspended as i32],
+        by_name: BTreeMap::from([("a".to_string(), Status::Suspended as i32)]),
+    };
+    let options = CanonicalOptions::new().enum_as_number(true);
+    let json = to_json_with(options, &message);
+    assert_eq!(json, r#"{"status":1,"history":[0,2],"byName":{"a":2}}"#);
+    let decoded: StatusShapes = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(decoded, message);
+
+    assert_eq!(
+        serde_json::to_string(&message).expect("serialize"),
+        r#"{"status":"STATUS_ACTIVE","history":["STATUS_UNSPECIFIED","STATUS_SUSPENDED"],"byName":{"a":"STATUS_SUSPENDED"}}"#
+    );
+}
+
+#[test]
+fn relaxed_timestamp_bounds_round_trip_sentinels() {
+    let relaxed = CanonicalOptions::new().relaxed_timestamp_bounds(true);
+    let parse = |json: &str| {
+        with_options(relaxed, || {
+            serde_json::from_str::<CanonicalValue<Timestamp>>(json).map(|value| value.0)
+        })
+    };
+
+    for (sentinel, expected) in [
+        (
+            Timestamp {
+                seconds: 0,
+                nanos: 2_000_000_000,
+            },
+            r#"{"seconds":"0","nanos":2000000000}"#,
+        ),
+        (
+            Timestamp {
+                seconds: i64::MAX,
+                nanos: 0,
+            },
+            r#"{"seconds":"9223372036854775807","nanos":0}"#,
+        ),
+        (
+            Timestamp {
+                seconds: -62_135_596_801,
+                nanos: -1,
+            },
+            r#"{"seconds":"-62135596801","nanos":-1}"#,
+        ),
+    ] {
+        let json = to_json_with(relaxed, &sentinel);
+        assert_eq!(json, expected);
+        assert_eq!(parse(&json).expect("deserialize"), sentinel);
+        // The default stays strict in both directions.
+        assert!(serde_json::to_string(&Canonical::new(&sentinel)).is_err());
+        assert!(serde_json::from_str::<CanonicalValue<Timestamp>>(&json).is_err());
+    }
+
+    // In-range values keep their canonical string.
+    let canonical = Timestamp {
+        seconds: 1,
+        nanos: 500_000_000,
+    };
+    let json = to_json_with(relaxed, &canonical);
+    assert_eq!(json, r#""1970-01-01T00:00:01.500Z""#);
+    assert_eq!(parse(&json).expect("deserialize"), canonical);
+
+    let err = parse(r#"{"seconds":"1","seconds":"2"}"#).expect_err("duplicate key");
+    assert!(
+        err.to_string().contains("duplicate timestamp field"),
+        "{err}"
+    );
+    let err = parse(r#"{"secs":1}"#).expect_err("unknown key");
+    assert!(err.to_string().contains("unknown field `secs`"), "{err}");
+}
+
+#[test]
+fn null_message_as_default_reads_null_as_an_empty_message() {
+    use prost_canonical_serde::from_canonical_str;
+    use prost_canonical_serde_example::Nested;
+
+    // Conformance requires rejecting a top-level `null`.
+    assert!(from_canonical_str::<KitchenSink>("null").is_err());
+
+    let options = CanonicalOptions::new().null_message_as_default(true);
+    let message =
+        with_options(options, || from_canonical_str::<KitchenSink>("null")).expect("null message");
+    assert_eq!(message, KitchenSink::default());
+
+    let message: KitchenSink = with_options(options, || {
+        serde_json::from_str(r#"{"repeatedNested":[null,{"id":1}],"nested":null}"#)
+    })
+    .expect("null elements");
+    assert_eq!(
+        message.repeated_nested,
+        [
+            Nested::default(),
+            Nested {
+                id: 1,
+                note: String::new(),
+            },
+        ]
+    );
+    assert_eq!(message.nested, None);
+
+    let err = with_options(options, || from_canonical_str::<KitchenSink>("[]"))
+        .expect_err("not a message");
+    assert!(err.to_string().contains("expected map"), "{err}");
+}
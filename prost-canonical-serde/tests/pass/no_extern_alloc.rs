@@ -0,0 +1,23 @@
+// A plain `std` crate deriving on every field shape that allocates, with no
+// `extern crate alloc` in scope.
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+struct Owned {
+    #[prost_canonical_serde(proto_name = "name", json_name = "name")]
+    name: String,
+    #[prost_canonical_serde(proto_name = "data", json_name = "data")]
+    data: Vec<u8>,
+    #[prost_canonical_serde(proto_name = "ids", json_name = "ids")]
+    ids: Vec<i32>,
+    #[prost_canonical_serde(proto_name = "labels", json_name = "labels")]
+    labels: BTreeMap<String, String>,
+    #[prost_canonical_serde(proto_name = "next", json_name = "next")]
+    next: Option<Box<Owned>>,
+}
+
+fn main() {
+    let decoded: Owned = serde_json::from_str(r#"{"name":"n","ids":[1]}"#).unwrap();
+    assert_eq!(decoded.ids, [1]);
+}
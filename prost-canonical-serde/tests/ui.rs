@@ -0,0 +1,6 @@
+#[test]
+fn ui() {
+    let tests = trybuild::TestCases::new();
+    tests.compile_fail("tests/ui/*.rs");
+    tests.pass("tests/pass/*.rs");
+}
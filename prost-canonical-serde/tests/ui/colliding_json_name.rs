@@ -0,0 +1,13 @@
+extern crate alloc;
+
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+struct Colliding {
+    #[prost_canonical_serde(proto_name = "a", json_name = "b")]
+    a: i32,
+    #[prost_canonical_serde(proto_name = "b", json_name = "b")]
+    b: i32,
+}
+
+fn main() {}
@@ -0,0 +1,32 @@
+extern crate alloc;
+
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+struct Colliding {
+    #[prost_canonical_serde(proto_name = "name", json_name = "name")]
+    name: String,
+    #[prost(oneof = "Choice", tags = "2, 3")]
+    choice: Option<Choice>,
+    #[prost(oneof = "Other", tags = "4")]
+    other: Option<Other>,
+}
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+enum Choice {
+    #[prost(string, tag = "2")]
+    #[prost_canonical_serde(proto_name = "name", json_name = "name")]
+    Name(String),
+    #[prost(int32, tag = "3")]
+    #[prost_canonical_serde(proto_name = "id", json_name = "id")]
+    Id(i32),
+}
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+enum Other {
+    #[prost(int32, tag = "4")]
+    #[prost_canonical_serde(proto_name = "id", json_name = "id")]
+    Id(i32),
+}
+
+fn main() {}
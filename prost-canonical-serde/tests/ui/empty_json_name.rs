@@ -0,0 +1,11 @@
+extern crate alloc;
+
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+struct EmptyName {
+    #[prost_canonical_serde(json_name = "")]
+    value: i32,
+}
+
+fn main() {}
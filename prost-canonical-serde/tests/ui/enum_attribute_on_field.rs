@@ -0,0 +1,18 @@
+extern crate alloc;
+
+use prost_canonical_serde::CanonicalSerialize;
+
+#[derive(CanonicalSerialize)]
+struct Numbered {
+    #[prost(enumeration = "Color", tag = "1")]
+    #[prost_canonical_serde(enum_numbers)]
+    color: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+enum Color {
+    Red = 0,
+}
+
+fn main() {}
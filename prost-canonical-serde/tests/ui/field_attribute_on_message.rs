@@ -0,0 +1,11 @@
+extern crate alloc;
+
+use prost_canonical_serde::CanonicalSerialize;
+
+#[derive(CanonicalSerialize)]
+#[prost_canonical_serde(skip)]
+struct Skipped {
+    value: i32,
+}
+
+fn main() {}
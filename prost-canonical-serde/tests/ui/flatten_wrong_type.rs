@@ -0,0 +1,11 @@
+extern crate alloc;
+
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+struct FlattenScalar {
+    #[prost_canonical_serde(json_name = "count", flatten)]
+    count: Vec<i32>,
+}
+
+fn main() {}
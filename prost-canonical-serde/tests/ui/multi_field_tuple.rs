@@ -0,0 +1,8 @@
+extern crate alloc;
+
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+struct Pair(i32, i32);
+
+fn main() {}
@@ -0,0 +1,9 @@
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+struct NotAMessage {
+    #[prost_canonical_serde(json_name = "names", omit_empty_message)]
+    names: Vec<String>,
+}
+
+fn main() {}
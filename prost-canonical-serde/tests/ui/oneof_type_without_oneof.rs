@@ -0,0 +1,11 @@
+extern crate alloc;
+
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+struct NotAOneof {
+    #[prost_canonical_serde(json_name = "value", oneof_type = "Other")]
+    value: Option<i32>,
+}
+
+fn main() {}
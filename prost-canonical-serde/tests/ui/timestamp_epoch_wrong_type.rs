@@ -0,0 +1,11 @@
+extern crate alloc;
+
+use prost_canonical_serde::{CanonicalDeserialize, CanonicalSerialize};
+
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+struct NotATimestamp {
+    #[prost_canonical_serde(json_name = "seconds", timestamp_epoch)]
+    seconds: i64,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+extern crate alloc;
+
+use prost_canonical_serde::CanonicalSerialize;
+
+#[derive(CanonicalSerialize)]
+struct Misspelled {
+    #[prost_canonical_serde(skp)]
+    cache: Vec<u8>,
+}
+
+fn main() {}